@@ -0,0 +1,269 @@
+//! `batch`: thumbnails and proof bundles for every image under a directory.
+//!
+//! The circuit shape is fixed once per process, so images are grouped by
+//! size and every group is proved by a child process (`batch-shape`) that
+//! generates the proving key once and reuses it for the whole group.
+
+use core::{
+    backend::Backend, bundle::ProofBundle, downsample::Rounding, img_proving_key, rounding,
+    ImgProvingKey,
+};
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs,
+    io::{BufRead, BufReader},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::open_rgba;
+
+const EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "webp", "bmp", "tiff"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageReport {
+    // relative to the input directory
+    pub path: PathBuf,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub bundle: Option<PathBuf>,
+    pub thumbnail: Option<PathBuf>,
+    pub seconds: f64,
+    pub error: Option<String>,
+}
+
+impl ImageReport {
+    fn failed(path: PathBuf, error: String) -> Self {
+        ImageReport {
+            path,
+            width: None,
+            height: None,
+            bundle: None,
+            thumbnail: None,
+            seconds: 0.0,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub backend: Backend,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub images: Vec<ImageReport>,
+}
+
+// images below `dir`, relative to `root`, in a stable order
+fn walk(root: &Path, dir: &Path, images: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk(root, &path, images)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| {
+                EXTENSIONS.contains(&ext.to_lowercase().as_str())
+            })
+        {
+            images.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+// out/<relative path>.bundle.json and out/<relative path>.thumb.png
+fn outputs(output: &Path, path: &Path) -> (PathBuf, PathBuf) {
+    let base = output.join(path);
+    let mut bundle = base.clone().into_os_string();
+    bundle.push(".bundle.json");
+    let mut thumbnail = base.into_os_string();
+    thumbnail.push(".thumb.png");
+    (bundle.into(), thumbnail.into())
+}
+
+/// Proves every image under `input`, writes the outputs and `report`; Ok(false) if any image failed.
+pub fn run_batch(
+    input: &Path,
+    output: &Path,
+    report: &Path,
+    backend: Backend,
+) -> Result<bool, Box<dyn Error>> {
+    let mut images = Vec::new();
+    walk(input, input, &mut images).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let mut reports = Vec::new();
+    let mut shapes: BTreeMap<(u32, u32), Vec<PathBuf>> = BTreeMap::new();
+    for path in images {
+        match image::image_dimensions(input.join(&path)) {
+            Ok(shape) => shapes.entry(shape).or_default().push(path),
+            Err(e) => reports.push(ImageReport::failed(path, e.to_string())),
+        }
+    }
+
+    for ((width, height), paths) in shapes {
+        eprintln!("{} X {}: {} images", width, height, paths.len());
+        reports.extend(prove_shape_group(
+            input, output, backend, width, height, paths,
+        )?);
+    }
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let failed = reports.iter().filter(|r| r.error.is_some()).count();
+    let batch = BatchReport {
+        input: input.to_path_buf(),
+        output: output.to_path_buf(),
+        backend,
+        succeeded: reports.len() - failed,
+        failed,
+        images: reports,
+    };
+    if let Some(parent) = report.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(report, serde_json::to_string_pretty(&batch)?)?;
+    println!(
+        "{} succeeded, {} failed, report: {}",
+        batch.succeeded,
+        batch.failed,
+        report.display()
+    );
+    Ok(failed == 0)
+}
+
+// runs `batch-shape` for one group, images the child did not report (e.g. it crashed) count as failed
+fn prove_shape_group(
+    input: &Path,
+    output: &Path,
+    backend: Backend,
+    width: u32,
+    height: u32,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<ImageReport>, Box<dyn Error>> {
+    let mut child = Command::new(env::current_exe()?)
+        .arg("batch-shape")
+        .arg("--input")
+        .arg(input)
+        .arg("--output")
+        .arg(output)
+        .arg("--backend")
+        .arg(match backend {
+            Backend::Ipa => "ipa",
+            Backend::Kzg => "kzg",
+        })
+        .arg("--width")
+        .arg(width.to_string())
+        .arg("--height")
+        .arg(height.to_string())
+        .arg("--rounding")
+        .arg(match rounding() {
+            Rounding::Floor => "floor",
+            Rounding::HalfUp => "half-up",
+            Rounding::HalfEven => "half-even",
+        })
+        .args(&paths)
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut reports = Vec::new();
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        if let Ok(report) = serde_json::from_str::<ImageReport>(&line?) {
+            reports.push(report);
+        }
+    }
+    let status = child.wait()?;
+
+    for path in paths {
+        if !reports.iter().any(|r| r.path == path) {
+            let error = format!("prover exited with {} before this image", status);
+            reports.push(ImageReport::failed(path, error));
+        }
+    }
+    Ok(reports)
+}
+
+/// `batch-shape`: proves images of one shape with one proving key, one JSON report per line on stdout.
+pub fn run_shape_group(
+    input: &Path,
+    output: &Path,
+    backend: Backend,
+    width: u32,
+    height: u32,
+    paths: &[PathBuf],
+) -> Result<bool, Box<dyn Error>> {
+    let pk = img_proving_key(backend, width, height);
+
+    for path in paths {
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            prove_one(input, output, &pk, width, height, path)
+        }))
+        .unwrap_or_else(|_| Err("prover panicked".into()));
+
+        let report = match result {
+            Ok((bundle, thumbnail)) => ImageReport {
+                path: path.clone(),
+                width: Some(width),
+                height: Some(height),
+                bundle: Some(bundle),
+                thumbnail: Some(thumbnail),
+                seconds: start.elapsed().as_secs_f64(),
+                error: None,
+            },
+            Err(e) => ImageReport {
+                seconds: start.elapsed().as_secs_f64(),
+                ..ImageReport::failed(path.clone(), e.to_string())
+            },
+        };
+        println!("{}", serde_json::to_string(&report)?);
+    }
+    Ok(true)
+}
+
+fn prove_one(
+    input: &Path,
+    output: &Path,
+    pk: &ImgProvingKey,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    let img = open_rgba(&input.join(path))?;
+    if img.dimensions() != (width, height) {
+        return Err(format!(
+            "expected {} X {}, got {:?}",
+            width,
+            height,
+            img.dimensions()
+        )
+        .into());
+    }
+
+    let bundle = ProofBundle::create_with_pk(pk, img.into_raw(), width, height);
+    let (bundle_path, thumbnail_path) = outputs(output, path);
+    if let Some(parent) = bundle_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&bundle_path, bundle.to_json())?;
+    RgbaImage::from_raw(
+        bundle.thumbnail.width,
+        bundle.thumbnail.height,
+        bundle.thumbnail.pixels,
+    )
+    .ok_or("thumbnail has an unexpected size")?
+    .save(&thumbnail_path)?;
+    Ok((bundle_path, thumbnail_path))
+}
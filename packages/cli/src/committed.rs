@@ -0,0 +1,522 @@
+//! Statements about a committed original: `prove-approx`, `prove-similarity`,
+//! `watermark`, `ycbcr`, `statistics`, `phash` and their `verify-*`.
+//!
+//! Every proof binds the RGBA original with a digest, the prover writes it to
+//! `--digest` and the verifier reads it from there.
+
+use core::{
+    approx::{create_approx_proof, verify_approx},
+    phash::{create_hash_proof, verify_hash, PerceptualHash},
+    similarity::{create_similarity_proof, max_mse_for_psnr, verify_similarity, Transform},
+    statistics::{create_statistics_proof, verify_statistics, PublicStatistics, Statistic},
+    watermark::{create_watermark_proof, verify_watermark, Watermark},
+    ycbcr::{create_ycbcr_proof, verify_ycbcr, Subsampling, YCbCrImage},
+    SCALE_FACTOR,
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, ValueEnum};
+use image::RgbaImage;
+
+use crate::{open_rgba, read_digest, TransformArg};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SubsamplingArg {
+    #[value(name = "444")]
+    S444,
+    #[value(name = "420")]
+    S420,
+}
+
+impl From<SubsamplingArg> for Subsampling {
+    fn from(arg: SubsamplingArg) -> Self {
+        match arg {
+            SubsamplingArg::S444 => Subsampling::Chroma444,
+            SubsamplingArg::S420 => Subsampling::Chroma420,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HashArg {
+    Average,
+    Difference,
+}
+
+impl From<HashArg> for PerceptualHash {
+    fn from(arg: HashArg) -> Self {
+        match arg {
+            HashArg::Average => PerceptualHash::Average,
+            HashArg::Difference => PerceptualHash::Difference,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ProveApproxArgs {
+    input: PathBuf,
+    published: PathBuf,
+    #[arg(long)]
+    tolerance: u8,
+    #[arg(long, default_value = "approx.bin")]
+    out: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyApproxArgs {
+    proof: PathBuf,
+    published: PathBuf,
+    #[arg(long)]
+    tolerance: u8,
+    #[arg(long)]
+    digest: PathBuf,
+    /// Width of the original, defaults to twice the thumbnail's
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of the original, defaults to twice the thumbnail's
+    #[arg(long)]
+    height: Option<u32>,
+}
+
+#[derive(Args)]
+pub struct ProveSimilarityArgs {
+    input: PathBuf,
+    published: PathBuf,
+    #[arg(long, value_enum, default_value_t = TransformArg::Downsample)]
+    transform: TransformArg,
+    /// Largest mean squared error per RGBA sample
+    #[arg(long, conflicts_with = "psnr", required_unless_present = "psnr")]
+    max_mse: Option<u32>,
+    /// Smallest PSNR in dB, converted to --max-mse
+    #[arg(long)]
+    psnr: Option<f64>,
+    #[arg(long, default_value = "similarity.bin")]
+    out: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifySimilarityArgs {
+    proof: PathBuf,
+    published: PathBuf,
+    #[arg(long)]
+    digest: PathBuf,
+    #[arg(long, value_enum, default_value_t = TransformArg::Downsample)]
+    transform: TransformArg,
+    #[arg(long, conflicts_with = "psnr", required_unless_present = "psnr")]
+    max_mse: Option<u32>,
+    #[arg(long)]
+    psnr: Option<f64>,
+    /// Width of the original, defaults to the one the transform implies
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of the original, defaults to the one the transform implies
+    #[arg(long)]
+    height: Option<u32>,
+}
+
+#[derive(Args)]
+pub struct WatermarkArgs {
+    input: PathBuf,
+    watermark: PathBuf,
+    #[arg(long, default_value_t = 0)]
+    x: u32,
+    #[arg(long, default_value_t = 0)]
+    y: u32,
+    #[arg(long, value_enum, default_value_t = TransformArg::Downsample)]
+    transform: TransformArg,
+    #[arg(long, default_value = "watermarked.png")]
+    out: PathBuf,
+    #[arg(long, default_value = "watermark.bin")]
+    proof: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyWatermarkArgs {
+    proof: PathBuf,
+    published: PathBuf,
+    watermark: PathBuf,
+    #[arg(long, default_value_t = 0)]
+    x: u32,
+    #[arg(long, default_value_t = 0)]
+    y: u32,
+    #[arg(long)]
+    digest: PathBuf,
+    #[arg(long, value_enum, default_value_t = TransformArg::Downsample)]
+    transform: TransformArg,
+    /// Width of the original, defaults to the one the transform implies
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of the original, defaults to the one the transform implies
+    #[arg(long)]
+    height: Option<u32>,
+}
+
+#[derive(Args)]
+pub struct YcbcrArgs {
+    input: PathBuf,
+    #[arg(long, value_enum, default_value_t = SubsamplingArg::S444)]
+    subsampling: SubsamplingArg,
+    #[arg(long, value_enum, default_value_t = TransformArg::Identity)]
+    transform: TransformArg,
+    /// Y, Cb and Cr planes one after another
+    #[arg(long, default_value = "image.yuv")]
+    out: PathBuf,
+    #[arg(long, default_value = "ycbcr.bin")]
+    proof: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyYcbcrArgs {
+    proof: PathBuf,
+    planes: PathBuf,
+    /// Width of the original
+    #[arg(long)]
+    width: u32,
+    /// Height of the original
+    #[arg(long)]
+    height: u32,
+    #[arg(long)]
+    digest: PathBuf,
+    #[arg(long, value_enum, default_value_t = SubsamplingArg::S444)]
+    subsampling: SubsamplingArg,
+    #[arg(long, value_enum, default_value_t = TransformArg::Identity)]
+    transform: TransformArg,
+}
+
+#[derive(Args)]
+pub struct StatisticsArgs {
+    input: PathBuf,
+    /// Bins per channel (a power of two up to 32), publishes the means if omitted
+    #[arg(long)]
+    histogram: Option<u32>,
+    #[arg(long, value_enum, default_value_t = TransformArg::Identity)]
+    transform: TransformArg,
+    #[arg(long, default_value = "statistics.json")]
+    out: PathBuf,
+    #[arg(long, default_value = "statistics.bin")]
+    proof: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyStatisticsArgs {
+    proof: PathBuf,
+    statistics: PathBuf,
+    #[arg(long)]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct PhashArgs {
+    input: PathBuf,
+    #[arg(long, value_enum, default_value_t = HashArg::Average)]
+    kind: HashArg,
+    #[arg(long, default_value = "phash.bin")]
+    proof: PathBuf,
+    /// Digest of the original (32 bytes), the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyPhashArgs {
+    proof: PathBuf,
+    /// 16 hex digits
+    #[arg(long)]
+    hash: String,
+    /// Width of the original
+    #[arg(long)]
+    width: u32,
+    /// Height of the original
+    #[arg(long)]
+    height: u32,
+    #[arg(long)]
+    digest: PathBuf,
+    #[arg(long, value_enum, default_value_t = HashArg::Average)]
+    kind: HashArg,
+}
+
+fn read_watermark(path: &Path, x: u32, y: u32) -> Result<Watermark, Box<dyn Error>> {
+    let mark = open_rgba(path)?;
+    let (width, height) = mark.dimensions();
+    Ok(Watermark::new(mark.into_raw(), width, height, x, y))
+}
+
+pub fn run_prove_approx(args: ProveApproxArgs) -> Result<bool, Box<dyn Error>> {
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let thumbnail = open_rgba(&args.published)?;
+    let (proof, root) = create_approx_proof(
+        original.into_raw(),
+        width,
+        height,
+        thumbnail.as_raw(),
+        args.tolerance,
+    )
+    .ok_or_else(|| {
+        format!(
+            "{} is not within {} of the reduction of {}",
+            args.published.display(),
+            args.tolerance,
+            args.input.display()
+        )
+    })?;
+    fs::write(&args.out, proof)?;
+    fs::write(&args.digest, root)?;
+    println!("proof: {}", args.out.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_approx(args: VerifyApproxArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let thumbnail = open_rgba(&args.published)?;
+    let valid = verify_approx(
+        &proof,
+        args.width.unwrap_or(thumbnail.width() * SCALE_FACTOR),
+        args.height.unwrap_or(thumbnail.height() * SCALE_FACTOR),
+        thumbnail.as_raw(),
+        args.tolerance,
+        root,
+    );
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_prove_similarity(args: ProveSimilarityArgs) -> Result<bool, Box<dyn Error>> {
+    let max_mse = args
+        .max_mse
+        .unwrap_or_else(|| max_mse_for_psnr(args.psnr.unwrap()));
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let public = open_rgba(&args.published)?;
+    let (proof, root) = create_similarity_proof(
+        args.transform.into(),
+        original.into_raw(),
+        width,
+        height,
+        public.as_raw(),
+        max_mse,
+    )
+    .ok_or_else(|| {
+        format!(
+            "{} is not within MSE {} of {}",
+            args.published.display(),
+            max_mse,
+            args.input.display()
+        )
+    })?;
+    fs::write(&args.out, proof)?;
+    fs::write(&args.digest, root)?;
+    println!("proof: {}", args.out.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_similarity(args: VerifySimilarityArgs) -> Result<bool, Box<dyn Error>> {
+    let max_mse = args
+        .max_mse
+        .unwrap_or_else(|| max_mse_for_psnr(args.psnr.unwrap()));
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let public = open_rgba(&args.published)?;
+    let scale = args.transform.scale();
+    let valid = verify_similarity(
+        args.transform.into(),
+        &proof,
+        args.width.unwrap_or(public.width() * scale),
+        args.height.unwrap_or(public.height() * scale),
+        public.as_raw(),
+        max_mse,
+        root,
+    );
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_watermark(args: WatermarkArgs) -> Result<bool, Box<dyn Error>> {
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let mark = read_watermark(&args.watermark, args.x, args.y)?;
+    let (published, transcript, root) = create_watermark_proof(
+        args.transform.into(),
+        original.into_raw(),
+        width,
+        height,
+        &mark,
+    )
+    .ok_or_else(|| {
+        format!(
+            "{} does not fit at ({}, {})",
+            args.watermark.display(),
+            args.x,
+            args.y
+        )
+    })?;
+    let (s_width, s_height) = Transform::from(args.transform).shape(width, height);
+    RgbaImage::from_raw(s_width, s_height, published)
+        .expect("the composited image has the transformed shape")
+        .save(&args.out)?;
+    fs::write(&args.proof, transcript)?;
+    fs::write(&args.digest, root)?;
+    println!("watermarked: {}", args.out.display());
+    println!("proof: {}", args.proof.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_watermark(args: VerifyWatermarkArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let public = open_rgba(&args.published)?;
+    let mark = read_watermark(&args.watermark, args.x, args.y)?;
+    let scale = args.transform.scale();
+    let valid = verify_watermark(
+        args.transform.into(),
+        &proof,
+        args.width.unwrap_or(public.width() * scale),
+        args.height.unwrap_or(public.height() * scale),
+        public.as_raw(),
+        &mark,
+        root,
+    );
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_ycbcr(args: YcbcrArgs) -> Result<bool, Box<dyn Error>> {
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let (image, transcript, root) = create_ycbcr_proof(
+        args.transform.into(),
+        args.subsampling.into(),
+        original.into_raw(),
+        width,
+        height,
+    );
+    fs::write(&args.out, image.to_planar())?;
+    fs::write(&args.proof, transcript)?;
+    fs::write(&args.digest, root)?;
+    println!(
+        "planes: {} ({} X {})",
+        args.out.display(),
+        image.width,
+        image.height
+    );
+    println!("proof: {}", args.proof.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_ycbcr(args: VerifyYcbcrArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let buf = fs::read(&args.planes).map_err(|e| format!("{}: {}", args.planes.display(), e))?;
+    let (s_width, s_height) = Transform::from(args.transform).shape(args.width, args.height);
+    let image = YCbCrImage::from_planar(&buf, s_width, s_height, args.subsampling.into())
+        .ok_or_else(|| {
+            format!(
+                "{}: not {} X {} planes",
+                args.planes.display(),
+                s_width,
+                s_height
+            )
+        })?;
+    let valid = verify_ycbcr(
+        args.transform.into(),
+        &proof,
+        args.width,
+        args.height,
+        &image,
+        root,
+    );
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_statistics(args: StatisticsArgs) -> Result<bool, Box<dyn Error>> {
+    let statistic = match args.histogram {
+        Some(bins) => Statistic::Histogram { bins },
+        None => Statistic::Means,
+    };
+    if !statistic.is_valid() {
+        return Err("--histogram takes a power of two up to 32".into());
+    }
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let (statistics, transcript, root) = create_statistics_proof(
+        args.transform.into(),
+        statistic,
+        original.into_raw(),
+        width,
+        height,
+    );
+    fs::write(&args.out, serde_json::to_string_pretty(&statistics)?)?;
+    fs::write(&args.proof, transcript)?;
+    fs::write(&args.digest, root)?;
+    println!("statistics: {}", args.out.display());
+    println!("proof: {}", args.proof.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_statistics(args: VerifyStatisticsArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let json = fs::read_to_string(&args.statistics)
+        .map_err(|e| format!("{}: {}", args.statistics.display(), e))?;
+    let statistics: PublicStatistics =
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", args.statistics.display(), e))?;
+    let valid = verify_statistics(&proof, &statistics, root);
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_phash(args: PhashArgs) -> Result<bool, Box<dyn Error>> {
+    let original = open_rgba(&args.input)?;
+    let (width, height) = original.dimensions();
+    let (hash, transcript, root) =
+        create_hash_proof(args.kind.into(), original.into_raw(), width, height)
+            .ok_or_else(|| format!("{}: too small for the hash grid", args.input.display()))?;
+    fs::write(&args.proof, transcript)?;
+    fs::write(&args.digest, root)?;
+    println!("hash: {:016x}", hash);
+    println!("proof: {}", args.proof.display());
+    println!("digest: {}", args.digest.display());
+    Ok(true)
+}
+
+pub fn run_verify_phash(args: VerifyPhashArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let hash = u64::from_str_radix(&args.hash, 16).map_err(|e| format!("--hash: {}", e))?;
+    let valid = verify_hash(
+        args.kind.into(),
+        &proof,
+        args.width,
+        args.height,
+        hash,
+        root,
+    );
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
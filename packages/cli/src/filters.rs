@@ -0,0 +1,189 @@
+//! `mosaic`, `blur` and `quantize`: filters that keep the size of the image.
+//!
+//! The blur and the quantization prove RGBA originals only and bind them
+//! with a digest, the verifier reads it from `--digest`.
+
+use core::{
+    blur::{self, create_blur_proof, verify_blur, Kernel},
+    mosaic::{self, create_mosaic_proof, verify_mosaic},
+    quantize::{self, create_quantize_proof, verify_quantize},
+};
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::{Args, ValueEnum};
+
+use crate::{open_rgba, read_digest, read_image, rgba_image, BackendArg};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum KernelArg {
+    Box3,
+    Box5,
+    Gaussian3,
+    Gaussian5,
+}
+
+impl From<KernelArg> for Kernel {
+    fn from(arg: KernelArg) -> Self {
+        match arg {
+            KernelArg::Box3 => Kernel::box_blur(3),
+            KernelArg::Box5 => Kernel::box_blur(5),
+            KernelArg::Gaussian3 => Kernel::gaussian3(),
+            KernelArg::Gaussian5 => Kernel::gaussian5(),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct MosaicArgs {
+    input: PathBuf,
+    output: PathBuf,
+    #[arg(long)]
+    grain: u32,
+    /// Also proves the pixelation and writes the proof here
+    #[arg(long)]
+    proof: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+    backend: BackendArg,
+}
+
+#[derive(Args)]
+pub struct VerifyMosaicArgs {
+    proof: PathBuf,
+    mosaic: PathBuf,
+    #[arg(long)]
+    grain: u32,
+    #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+    backend: BackendArg,
+}
+
+#[derive(Args)]
+pub struct BlurArgs {
+    input: PathBuf,
+    output: PathBuf,
+    #[arg(long, value_enum, default_value_t = KernelArg::Gaussian3)]
+    kernel: KernelArg,
+    /// Also proves the blur of the RGBA original and writes the proof here
+    #[arg(long)]
+    proof: Option<PathBuf>,
+    /// Digest of the original (32 bytes) written with --proof, the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyBlurArgs {
+    proof: PathBuf,
+    blurred: PathBuf,
+    #[arg(long, value_enum, default_value_t = KernelArg::Gaussian3)]
+    kernel: KernelArg,
+    #[arg(long)]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct QuantizeArgs {
+    input: PathBuf,
+    output: PathBuf,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=8))]
+    bits: u32,
+    /// Also proves the quantization of the RGBA original and writes the proof here
+    #[arg(long)]
+    proof: Option<PathBuf>,
+    /// Digest of the original (32 bytes) written with --proof, the verifier needs it
+    #[arg(long, default_value = "digest.bin")]
+    digest: PathBuf,
+}
+
+#[derive(Args)]
+pub struct VerifyQuantizeArgs {
+    proof: PathBuf,
+    quantized: PathBuf,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=8))]
+    bits: u32,
+    #[arg(long)]
+    digest: PathBuf,
+}
+
+pub fn run_mosaic(args: MosaicArgs) -> Result<bool, Box<dyn Error>> {
+    let img = read_image(&args.input, true)?;
+    if args.grain == 0 || args.grain > img.width.min(img.height) {
+        return Err("--grain must be between 1 and the image size".into());
+    }
+    let mosaic = match args.proof {
+        Some(proof) => {
+            let (mosaic, bytes) = create_mosaic_proof(args.backend.into(), &img, args.grain);
+            fs::write(&proof, bytes)?;
+            println!("proof: {}", proof.display());
+            mosaic
+        }
+        None => mosaic::mosaic(&img, args.grain),
+    };
+    mosaic.to_dynamic().save(&args.output)?;
+    Ok(true)
+}
+
+pub fn run_verify_mosaic(args: VerifyMosaicArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let mosaic = read_image(&args.mosaic, false)?;
+    let valid = verify_mosaic(args.backend.into(), &proof, &mosaic, args.grain);
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_blur(args: BlurArgs) -> Result<bool, Box<dyn Error>> {
+    let img = read_image(&args.input, true)?;
+    if img.width == 0 || img.height == 0 {
+        return Err(format!("{}: empty image", args.input.display()).into());
+    }
+    let kernel = args.kernel.into();
+    let blurred = match args.proof {
+        Some(proof) => {
+            let (blurred, bytes, root) =
+                create_blur_proof(&rgba_image(img.to_dynamic().to_rgba8()), &kernel);
+            fs::write(&proof, bytes)?;
+            fs::write(&args.digest, root)?;
+            println!("proof: {}", proof.display());
+            println!("digest: {}", args.digest.display());
+            blurred
+        }
+        None => blur::blur(&img, &kernel),
+    };
+    blurred.to_dynamic().save(&args.output)?;
+    Ok(true)
+}
+
+pub fn run_verify_blur(args: VerifyBlurArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let blurred = rgba_image(open_rgba(&args.blurred)?);
+    let valid = verify_blur(&proof, &blurred, &args.kernel.into(), root);
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_quantize(args: QuantizeArgs) -> Result<bool, Box<dyn Error>> {
+    let img = read_image(&args.input, true)?;
+    let quantized = match args.proof {
+        Some(proof) => {
+            let (quantized, bytes, root) =
+                create_quantize_proof(&rgba_image(img.to_dynamic().to_rgba8()), args.bits);
+            fs::write(&proof, bytes)?;
+            fs::write(&args.digest, root)?;
+            println!("proof: {}", proof.display());
+            println!("digest: {}", args.digest.display());
+            quantized
+        }
+        None => quantize::quantize(&img, args.bits),
+    };
+    quantized.to_dynamic().save(&args.output)?;
+    Ok(true)
+}
+
+pub fn run_verify_quantize(args: VerifyQuantizeArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let root = read_digest(&args.digest)?;
+    let quantized = rgba_image(open_rgba(&args.quantized)?);
+    let valid = verify_quantize(&proof, &quantized, args.bits, root);
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
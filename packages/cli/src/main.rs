@@ -1,26 +1,262 @@
-use core::*;
-use image::{io::Reader as ImageReader, EncodableLayout, RgbaImage};
-use std::path::Path;
-use std::{thread, time};
-
-fn main() {
-    let now = time::Instant::now();
-    let path = Path::new("packages/cli/src/test100X61.png");
-    let img = ImageReader::open(path).unwrap().decode().unwrap();
-    let width = img.width();
-    let height = img.height();
-    let vec = img.as_bytes().to_vec();
-    let (converted, _, _, _, _) = core::exec(vec.clone(), 2, width, height);
-    let s_width = converted.width();
-    let s_height = converted.height();
-    let s_img = RgbaImage::from_vec(s_width, s_height, converted.as_bytes().to_vec()).unwrap();
-    let s_vec = s_img.as_bytes().to_vec();
-
-    let proof = core::create_img_proof(vec, width, height);
-    // 最初の時刻からの経過時間を表示
-    println!("time: {:?}", now.elapsed());
-    let result = core::verify_img(proof, s_vec, s_width, s_height);
-
-    println!("result: {}", result);
-    assert_eq!(result, true);
+use core::{
+    backend::Backend,
+    channels::{ChannelLayout, NormalizedImage},
+    decode::{decode, decode_original},
+    downsample::Rounding,
+    similarity::Transform,
+    SCALE_FACTOR,
+};
+use image::RgbaImage;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+mod batch;
+mod committed;
+mod filters;
+mod reduce;
+
+// 終了コード: 0 成功 / 検証成功、1 検証失敗、2 引数やファイルのエラー
+const EXIT_INVALID: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+#[derive(Parser)]
+#[command(about = "Prove and verify reductions of photos")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// How block averages are rounded, provers and verifiers must agree
+    #[arg(long, global = true, value_enum, default_value_t = RoundingArg::Floor)]
+    rounding: RoundingArg,
+    /// KZG SRS of a trusted setup ceremony, defaults to $KZG_SRS_PATH
+    #[arg(long, global = true)]
+    srs: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Pixelates <INPUT> into <OUTPUT>, keeping its size
+    Mosaic(filters::MosaicArgs),
+    /// Checks that <PROOF> proves <MOSAIC> is a pixelation of some original
+    VerifyMosaic(filters::VerifyMosaicArgs),
+    /// Blurs <INPUT> into <OUTPUT> with a public kernel, keeping its size
+    Blur(filters::BlurArgs),
+    /// Checks that <PROOF> proves <BLURRED> is a blur of the committed original
+    VerifyBlur(filters::VerifyBlurArgs),
+    /// Reduces every sample of <INPUT> to --bits bits into <OUTPUT>
+    Quantize(filters::QuantizeArgs),
+    /// Checks that <PROOF> proves <QUANTIZED> is a quantization of the committed original
+    VerifyQuantize(filters::VerifyQuantizeArgs),
+    /// Reduces <INPUT> and proves the reduction
+    Prove(reduce::ProveArgs),
+    /// Checks that <PROOF> proves <THUMB> is a reduction of some original
+    Verify(reduce::VerifyArgs),
+    /// Proves that <PUBLISHED> is within --tolerance of the reduction of <INPUT>
+    ProveApprox(committed::ProveApproxArgs),
+    /// Checks that <PROOF> proves <PUBLISHED> is within --tolerance of the reduction of the committed original
+    VerifyApprox(committed::VerifyApproxArgs),
+    /// Proves that <PUBLISHED> is within an MSE (or PSNR) threshold of a transform of <INPUT>
+    ProveSimilarity(committed::ProveSimilarityArgs),
+    /// Checks that <PROOF> proves <PUBLISHED> is within the threshold of the committed original
+    VerifySimilarity(committed::VerifySimilarityArgs),
+    /// Blends <WATERMARK> over a transform of <INPUT> at (--x, --y) and proves it
+    Watermark(committed::WatermarkArgs),
+    /// Checks that <PROOF> proves <PUBLISHED> is <WATERMARK> over the committed original
+    VerifyWatermark(committed::VerifyWatermarkArgs),
+    /// Converts a transform of <INPUT> to planar YCbCr and proves the conversion
+    Ycbcr(committed::YcbcrArgs),
+    /// Checks that <PROOF> proves <PLANES> is the YCbCr conversion of the committed original
+    VerifyYcbcr(committed::VerifyYcbcrArgs),
+    /// Publishes channel means or histograms of a transform of <INPUT> and proves them
+    Statistics(committed::StatisticsArgs),
+    /// Checks that <PROOF> proves <STATISTICS> are those of the committed original
+    VerifyStatistics(committed::VerifyStatisticsArgs),
+    /// Computes the perceptual hash of <INPUT> and proves it
+    Phash(committed::PhashArgs),
+    /// Checks that <PROOF> proves --hash is the perceptual hash of the committed original
+    VerifyPhash(committed::VerifyPhashArgs),
+    /// Writes the parameters and keys for originals of one shape
+    Keygen(reduce::KeygenArgs),
+    /// Writes a thumbnail and a proof bundle for every image under <INPUT>
+    Batch {
+        input: PathBuf,
+        #[arg(long, default_value = "out")]
+        out: PathBuf,
+        /// JSON summary, defaults to <OUT>/report.json
+        #[arg(long)]
+        report: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+        backend: BackendArg,
+    },
+    // images of one shape, run by `batch` in a child process
+    #[command(hide = true)]
+    BatchShape {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        #[arg(long, value_enum)]
+        backend: BackendArg,
+        #[arg(long)]
+        width: u32,
+        #[arg(long)]
+        height: u32,
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendArg {
+    Ipa,
+    Kzg,
+}
+
+impl From<BackendArg> for Backend {
+    fn from(arg: BackendArg) -> Self {
+        match arg {
+            BackendArg::Ipa => Backend::Ipa,
+            BackendArg::Kzg => Backend::Kzg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TransformArg {
+    Identity,
+    Downsample,
+}
+
+impl TransformArg {
+    // original size / transformed size
+    fn scale(self) -> u32 {
+        match self {
+            TransformArg::Identity => 1,
+            TransformArg::Downsample => SCALE_FACTOR,
+        }
+    }
+}
+
+impl From<TransformArg> for Transform {
+    fn from(arg: TransformArg) -> Self {
+        match arg {
+            TransformArg::Identity => Transform::Identity,
+            TransformArg::Downsample => Transform::Downsample,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RoundingArg {
+    Floor,
+    HalfUp,
+    HalfEven,
+}
+
+impl From<RoundingArg> for Rounding {
+    fn from(arg: RoundingArg) -> Self {
+        match arg {
+            RoundingArg::Floor => Rounding::Floor,
+            RoundingArg::HalfUp => Rounding::HalfUp,
+            RoundingArg::HalfEven => Rounding::HalfEven,
+        }
+    }
+}
+
+fn read_image(path: &Path, original: bool) -> Result<NormalizedImage, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let decoded = if original {
+        decode_original(&bytes)
+    } else {
+        decode(&bytes)
+    };
+    decoded.map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+// 元の画像を RGBA8 で読み込む (batch は RGBA だけを扱う)
+fn open_rgba(path: &Path) -> Result<RgbaImage, Box<dyn Error>> {
+    Ok(read_image(path, true)?.to_dynamic().to_rgba8())
+}
+
+// 証明は RGBA の画像だけを扱う (blur, quantize)
+fn rgba_image(img: RgbaImage) -> NormalizedImage {
+    NormalizedImage {
+        layout: ChannelLayout::Rgba,
+        width: img.width(),
+        height: img.height(),
+        pixels: img.into_raw(),
+    }
+}
+
+fn read_digest(path: &Path) -> Result<[u8; 32], Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    <[u8; 32]>::try_from(bytes)
+        .map_err(|_| format!("{}: not a 32 byte digest", path.display()).into())
+}
+
+// Ok(false) は検証失敗 (batch では失敗した画像がある)
+fn run(command: Command) -> Result<bool, Box<dyn Error>> {
+    match command {
+        Command::Mosaic(args) => filters::run_mosaic(args),
+        Command::VerifyMosaic(args) => filters::run_verify_mosaic(args),
+        Command::Blur(args) => filters::run_blur(args),
+        Command::VerifyBlur(args) => filters::run_verify_blur(args),
+        Command::Quantize(args) => filters::run_quantize(args),
+        Command::VerifyQuantize(args) => filters::run_verify_quantize(args),
+        Command::Prove(args) => reduce::run_prove(args),
+        Command::Verify(args) => reduce::run_verify(args),
+        Command::ProveApprox(args) => committed::run_prove_approx(args),
+        Command::VerifyApprox(args) => committed::run_verify_approx(args),
+        Command::ProveSimilarity(args) => committed::run_prove_similarity(args),
+        Command::VerifySimilarity(args) => committed::run_verify_similarity(args),
+        Command::Watermark(args) => committed::run_watermark(args),
+        Command::VerifyWatermark(args) => committed::run_verify_watermark(args),
+        Command::Ycbcr(args) => committed::run_ycbcr(args),
+        Command::VerifyYcbcr(args) => committed::run_verify_ycbcr(args),
+        Command::Statistics(args) => committed::run_statistics(args),
+        Command::VerifyStatistics(args) => committed::run_verify_statistics(args),
+        Command::Phash(args) => committed::run_phash(args),
+        Command::VerifyPhash(args) => committed::run_verify_phash(args),
+        Command::Keygen(args) => reduce::run_keygen(args),
+        Command::Batch {
+            input,
+            out,
+            report,
+            backend,
+        } => {
+            let report = report.unwrap_or_else(|| out.join("report.json"));
+            batch::run_batch(&input, &out, &report, backend.into())
+        }
+        Command::BatchShape {
+            input,
+            output,
+            backend,
+            width,
+            height,
+            paths,
+        } => batch::run_shape_group(&input, &output, backend.into(), width, height, &paths),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    core::set_rounding(cli.rounding.into());
+    if let Some(path) = &cli.srs {
+        if let Err(e) = core::load_kzg_srs_file(path) {
+            eprintln!("error: {}: {}", path.display(), e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+    match run(cli.command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_INVALID),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
 }
@@ -0,0 +1,130 @@
+//! `prove`, `verify` and `keygen`: the reduction of an original to its
+//! thumbnail.
+//!
+//! The circuit shape is set once per process from the size of the original,
+//! `verify` fails instead of checking the proof against another shape.
+
+use core::{
+    channels::{create_img_proof_normalized, verify_img_dynamic, ChannelLayout},
+    create_img_proof_with_pk,
+    keys::{read_img_proving_key, read_img_verifying_key, write_img_keys},
+    verify_img_with_vk, SCALE_FACTOR,
+};
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::Args;
+
+use crate::{read_image, BackendArg};
+
+#[derive(Args)]
+pub struct ProveArgs {
+    input: PathBuf,
+    #[arg(long, default_value = "proof.bin")]
+    out: PathBuf,
+    #[arg(long, default_value = "thumb.png")]
+    thumb: PathBuf,
+    #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+    backend: BackendArg,
+    /// Directory written by `keygen` for this shape, RGBA originals only
+    #[arg(long)]
+    keys: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    proof: PathBuf,
+    thumb: PathBuf,
+    #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+    backend: BackendArg,
+    /// Width of the original, defaults to twice the thumbnail's
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of the original, defaults to twice the thumbnail's
+    #[arg(long)]
+    height: Option<u32>,
+    /// Directory written by `keygen` for this shape, RGBA thumbnails only
+    #[arg(long)]
+    keys: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct KeygenArgs {
+    #[arg(long)]
+    width: u32,
+    #[arg(long)]
+    height: u32,
+    #[arg(long, default_value = "keys")]
+    out: PathBuf,
+    #[arg(long, value_enum, default_value_t = BackendArg::Ipa)]
+    backend: BackendArg,
+}
+
+pub fn run_prove(args: ProveArgs) -> Result<bool, Box<dyn Error>> {
+    let img = read_image(&args.input, true)?;
+    let (thumbnail, proof) = match args.keys {
+        Some(keys) => {
+            if img.layout != ChannelLayout::Rgba {
+                return Err("--keys only supports RGBA originals".into());
+            }
+            let pk = read_img_proving_key(args.backend.into(), img.width, img.height, &keys)
+                .map_err(|e| format!("{}: {}", keys.display(), e))?;
+            let proof = create_img_proof_with_pk(&pk, img.pixels.clone(), img.width, img.height);
+            (img.reduce(), proof)
+        }
+        None => create_img_proof_normalized(args.backend.into(), &img),
+    };
+    fs::write(&args.out, proof)?;
+    thumbnail.to_dynamic().save(&args.thumb)?;
+    println!(
+        "proof: {}, thumbnail: {} ({} X {}, {:?})",
+        args.out.display(),
+        args.thumb.display(),
+        thumbnail.width,
+        thumbnail.height,
+        thumbnail.layout
+    );
+    Ok(true)
+}
+
+pub fn run_verify(args: VerifyArgs) -> Result<bool, Box<dyn Error>> {
+    let proof = fs::read(&args.proof).map_err(|e| format!("{}: {}", args.proof.display(), e))?;
+    let thumbnail = read_image(&args.thumb, false)?;
+    // 回路の形は元の画像のサイズで決まる、奇数のサイズは縮小画像からは分からないので指定する
+    let width = args.width.unwrap_or(thumbnail.width * SCALE_FACTOR);
+    let height = args.height.unwrap_or(thumbnail.height * SCALE_FACTOR);
+
+    let valid = match args.keys {
+        Some(keys) => {
+            if thumbnail.layout != ChannelLayout::Rgba {
+                return Err("--keys only supports RGBA thumbnails".into());
+            }
+            let vk = read_img_verifying_key(args.backend.into(), width, height, &keys)
+                .map_err(|e| format!("{}: {}", keys.display(), e))?;
+            verify_img_with_vk(
+                &vk,
+                proof,
+                thumbnail.pixels,
+                thumbnail.width,
+                thumbnail.height,
+            )
+        }
+        None => {
+            if !core::set_shape(width, height) {
+                return Err(format!(
+                    "the circuit shape is already set, not for {} X {}",
+                    width, height
+                )
+                .into());
+            }
+            verify_img_dynamic(args.backend.into(), proof, &thumbnail)
+        }
+    };
+    println!("{}", if valid { "valid" } else { "invalid" });
+    Ok(valid)
+}
+
+pub fn run_keygen(args: KeygenArgs) -> Result<bool, Box<dyn Error>> {
+    write_img_keys(args.backend.into(), args.width, args.height, &args.out)?;
+    println!("keys: {}", args.out.display());
+    Ok(true)
+}
@@ -0,0 +1,72 @@
+use core::{
+    backend::Backend, create_img_proof_with_threads, tile::create_tiled_proof_with_threads,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+const TILE_SIZE: u32 = 32;
+
+fn gradient() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| {
+            let (x, y) = (i % WIDTH, i / WIDTH);
+            [(x * 2) as u8, (y * 2) as u8, ((x + y) % 256) as u8, 255]
+        })
+        .collect()
+}
+
+fn tiled_proof(c: &mut Criterion) {
+    let origin_buf = gradient();
+    let mut group = c.benchmark_group("tiled_proof_128x128");
+    group.sample_size(10);
+    for threads in [1, 2, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    create_tiled_proof_with_threads(
+                        origin_buf.clone(),
+                        WIDTH,
+                        HEIGHT,
+                        TILE_SIZE,
+                        TILE_SIZE,
+                        threads,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// baseline: one tile proved as a whole image (the shape is global, so the tile shape),
+// only halo2's FFTs and MSMs run in parallel
+fn img_proof(c: &mut Criterion) {
+    let (width, height) = (TILE_SIZE, TILE_SIZE);
+    let origin_buf = gradient()[..(width * height * 4) as usize].to_vec();
+    let mut group = c.benchmark_group("img_proof_32x32");
+    group.sample_size(10);
+    for threads in [1, 2, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    create_img_proof_with_threads(
+                        Backend::Ipa,
+                        origin_buf.clone(),
+                        width,
+                        height,
+                        threads,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tiled_proof, img_proof);
+criterion_main!(benches);
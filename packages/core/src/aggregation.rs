@@ -0,0 +1,171 @@
+//! Recursive aggregation of many KZG image proofs into a single proof.
+//!
+//! Thumbnail proofs meant for aggregation use SHPLONK with a Poseidon transcript,
+//! so the aggregation circuit can verify them in circuit. The aggregated proof
+//! re-exposes every thumbnail's pixels after the KZG accumulator and is checked
+//! by an EVM verifier contract (see `evm`).
+
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{keygen_pk, keygen_vk, ProvingKey, VerifyingKey},
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use image::{ImageBuffer, Rgba};
+use once_cell::sync::Lazy;
+use snark_verifier::system::halo2::{compile, Config};
+use snark_verifier_sdk::{
+    evm::{gen_evm_proof_shplonk, gen_evm_verifier_shplonk},
+    gen_pk,
+    halo2::{aggregation::AggregationCircuit, gen_proof_shplonk},
+    CircuitExt, Snark, SHPLONK,
+};
+
+use crate::{
+    evm::{encode_calldata, evm_verify},
+    img_circuit, img_public_input, kzg_srs, MyCircuit, Parameter, K,
+};
+
+// the aggregation circuit verifies pairings of BN254 points in circuit and needs 2^21 rows
+pub const AGG_K: u32 = 21;
+// limbs of the KZG accumulator exposed before the thumbnails
+const ACCUMULATOR_LIMBS: usize = 4 * 3;
+
+// prefix of the loaded ceremony SRS, like every other KZG circuit
+pub static AGG_PARAMETER: Lazy<Parameter<ParamsKZG<Bn256>>> =
+    Lazy::new(|| Parameter::set_parameter(kzg_srs(AGG_K)));
+
+// thumbnail proofs must use a prefix of the aggregation SRS, not the standalone `KZG_PARAMETER`
+fn inner_parameter() -> ParamsKZG<Bn256> {
+    let mut params = AGG_PARAMETER.get_parameter();
+    params.downsize(*K.get_or_init(|| 10));
+    params
+}
+
+fn inner_vk(params: &ParamsKZG<Bn256>) -> VerifyingKey<G1Affine> {
+    keygen_vk(params, &MyCircuit::<Fr>::default()).expect("keygen_vk should not fail")
+}
+
+pub struct AggregatedProof {
+    pub proof: Vec<u8>,
+    // accumulator limbs, then the pixels of every thumbnail in input order
+    pub instances: Vec<Vec<Fr>>,
+}
+
+/// Proof of one reduction that `aggregate_img_proofs` can fold.
+pub fn create_img_proof_aggregatable(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+    let (circuit, result) = img_circuit::<Fr>(origin_buf, width, height);
+    let instances = img_public_input::<Fr>(&result);
+
+    let params = inner_parameter();
+    let empty_circuit = MyCircuit::<Fr>::default();
+    let pk =
+        keygen_pk(&params, inner_vk(&params), &empty_circuit).expect("keygen_pk should not fail");
+    gen_proof_shplonk(&params, &pk, circuit, instances, None)
+}
+
+fn img_snark(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> Snark {
+    let instances = img_public_input::<Fr>(img);
+    let protocol = compile(
+        params,
+        vk,
+        Config::kzg().with_num_instance(instances.iter().map(Vec::len).collect()),
+    );
+    Snark::new(protocol, instances, proof.to_vec())
+}
+
+fn img_snarks(proofs: &[(Vec<u8>, Vec<u8>)], s_width: u32, s_height: u32) -> Option<Vec<Snark>> {
+    let inner_params = inner_parameter();
+    let vk = inner_vk(&inner_params);
+    proofs
+        .iter()
+        .map(|(proof, small_buf)| {
+            let img = ImageBuffer::from_raw(s_width, s_height, small_buf.clone())?;
+            Some(img_snark(&inner_params, &vk, proof, &img))
+        })
+        .collect()
+}
+
+// the key and the keygen circuit, which carries the break points for the prover;
+// `AggregationCircuit::keygen` picks the lookup bits (AGG_K - 1) itself
+fn aggregation_pk(
+    params: &ParamsKZG<Bn256>,
+    snarks: Vec<Snark>,
+) -> (ProvingKey<G1Affine>, AggregationCircuit) {
+    let mut agg_circuit = AggregationCircuit::keygen::<SHPLONK>(params, snarks);
+    agg_circuit.expose_previous_instances(false);
+    let pk = gen_pk(params, &agg_circuit, None);
+    (pk, agg_circuit)
+}
+
+/// Folds (proof, thumbnail) pairs into one proof, all thumbnails are `s_width` X `s_height`.
+pub fn aggregate_img_proofs(
+    proofs: &[(Vec<u8>, Vec<u8>)],
+    s_width: u32,
+    s_height: u32,
+) -> AggregatedProof {
+    let snarks = img_snarks(proofs, s_width, s_height).expect("thumbnail size mismatch");
+    let params = AGG_PARAMETER.get_parameter();
+    let (pk, keygen_circuit) = aggregation_pk(&params, snarks.clone());
+
+    let mut agg_circuit =
+        AggregationCircuit::prover::<SHPLONK>(&params, snarks, keygen_circuit.break_points());
+    agg_circuit.expose_previous_instances(false);
+    let instances = agg_circuit.instances();
+    let proof = gen_evm_proof_shplonk(&params, &pk, agg_circuit, instances.clone());
+
+    AggregatedProof { proof, instances }
+}
+
+/// Deployment code of the verifier contract for aggregations of `proofs.len()` thumbnails
+/// of `s_width` X `s_height`. Its key only depends on the number and the shape of the
+/// thumbnails, so the verifier derives it once from proofs of its own (or any valid ones)
+/// and keeps it; the code must never be taken from the prover.
+pub fn aggregation_verifier(
+    proofs: &[(Vec<u8>, Vec<u8>)],
+    s_width: u32,
+    s_height: u32,
+) -> Option<Vec<u8>> {
+    let snarks = img_snarks(proofs, s_width, s_height)?;
+    let params = AGG_PARAMETER.get_parameter();
+    let (pk, keygen_circuit) = aggregation_pk(&params, snarks);
+    Some(gen_evm_verifier_shplonk::<AggregationCircuit>(
+        &params,
+        pk.get_vk(),
+        keygen_circuit.num_instance(),
+        None,
+    ))
+}
+
+/// Checks the aggregated proof with the trusted verifier `deployment_code` (see
+/// `aggregation_verifier`) and that it covers exactly the given thumbnails.
+pub fn verify_aggregated(
+    deployment_code: &[u8],
+    aggregated: &AggregatedProof,
+    small_bufs: &[Vec<u8>],
+    s_width: u32,
+    s_height: u32,
+) -> bool {
+    let mut thumbnails = Vec::new();
+    for small_buf in small_bufs {
+        match ImageBuffer::<Rgba<u8>, Vec<_>>::from_raw(s_width, s_height, small_buf.clone()) {
+            Some(img) => thumbnails.extend(img_public_input::<Fr>(&img).into_iter().flatten()),
+            None => return false,
+        }
+    }
+
+    let exposed = match aggregated.instances.first() {
+        Some(exposed) if exposed.len() >= ACCUMULATOR_LIMBS => &exposed[ACCUMULATOR_LIMBS..],
+        _ => return false,
+    };
+    if exposed != &thumbnails[..] {
+        return false;
+    }
+
+    let calldata = encode_calldata(&aggregated.instances, &aggregated.proof);
+    evm_verify(deployment_code.to_vec(), calldata).is_ok()
+}
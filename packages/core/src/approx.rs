@@ -0,0 +1,271 @@
+//! Approximate equality: proofs that survive mild recompression.
+//!
+//! Platforms re-encode published thumbnails, so an exact reduction proof stops
+//! verifying. This circuit proves that every sample of the published thumbnail
+//! is within a public tolerance `t` of the reference reduction
+//! (`Downsampler::default()`) of a committed RGBA8 original. The original is
+//! bound by its digest and reduced by `ReferenceConfig` (`Transform::Downsample`),
+//! which range checks its samples and the block remainders. One thumbnail
+//! sample per row: the reference average copied from the reduction and the
+//! published sample, which is the instance. A lookup checks that
+//! `published - average + t` lies in `0..=2t`.
+//!
+//! The digest is Poseidon over Pasta, so only the IPA backend is supported.
+
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Error, Expression, Instance, Selector,
+        TableColumn,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::pixels_digest,
+    helpers::{ipa_parameter, sample},
+    set_once, set_shape,
+    similarity::{reference_rows, ReferenceConfig, ReferenceWitness, Transform},
+    Parameter, HEIGHT, WIDTH,
+};
+
+// 回路の形: 元の画像のサイズ (WIDTH / HEIGHT) と許容誤差
+pub static TOLERANCE: OnceCell<u8> = OnceCell::new();
+
+// the digest of the original dominates the rows, so k follows the original size
+pub static APPROX_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+// (original pixels, thumbnail samples)
+fn approx_size() -> (usize, usize) {
+    let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+    let (s_width, s_height) = Transform::Downsample.shape(width as u32, height as u32);
+    (width * height, s_width as usize * s_height as usize * 4)
+}
+
+fn approx_parameter() -> &'static Parameter {
+    APPROX_PARAMETER.get_or_init(|| {
+        let (pixels, samples) = approx_size();
+        // the digest, the original, its reduction and the comparison
+        ipa_parameter(reference_rows(pixels, samples) + samples)
+    })
+}
+
+// 元の画像のサイズと許容誤差から回路の形を設定する: 既に別の形なら false
+pub fn set_approx_shape(width: u32, height: u32, tolerance: u8) -> bool {
+    set_shape(width, height) & set_once(&TOLERANCE, tolerance)
+}
+
+/// Whether every sample of `published` is within `tolerance` of `reference` (same length).
+pub fn within_tolerance(reference: &[u8], published: &[u8], tolerance: u8) -> bool {
+    reference.len() == published.len()
+        && reference
+            .iter()
+            .zip(published)
+            .all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+}
+
+#[derive(Clone, Debug)]
+pub struct ApproxConfig {
+    reference: ReferenceConfig,
+    average: Column<Advice>,
+    published: Column<Advice>,
+    q_approx: Selector,
+    // 0..=2t
+    difference_table: TableColumn,
+    instance: Column<Instance>,
+    // row 0: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ApproxCircuit {
+    pub reference: ReferenceWitness,
+    // RGBA8 thumbnail, empty without witnesses
+    pub published: Vec<Value<u8>>,
+}
+
+impl Circuit<Fp> for ApproxCircuit {
+    type Config = ApproxConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let tolerance = *TOLERANCE.get().unwrap() as u64;
+
+        let reference = ReferenceConfig::configure(meta);
+        let average = meta.advice_column();
+        let published = meta.advice_column();
+        let instance = meta.instance_column();
+        let statement = meta.instance_column();
+        [average, published]
+            .iter()
+            .for_each(|&col| meta.enable_equality(col));
+        meta.enable_equality(instance);
+        meta.enable_equality(statement);
+
+        // lookups need the selector inside the expressions
+        let q_approx = meta.complex_selector();
+        let difference_table = meta.lookup_table_column();
+        // 選択されていない行は 0 で、表に含まれる
+        meta.lookup("within tolerance", |virtual_cells| {
+            let q_approx = virtual_cells.query_selector(q_approx);
+            let average = virtual_cells.query_advice(average, Rotation::cur());
+            let published = virtual_cells.query_advice(published, Rotation::cur());
+            let difference = published - average + Expression::Constant(Fp::from(tolerance));
+            vec![(q_approx * difference, difference_table)]
+        });
+
+        ApproxConfig {
+            reference,
+            average,
+            published,
+            q_approx,
+            difference_table,
+            instance,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let tolerance = *TOLERANCE.get().unwrap() as u64;
+
+        let (digest, references) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            Transform::Downsample,
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+
+        layouter.assign_table(
+            || "within tolerance",
+            |mut table| {
+                for v in 0..=2 * tolerance {
+                    table.assign_cell(
+                        || "difference",
+                        config.difference_table,
+                        v as usize,
+                        || Value::known(Fp::from(v)),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        let published = layouter.assign_region(
+            || "approximate reduction",
+            |mut region| {
+                let mut cells = Vec::with_capacity(references.len());
+                for (i, reference) in references.iter().enumerate() {
+                    config.q_approx.enable(&mut region, i)?;
+                    reference.copy_advice(|| "average", &mut region, config.average, i)?;
+                    cells.push(region.assign_advice(
+                        || "published",
+                        config.published,
+                        i,
+                        || sample(&self.published, i),
+                    )?);
+                }
+                Ok(cells)
+            },
+        )?;
+
+        for (i, cell) in published.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.instance, i)?;
+        }
+        Ok(())
+    }
+}
+
+/// The circuit proving `published` is within `tolerance` of the reduction of the
+/// RGBA8 original, also sets the shape globals; None if it is not.
+pub fn approx_circuit(
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+    published: &[u8],
+    tolerance: u8,
+) -> Option<ApproxCircuit> {
+    if origin_buf.len() != (width * height * 4) as usize {
+        return None;
+    }
+    let (reference, _, _) = Transform::Downsample.apply(origin_buf, width, height);
+    if !within_tolerance(&reference, published, tolerance) {
+        return None;
+    }
+    assert!(
+        set_approx_shape(width, height, tolerance),
+        "the circuit shape is already set for another image size or tolerance"
+    );
+    Some(ApproxCircuit {
+        reference: ReferenceWitness::new(Transform::Downsample, origin_buf, width, height),
+        published: published.iter().map(|&v| Value::known(v)).collect(),
+    })
+}
+
+/// [published samples, [digest of the original]]
+pub fn approx_public_input(published: &[u8], digest: Fp) -> Vec<Vec<Fp>> {
+    vec![
+        published.iter().map(|&v| Fp::from(v as u64)).collect(),
+        vec![digest],
+    ]
+}
+
+/// Proves that every sample of the RGBA8 thumbnail `published` (e.g. recompressed) is
+/// within `tolerance` of the reduction of the RGBA8 original; returns the proof and the
+/// digest of the original (`Fp::to_repr`), or None if it is not.
+pub fn create_approx_proof(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    published: &[u8],
+    tolerance: u8,
+) -> Option<(Vec<u8>, [u8; 32])> {
+    let circuit = approx_circuit(&origin_buf, width, height, published, tolerance)?;
+    let digest = pixels_digest(&origin_buf);
+    let public_input = approx_public_input(published, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = approx_parameter().get_parameter();
+    let empty_circuit = ApproxCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    Some((proof, digest.to_repr()))
+}
+
+/// Checks a proof of `create_approx_proof` for an original of `width` X `height`
+/// with digest `digest` against the published thumbnail.
+pub fn verify_approx(
+    proof: &[u8],
+    width: u32,
+    height: u32,
+    published: &[u8],
+    tolerance: u8,
+    digest: [u8; 32],
+) -> bool {
+    let (s_width, s_height) = Transform::Downsample.shape(width, height);
+    if published.len() != (s_width * s_height * 4) as usize {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_approx_shape(width, height, tolerance) {
+        return false;
+    }
+    let public_input = approx_public_input(published, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = approx_parameter().get_parameter();
+    verify_ipa(&params, &ApproxCircuit::default(), proof, &public_input)
+}
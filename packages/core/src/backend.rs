@@ -0,0 +1,341 @@
+//! Commitment schemes the image circuits can be proven with.
+//!
+//! `prove` / `verify` are generic over the halo2 `CommitmentScheme`, the
+//! `*_ipa` and `*_kzg` helpers pin them to IPA over Pasta and KZG over BN254.
+
+use halo2_proofs::{
+    halo2curves::{
+        bn256::{Bn256, Fr, G1Affine},
+        pasta::{EqAffine, Fp},
+    },
+    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ProvingKey, VerifyingKey},
+    poly::{
+        commitment::{CommitmentScheme, Prover, Verifier},
+        ipa::{
+            commitment::{IPACommitmentScheme, ParamsIPA},
+            multiopen::{ProverIPA, VerifierIPA},
+            strategy::{
+                AccumulatorStrategy as IpaAccumulatorStrategy, SingleStrategy as IpaSingleStrategy,
+            },
+        },
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverGWC, VerifierGWC},
+            strategy::{
+                AccumulatorStrategy as KzgAccumulatorStrategy, SingleStrategy as KzgSingleStrategy,
+            },
+        },
+        VerificationStrategy,
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // IPA over Pasta, no trusted setup
+    #[default]
+    Ipa,
+    // KZG over BN254, cheap to verify (on-chain)
+    Kzg,
+}
+
+pub fn prove<'params, Scheme, P, ConcreteCircuit>(
+    params: &'params Scheme::ParamsProver,
+    empty_circuit: &ConcreteCircuit,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Scheme::Scalar]],
+) -> Vec<u8>
+where
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+{
+    let vk = in_span!(
+        "keygen_vk",
+        keygen_vk(params, empty_circuit).expect("keygen_vk should not fail")
+    );
+    let pk = in_span!(
+        "keygen_pk",
+        keygen_pk(params, vk, empty_circuit).expect("keygen_pk should not fail")
+    );
+
+    prove_with_pk::<Scheme, P, _>(params, &pk, circuit, public_input)
+}
+
+/// Same as `prove` with a proving key generated once for many proofs of the same shape.
+pub fn prove_with_pk<'params, Scheme, P, ConcreteCircuit>(
+    params: &'params Scheme::ParamsProver,
+    pk: &ProvingKey<Scheme::Curve>,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Scheme::Scalar]],
+) -> Vec<u8>
+where
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+{
+    enter_span!("create_proof");
+    let mut transcript = Blake2bWrite::<_, Scheme::Curve, Challenge255<_>>::init(vec![]);
+    create_proof::<Scheme, P, _, _, _, _>(
+        params,
+        pk,
+        &[circuit],
+        &[public_input],
+        OsRng,
+        &mut transcript,
+    )
+    .expect("proof generation should not fail");
+    transcript.finalize()
+}
+
+pub fn verify<'params, Scheme, V, Strategy, ConcreteCircuit>(
+    params: &'params Scheme::ParamsVerifier,
+    empty_circuit: &ConcreteCircuit,
+    proof: &[u8],
+    public_input: &[&[Scheme::Scalar]],
+) -> bool
+where
+    Scheme: CommitmentScheme,
+    V: Verifier<'params, Scheme>,
+    Strategy: VerificationStrategy<'params, Scheme, V>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+{
+    let vk = in_span!(
+        "keygen_vk",
+        keygen_vk(params, empty_circuit).expect("keygen_vk should not fail")
+    );
+
+    verify_with_vk::<Scheme, V, Strategy>(params, &vk, proof, public_input)
+}
+
+/// Same as `verify` with a verifying key generated once or read from a file.
+pub fn verify_with_vk<'params, Scheme, V, Strategy>(
+    params: &'params Scheme::ParamsVerifier,
+    vk: &VerifyingKey<Scheme::Curve>,
+    proof: &[u8],
+    public_input: &[&[Scheme::Scalar]],
+) -> bool
+where
+    Scheme: CommitmentScheme,
+    V: Verifier<'params, Scheme>,
+    Strategy: VerificationStrategy<'params, Scheme, V>,
+{
+    enter_span!("verify");
+    let strategy = Strategy::new(params);
+    let mut transcript = Blake2bRead::<_, Scheme::Curve, Challenge255<_>>::init(proof);
+
+    verify_proof::<Scheme, V, _, _, Strategy>(
+        params,
+        vk,
+        strategy,
+        &[public_input],
+        &mut transcript,
+    )
+    .is_ok()
+}
+
+/// Checks many proofs of the same circuit with one keygen, folding all openings into a
+/// single accumulator so the expensive final check (MSM / pairing) runs once.
+pub fn verify_batch<'params, Scheme, V, Strategy, ConcreteCircuit>(
+    params: &'params Scheme::ParamsVerifier,
+    empty_circuit: &ConcreteCircuit,
+    proofs: &[(&[u8], Vec<&[Scheme::Scalar]>)],
+) -> bool
+where
+    Scheme: CommitmentScheme,
+    V: Verifier<'params, Scheme>,
+    Strategy: VerificationStrategy<'params, Scheme, V, Output = Strategy>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+{
+    let vk = in_span!(
+        "keygen_vk",
+        keygen_vk(params, empty_circuit).expect("keygen_vk should not fail")
+    );
+
+    enter_span!("verify");
+    let mut strategy = Strategy::new(params);
+    for (proof, public_input) in proofs {
+        let mut transcript = Blake2bRead::<_, Scheme::Curve, Challenge255<_>>::init(*proof);
+        strategy = match verify_proof::<Scheme, V, _, _, Strategy>(
+            params,
+            &vk,
+            strategy,
+            &[&public_input[..]],
+            &mut transcript,
+        ) {
+            Ok(strategy) => strategy,
+            Err(_) => return false,
+        };
+    }
+    strategy.finalize()
+}
+
+pub fn prove_ipa<ConcreteCircuit: Circuit<Fp>>(
+    params: &ParamsIPA<EqAffine>,
+    empty_circuit: &ConcreteCircuit,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Fp]],
+) -> Vec<u8> {
+    prove::<IPACommitmentScheme<EqAffine>, ProverIPA<'_, EqAffine>, _>(
+        params,
+        empty_circuit,
+        circuit,
+        public_input,
+    )
+}
+
+pub fn keygen_ipa<ConcreteCircuit: Circuit<Fp>>(
+    params: &ParamsIPA<EqAffine>,
+    empty_circuit: &ConcreteCircuit,
+) -> ProvingKey<EqAffine> {
+    let vk = in_span!(
+        "keygen_vk",
+        keygen_vk(params, empty_circuit).expect("keygen_vk should not fail")
+    );
+    in_span!(
+        "keygen_pk",
+        keygen_pk(params, vk, empty_circuit).expect("keygen_pk should not fail")
+    )
+}
+
+pub fn prove_ipa_with_pk<ConcreteCircuit: Circuit<Fp>>(
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Fp]],
+) -> Vec<u8> {
+    prove_with_pk::<IPACommitmentScheme<EqAffine>, ProverIPA<'_, EqAffine>, _>(
+        params,
+        pk,
+        circuit,
+        public_input,
+    )
+}
+
+pub fn verify_ipa<ConcreteCircuit: Circuit<Fp>>(
+    params: &ParamsIPA<EqAffine>,
+    empty_circuit: &ConcreteCircuit,
+    proof: &[u8],
+    public_input: &[&[Fp]],
+) -> bool {
+    verify::<
+        IPACommitmentScheme<EqAffine>,
+        VerifierIPA<'_, EqAffine>,
+        IpaSingleStrategy<'_, EqAffine>,
+        _,
+    >(params, empty_circuit, proof, public_input)
+}
+
+pub fn verify_ipa_with_vk(
+    params: &ParamsIPA<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_input: &[&[Fp]],
+) -> bool {
+    verify_with_vk::<
+        IPACommitmentScheme<EqAffine>,
+        VerifierIPA<'_, EqAffine>,
+        IpaSingleStrategy<'_, EqAffine>,
+    >(params, vk, proof, public_input)
+}
+
+pub fn verify_ipa_batch<ConcreteCircuit: Circuit<Fp>>(
+    params: &ParamsIPA<EqAffine>,
+    empty_circuit: &ConcreteCircuit,
+    proofs: &[(&[u8], Vec<&[Fp]>)],
+) -> bool {
+    verify_batch::<
+        IPACommitmentScheme<EqAffine>,
+        VerifierIPA<'_, EqAffine>,
+        IpaAccumulatorStrategy<'_, EqAffine>,
+        _,
+    >(params, empty_circuit, proofs)
+}
+
+pub fn prove_kzg<ConcreteCircuit: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    empty_circuit: &ConcreteCircuit,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Fr]],
+) -> Vec<u8> {
+    prove::<KZGCommitmentScheme<Bn256>, ProverGWC<'_, Bn256>, _>(
+        params,
+        empty_circuit,
+        circuit,
+        public_input,
+    )
+}
+
+pub fn keygen_kzg<ConcreteCircuit: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    empty_circuit: &ConcreteCircuit,
+) -> ProvingKey<G1Affine> {
+    let vk = in_span!(
+        "keygen_vk",
+        keygen_vk(params, empty_circuit).expect("keygen_vk should not fail")
+    );
+    in_span!(
+        "keygen_pk",
+        keygen_pk(params, vk, empty_circuit).expect("keygen_pk should not fail")
+    )
+}
+
+pub fn prove_kzg_with_pk<ConcreteCircuit: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: ConcreteCircuit,
+    public_input: &[&[Fr]],
+) -> Vec<u8> {
+    prove_with_pk::<KZGCommitmentScheme<Bn256>, ProverGWC<'_, Bn256>, _>(
+        params,
+        pk,
+        circuit,
+        public_input,
+    )
+}
+
+pub fn verify_kzg<ConcreteCircuit: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    empty_circuit: &ConcreteCircuit,
+    proof: &[u8],
+    public_input: &[&[Fr]],
+) -> bool {
+    verify::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, KzgSingleStrategy<'_, Bn256>, _>(
+        params,
+        empty_circuit,
+        proof,
+        public_input,
+    )
+}
+
+pub fn verify_kzg_with_vk(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    public_input: &[&[Fr]],
+) -> bool {
+    verify_with_vk::<KZGCommitmentScheme<Bn256>, VerifierGWC<'_, Bn256>, KzgSingleStrategy<'_, Bn256>>(
+        params,
+        vk,
+        proof,
+        public_input,
+    )
+}
+
+pub fn verify_kzg_batch<ConcreteCircuit: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    empty_circuit: &ConcreteCircuit,
+    proofs: &[(&[u8], Vec<&[Fr]>)],
+) -> bool {
+    verify_batch::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierGWC<'_, Bn256>,
+        KzgAccumulatorStrategy<'_, Bn256>,
+        _,
+    >(params, empty_circuit, proofs)
+}
@@ -0,0 +1,460 @@
+//! Convolution with a public kernel (box / gaussian blur) and its proof.
+//!
+//! Every output sample is `sum(weight * neighbour)` divided by the kernel's
+//! divisor and rounded as in `Rounding`; neighbours outside the image repeat
+//! the nearest edge pixel. The circuit keeps one image row per circuit row,
+//! reads the `size` rows of a neighbourhood with rotations (the rows above and
+//! below the image are copies of the edge rows) and checks
+//! `sum + offset = divisor * output + remainder` for every output sample, with
+//! the remainder looked up in the range the rounding allows for the kernel's
+//! divisor (`RemainderTable`).
+//!
+//! The proof is about an RGBA8 original: its samples are range checked to
+//! bytes and bound by `digest::pixels_digest`, the second public input, so the
+//! blur is tied to one committed original. The digest is Poseidon over Pasta,
+//! so only the IPA backend is supported.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    channels::{channel_public_input, ChannelLayout, NormalizedImage},
+    digest::{digest_rows, pixels_digest, DigestConfig},
+    helpers::{ipa_parameter, sample, RemainderTable},
+    rounding, set_once, Parameter, HEIGHT, WIDTH,
+};
+
+// RGBA
+const CHANNELS: usize = 4;
+
+// 畳み込みのカーネル (回路の形の一部)
+pub static KERNEL: OnceCell<Kernel> = OnceCell::new();
+
+// the digest of the original dominates the rows, so k follows the original size
+pub static BLUR_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn blur_parameter() -> &'static Parameter {
+    BLUR_PARAMETER.get_or_init(|| {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+        let radius = KERNEL.get().unwrap().radius();
+        // the digest, then one row per image row and the edge rows
+        ipa_parameter(digest_rows(width * height) + height + 2 * radius)
+    })
+}
+
+/// Square integer kernel, row major, applied as `sum(weight * sample) / divisor`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Kernel {
+    size: usize,
+    weights: Vec<u32>,
+    divisor: u32,
+}
+
+impl Kernel {
+    /// `size` must be odd and the weights must not add up to more than `divisor`,
+    /// so that every output fits in 8 bits.
+    pub fn new(size: usize, weights: Vec<u32>, divisor: u32) -> Self {
+        assert!(size % 2 == 1);
+        assert_eq!(weights.len(), size * size);
+        assert!(divisor > 0 && weights.iter().sum::<u32>() <= divisor);
+        Kernel {
+            size,
+            weights,
+            divisor,
+        }
+    }
+
+    pub fn box_blur(size: usize) -> Self {
+        Self::new(size, vec![1; size * size], (size * size) as u32)
+    }
+
+    /// 3 X 3 binomial approximation of a gaussian, sums to 16.
+    pub fn gaussian3() -> Self {
+        Self::binomial(&[1, 2, 1])
+    }
+
+    /// 5 X 5 binomial approximation of a gaussian, sums to 256.
+    pub fn gaussian5() -> Self {
+        Self::binomial(&[1, 4, 6, 4, 1])
+    }
+
+    fn binomial(row: &[u32]) -> Self {
+        let weights = row
+            .iter()
+            .flat_map(|a| row.iter().map(move |b| a * b))
+            .collect::<Vec<_>>();
+        let divisor = weights.iter().sum();
+        Self::new(row.len(), weights, divisor)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn weights(&self) -> &[u32] {
+        &self.weights
+    }
+
+    pub fn divisor(&self) -> u32 {
+        self.divisor
+    }
+
+    fn radius(&self) -> usize {
+        self.size / 2
+    }
+}
+
+// 画像の外の座標は端のピクセルを繰り返す
+fn clamp(i: usize, radius: usize, len: usize) -> usize {
+    i.saturating_sub(radius).min(len - 1)
+}
+
+// 元の画像の大きさとカーネルから回路の形を設定する: 既に別の形なら false
+pub fn set_blur_shape(width: u32, height: u32, kernel: &Kernel) -> bool {
+    set_once(&WIDTH, width as usize)
+        & set_once(&HEIGHT, height as usize)
+        & set_once(&KERNEL, kernel.clone())
+}
+
+// (blurred samples, remainders), interleaved like `img.pixels`
+fn convolve(img: &NormalizedImage, kernel: &Kernel) -> (Vec<u8>, Vec<u32>) {
+    let channels = img.layout.channels();
+    let (width, height) = (img.width as usize, img.height as usize);
+    let (size, radius) = (kernel.size, kernel.radius());
+    let rounding = rounding();
+
+    let mut blurred = Vec::with_capacity(img.pixels.len());
+    let mut remainders = Vec::with_capacity(img.pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..channels {
+                let mut sum = 0;
+                for dy in 0..size {
+                    let row = clamp(y + dy, radius, height);
+                    for dx in 0..size {
+                        let col = clamp(x + dx, radius, width);
+                        sum += kernel.weights[dy * size + dx]
+                            * img.pixels[(row * width + col) * channels + c] as u32;
+                    }
+                }
+                let (output, remainder) = rounding.divide(sum, kernel.divisor);
+                blurred.push(output as u8);
+                remainders.push(remainder);
+            }
+        }
+    }
+    (blurred, remainders)
+}
+
+/// `img` convolved with `kernel` and the current `rounding`, same size and layout.
+pub fn blur(img: &NormalizedImage, kernel: &Kernel) -> NormalizedImage {
+    let (pixels, _) = convolve(img, kernel);
+    NormalizedImage { pixels, ..*img }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlurConfig {
+    // one column per image column, circuit row `radius + y` holds image row y
+    original: [Vec<Column<Advice>>; CHANNELS],
+    // circuit row y holds output row y
+    blurred: [Vec<Column<Advice>>; CHANNELS],
+    remainder: [Vec<Column<Advice>>; CHANNELS],
+    // parity of `blurred` under `HalfEven`
+    parity: [Vec<Option<Column<Advice>>>; CHANNELS],
+    instance: [Vec<Column<Instance>>; CHANNELS],
+    // row 0: digest of the original
+    statement: Column<Instance>,
+    remainders: RemainderTable,
+    digest: DigestConfig,
+    q_blur: Selector,
+    q_original: Selector,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BlurCircuit {
+    // interleaved RGBA samples of the original, range checked to bytes by the
+    // circuit; empty without witnesses
+    pub original: Vec<Value<u32>>,
+    pub blurred: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u32>>,
+}
+
+impl Circuit<Fp> for BlurCircuit {
+    type Config = BlurConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let width = *WIDTH.get().unwrap();
+        let kernel = KERNEL.get().unwrap().clone();
+        let columns = |meta: &mut ConstraintSystem<Fp>| {
+            (0..width).map(|_| meta.advice_column()).collect::<Vec<_>>()
+        };
+
+        let original = [(); CHANNELS].map(|_| columns(meta));
+        let blurred = [(); CHANNELS].map(|_| columns(meta));
+        let remainder = [(); CHANNELS].map(|_| columns(meta));
+        let instance = [(); CHANNELS].map(|_| {
+            (0..width)
+                .map(|_| meta.instance_column())
+                .collect::<Vec<_>>()
+        });
+        // after the blurred columns, so the digest is the last instance column
+        let statement = meta.instance_column();
+        original
+            .iter()
+            .chain(blurred.iter())
+            .flatten()
+            .for_each(|&col| meta.enable_equality(col));
+        instance
+            .iter()
+            .flatten()
+            .chain([&statement])
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_blur = meta.complex_selector();
+        let q_original = meta.complex_selector();
+        let digest = DigestConfig::configure(meta);
+        // every original cell, the edge rows included
+        digest
+            .bytes()
+            .range_check(meta, "original byte", q_original, &original.concat());
+        let remainders = RemainderTable::configure(meta, rounding(), kernel.divisor);
+        let parity = std::array::from_fn(|c| {
+            (0..width)
+                .map(|x| {
+                    remainders.range_check(
+                        meta,
+                        "blur remainder",
+                        q_blur,
+                        blurred[c][x],
+                        remainder[c][x],
+                    )
+                })
+                .collect()
+        });
+        meta.create_gate("convolve", |virtual_cells| {
+            let q_blur = virtual_cells.query_selector(q_blur);
+            let offset = Expression::Constant(Fp::from(rounding().offset(kernel.divisor) as u64));
+            let divisor = Expression::Constant(Fp::from(kernel.divisor as u64));
+            let (size, radius) = (kernel.size, kernel.radius());
+
+            let mut constraints = Vec::with_capacity(CHANNELS * width);
+            for c in 0..CHANNELS {
+                for x in 0..width {
+                    let mut sum = offset.clone();
+                    for dy in 0..size {
+                        for dx in 0..size {
+                            let weight = kernel.weights[dy * size + dx];
+                            if weight == 0 {
+                                continue;
+                            }
+                            let col = clamp(x + dx, radius, width);
+                            sum = sum
+                                + Expression::Constant(Fp::from(weight as u64))
+                                    * virtual_cells
+                                        .query_advice(original[c][col], Rotation(dy as i32));
+                        }
+                    }
+                    let blurred = virtual_cells.query_advice(blurred[c][x], Rotation::cur());
+                    let remainder = virtual_cells.query_advice(remainder[c][x], Rotation::cur());
+                    constraints.push(sum - (divisor.clone() * blurred + remainder));
+                }
+            }
+            Constraints::with_selector(q_blur, constraints)
+        });
+
+        BlurConfig {
+            original,
+            blurred,
+            remainder,
+            parity,
+            instance,
+            statement,
+            remainders,
+            digest,
+            q_blur,
+            q_original,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let width = *WIDTH.get().unwrap();
+        let height = *HEIGHT.get().unwrap();
+        let radius = KERNEL.get().unwrap().radius();
+        config.remainders.load(&mut layouter)?;
+        config.digest.load(&mut layouter)?;
+
+        // (pixels of the image rows, blurred[(y * width + x) * CHANNELS + c])
+        let (original, blurred) = layouter.assign_region(
+            || "convolve",
+            |mut region| {
+                let mut rows: Vec<Vec<AssignedCell<Fp, Fp>>> =
+                    Vec::with_capacity(height + 2 * radius);
+                for row in 0..height + 2 * radius {
+                    config.q_original.enable(&mut region, row)?;
+                    let y = clamp(row, radius, height);
+                    let mut cells = Vec::with_capacity(width * CHANNELS);
+                    for x in 0..width {
+                        for c in 0..CHANNELS {
+                            cells.push(region.assign_advice(
+                                || "original",
+                                config.original[c][x],
+                                row,
+                                || sample(&self.original, (y * width + x) * CHANNELS + c),
+                            )?);
+                        }
+                    }
+                    rows.push(cells);
+                }
+                // 画像の上下の行は端の行のコピー
+                for row in (0..radius).chain(height + radius..height + 2 * radius) {
+                    let edge = clamp(row, radius, height) + radius;
+                    for (cell, edge_cell) in rows[row].iter().zip(&rows[edge]) {
+                        region.constrain_equal(cell.cell(), edge_cell.cell())?;
+                    }
+                }
+                let original = rows[radius..radius + height]
+                    .iter()
+                    .flat_map(|cells| cells.chunks(CHANNELS))
+                    .map(|pixel| <[_; CHANNELS]>::try_from(pixel.to_vec()).unwrap())
+                    .collect::<Vec<_>>();
+
+                let mut cells = Vec::with_capacity(height * width * CHANNELS);
+                for y in 0..height {
+                    config.q_blur.enable(&mut region, y)?;
+                    for x in 0..width {
+                        for c in 0..CHANNELS {
+                            let index = (y * width + x) * CHANNELS + c;
+                            region.assign_advice(
+                                || "remainder",
+                                config.remainder[c][x],
+                                y,
+                                || sample(&self.remainder, index),
+                            )?;
+                            RemainderTable::assign_parity(
+                                &mut region,
+                                config.parity[c][x],
+                                y,
+                                self.blurred
+                                    .get(index)
+                                    .copied()
+                                    .unwrap_or_else(Value::unknown),
+                            )?;
+                            cells.push(region.assign_advice(
+                                || "blurred",
+                                config.blurred[c][x],
+                                y,
+                                || sample(&self.blurred, index),
+                            )?);
+                        }
+                    }
+                }
+                Ok((original, cells))
+            },
+        )?;
+
+        let digest = config
+            .digest
+            .digest(layouter.namespace(|| "original digest"), &original)?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..CHANNELS {
+                    let cell = &blurred[(y * width + x) * CHANNELS + c];
+                    layouter.constrain_instance(cell.cell(), config.instance[c][x], y)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The circuit proving the returned blur of the RGBA8 image `img`, also sets the shape globals.
+pub fn blur_circuit(img: &NormalizedImage, kernel: &Kernel) -> (BlurCircuit, NormalizedImage) {
+    assert_eq!(img.layout, ChannelLayout::Rgba);
+    assert!(img.width > 0 && img.height > 0);
+    assert!(
+        set_blur_shape(img.width, img.height, kernel),
+        "the circuit shape is already set for another image size or kernel"
+    );
+    let (blurred, remainder) = convolve(img, kernel);
+
+    let circuit = BlurCircuit {
+        original: img.pixels.iter().map(|&v| Value::known(v as u32)).collect(),
+        blurred: blurred.iter().map(|&v| Value::known(v)).collect(),
+        remainder: remainder.iter().map(|&v| Value::known(v)).collect(),
+    };
+    let blurred = NormalizedImage {
+        pixels: blurred,
+        ..*img
+    };
+    (circuit, blurred)
+}
+
+/// [blurred samples as in `channel_public_input`.., [digest of the original]]
+pub fn blur_public_input(blurred: &NormalizedImage, digest: Fp) -> Vec<Vec<Fp>> {
+    let mut public_input = channel_public_input::<Fp>(blurred);
+    public_input.push(vec![digest]);
+    public_input
+}
+
+/// Blurs the RGBA8 image `img` with `kernel` and proves it, returns the blurred image
+/// (same size and layout), the proof and the digest of `img` (`Fp::to_repr`).
+pub fn create_blur_proof(
+    img: &NormalizedImage,
+    kernel: &Kernel,
+) -> (NormalizedImage, Vec<u8>, [u8; 32]) {
+    let (circuit, blurred) = blur_circuit(img, kernel);
+    let digest = pixels_digest(&img.pixels);
+    let public_input = blur_public_input(&blurred, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = blur_parameter().get_parameter();
+    let empty_circuit = BlurCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    (blurred, proof, digest.to_repr())
+}
+
+/// Checks that `proof` proves the RGBA8 `blurred` is the original with digest `digest`
+/// convolved with `kernel`.
+pub fn verify_blur(
+    proof: &[u8],
+    blurred: &NormalizedImage,
+    kernel: &Kernel,
+    digest: [u8; 32],
+) -> bool {
+    let expected = blurred.width as usize * blurred.height as usize * CHANNELS;
+    if blurred.layout != ChannelLayout::Rgba || blurred.pixels.len() != expected || expected == 0 {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_blur_shape(blurred.width, blurred.height, kernel) {
+        return false;
+    }
+    let public_input = blur_public_input(blurred, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = blur_parameter().get_parameter();
+    verify_ipa(&params, &BlurCircuit::default(), proof, &public_input)
+}
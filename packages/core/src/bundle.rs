@@ -0,0 +1,252 @@
+//! Self-describing proof documents exchanged between the web app and the backend.
+//!
+//! A [`ProofBundle`] carries everything `verify_img` needs: the shape of the
+//! original image, the published thumbnail and the proof transcript. It can be
+//! encoded as JSON (binary fields as base64 strings) or as compact CBOR
+//! (binary fields as byte strings).
+
+use std::fmt;
+
+use image::{EncodableLayout, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::Backend,
+    create_img_proof_with_pk, create_img_proof_with_progress,
+    downsample::{Downsampler, Rounding},
+    progress::{CancelToken, Cancelled, NoProgress, ProgressSink},
+    rounding, set_rounding, set_shape, verify_img_with, ImgProvingKey, SCALE_FACTOR,
+};
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    // RGBA8, row major
+    #[serde(with = "bytes")]
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofBundle {
+    pub version: u32,
+    // bundles written before KZG support carry IPA proofs
+    #[serde(default)]
+    pub backend: Backend,
+    // shape of the original picture
+    pub width: u32,
+    pub height: u32,
+    pub scale: u32,
+    // bundles written before rounding modes used floor division
+    #[serde(default)]
+    pub rounding: Rounding,
+    pub thumbnail: Thumbnail,
+    #[serde(with = "bytes")]
+    pub proof: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Json(serde_json::Error),
+    Cbor(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Json(e) => write!(f, "invalid JSON bundle: {}", e),
+            BundleError::Cbor(e) => write!(f, "invalid CBOR bundle: {}", e),
+            BundleError::UnsupportedVersion(v) => write!(f, "unsupported bundle version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl ProofBundle {
+    /// Reduces the RGBA8 picture, proves the reduction and packs both into a bundle.
+    pub fn create(origin_buf: Vec<u8>, width: u32, height: u32) -> Self {
+        Self::create_with(Backend::Ipa, origin_buf, width, height)
+    }
+
+    pub fn create_with(backend: Backend, origin_buf: Vec<u8>, width: u32, height: u32) -> Self {
+        Self::create_with_progress(
+            backend,
+            origin_buf,
+            width,
+            height,
+            &NoProgress,
+            &CancelToken::new(),
+        )
+        .expect("a fresh token is never cancelled")
+    }
+
+    /// Same as `create_with` with a proving key shared by all originals of this shape.
+    pub fn create_with_pk(
+        pk: &ImgProvingKey,
+        origin_buf: Vec<u8>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let thumbnail = Downsampler::default()
+            .downsample_rgba(&origin_buf, width, height)
+            .to_rgba_image();
+        let proof = create_img_proof_with_pk(pk, origin_buf, width, height);
+        Self::new(pk.backend(), width, height, &thumbnail, proof)
+    }
+
+    pub fn create_with_progress(
+        backend: Backend,
+        origin_buf: Vec<u8>,
+        width: u32,
+        height: u32,
+        progress: &impl ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Self, Cancelled> {
+        let thumbnail = Downsampler::default()
+            .downsample_rgba(&origin_buf, width, height)
+            .to_rgba_image();
+        let proof =
+            create_img_proof_with_progress(backend, origin_buf, width, height, progress, cancel)?;
+
+        Ok(Self::new(backend, width, height, &thumbnail, proof))
+    }
+
+    fn new(
+        backend: Backend,
+        width: u32,
+        height: u32,
+        thumbnail: &RgbaImage,
+        proof: Vec<u8>,
+    ) -> Self {
+        ProofBundle {
+            version: BUNDLE_VERSION,
+            backend,
+            width,
+            height,
+            scale: SCALE_FACTOR,
+            rounding: rounding(),
+            thumbnail: Thumbnail {
+                width: thumbnail.width(),
+                height: thumbnail.height(),
+                pixels: thumbnail.as_bytes().to_vec(),
+            },
+            proof,
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        // the circuit shape is global, a fresh verifier process learns it from the bundle
+        set_rounding(self.rounding);
+        if self.scale != SCALE_FACTOR || rounding() != self.rounding {
+            return false;
+        }
+        // a malformed thumbnail is rejected before it can fix the shape of this process
+        let thumbnail = &self.thumbnail;
+        if (thumbnail.width, thumbnail.height)
+            != (self.width / self.scale, self.height / self.scale)
+            || thumbnail.pixels.len() != thumbnail.width as usize * thumbnail.height as usize * 4
+        {
+            return false;
+        }
+        if !set_shape(self.width, self.height) {
+            return false;
+        }
+
+        verify_img_with(
+            self.backend,
+            self.proof.clone(),
+            self.thumbnail.pixels.clone(),
+            self.thumbnail.width,
+            self.thumbnail.height,
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("bundle serialization should not fail")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, BundleError> {
+        let bundle: Self = serde_json::from_str(json).map_err(BundleError::Json)?;
+        bundle.check_version()
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(self, &mut buf).expect("bundle serialization should not fail");
+        buf
+    }
+
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self, BundleError> {
+        let bundle: Self =
+            ciborium::de::from_reader(cbor).map_err(|e| BundleError::Cbor(e.to_string()))?;
+        bundle.check_version()
+    }
+
+    fn check_version(self) -> Result<Self, BundleError> {
+        if self.version != BUNDLE_VERSION {
+            return Err(BundleError::UnsupportedVersion(self.version));
+        }
+        Ok(self)
+    }
+}
+
+// base64 strings for human readable formats (JSON), raw byte strings otherwise (CBOR)
+mod bytes {
+    use std::fmt;
+
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("base64 string or byte string")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            STANDARD.decode(v).map_err(E::custom)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()? {
+                bytes.push(b);
+            }
+            Ok(bytes)
+        }
+    }
+}
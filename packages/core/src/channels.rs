@@ -0,0 +1,478 @@
+//! Images that are not RGBA8: a deterministic normalisation and reduction
+//! circuits specialised for 1 (gray) and 3 (RGB) channels.
+//!
+//! `normalize` maps any decoded `DynamicImage` to 8-bit gray, RGB or RGBA so
+//! the prover and every verifier derive the same bytes:
+//!
+//! - `L8`, `Rgb8`, `Rgba8`: unchanged
+//! - `La8`, `La16`: RGBA with red = green = blue = luma
+//! - 16-bit samples: `(v * 255 + 32767) / 65535`, i.e. rounded to the nearest 8-bit value
+//! - 32-bit float samples: clamped to `[0, 1]`, then `round(v * 255)`
+//! - palette images arrive expanded to RGB / RGBA from the decoder
+//! - any other layout: `DynamicImage::to_rgba8`
+//!
+//! RGBA images keep using `MyCircuit`, gray and RGB images are proved by
+//! `ChannelCircuit<F, 1>` / `ChannelCircuit<F, 3>`, which constrain every
+//! channel separately, look up every remainder in the range allowed by the
+//! rounding, and expose one instance column per thumbnail column and channel
+//! (channel major, like `MyCircuit`).
+
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{bn256::Fr, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use image::{DynamicImage, ImageBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{prove_ipa, prove_kzg, verify_ipa, verify_kzg, Backend},
+    create_img_proof_with,
+    downsample::Downsampler,
+    helpers::{sample, RemainderTable},
+    rounding, set_shape, verify_img_with, KZG_PARAMETER, PARAMETER, S_HEIGHT, S_WIDTH, WIDTH,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelLayout {
+    Gray,
+    Rgb,
+    Rgba,
+}
+
+impl ChannelLayout {
+    pub fn channels(&self) -> usize {
+        match self {
+            ChannelLayout::Gray => 1,
+            ChannelLayout::Rgb => 3,
+            ChannelLayout::Rgba => 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NormalizedImage {
+    pub layout: ChannelLayout,
+    pub width: u32,
+    pub height: u32,
+    // 8-bit samples, row major, `layout.channels()` per pixel
+    pub pixels: Vec<u8>,
+}
+
+fn from_16(v: u16) -> u8 {
+    ((v as u32 * 255 + 32767) / 65535) as u8
+}
+
+fn from_f32(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Converts a decoded image to 8-bit samples, see the module documentation for the rules.
+pub fn normalize(img: &DynamicImage) -> NormalizedImage {
+    let (layout, pixels) = match img {
+        DynamicImage::ImageLuma8(buf) => (ChannelLayout::Gray, buf.as_raw().clone()),
+        DynamicImage::ImageRgb8(buf) => (ChannelLayout::Rgb, buf.as_raw().clone()),
+        DynamicImage::ImageRgba8(buf) => (ChannelLayout::Rgba, buf.as_raw().clone()),
+        DynamicImage::ImageLumaA8(buf) => (
+            ChannelLayout::Rgba,
+            buf.pixels()
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+        ),
+        DynamicImage::ImageLuma16(buf) => (
+            ChannelLayout::Gray,
+            buf.as_raw().iter().map(|&v| from_16(v)).collect(),
+        ),
+        DynamicImage::ImageLumaA16(buf) => (
+            ChannelLayout::Rgba,
+            buf.pixels()
+                .flat_map(|p| {
+                    let luma = from_16(p[0]);
+                    [luma, luma, luma, from_16(p[1])]
+                })
+                .collect(),
+        ),
+        DynamicImage::ImageRgb16(buf) => (
+            ChannelLayout::Rgb,
+            buf.as_raw().iter().map(|&v| from_16(v)).collect(),
+        ),
+        DynamicImage::ImageRgba16(buf) => (
+            ChannelLayout::Rgba,
+            buf.as_raw().iter().map(|&v| from_16(v)).collect(),
+        ),
+        DynamicImage::ImageRgb32F(buf) => (
+            ChannelLayout::Rgb,
+            buf.as_raw().iter().map(|&v| from_f32(v)).collect(),
+        ),
+        DynamicImage::ImageRgba32F(buf) => (
+            ChannelLayout::Rgba,
+            buf.as_raw().iter().map(|&v| from_f32(v)).collect(),
+        ),
+        _ => (ChannelLayout::Rgba, img.to_rgba8().into_raw()),
+    };
+    NormalizedImage {
+        layout,
+        width: img.width(),
+        height: img.height(),
+        pixels,
+    }
+}
+
+impl NormalizedImage {
+    /// The image as `L8` / `Rgb8` / `Rgba8`, e.g. to save a thumbnail losslessly.
+    pub fn to_dynamic(&self) -> DynamicImage {
+        let (width, height, pixels) = (self.width, self.height, self.pixels.clone());
+        match self.layout {
+            ChannelLayout::Gray => {
+                DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels).unwrap())
+            }
+            ChannelLayout::Rgb => {
+                DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels).unwrap())
+            }
+            ChannelLayout::Rgba => {
+                DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels).unwrap())
+            }
+        }
+    }
+
+    /// Thumbnail in the same layout.
+    pub fn reduce(&self) -> NormalizedImage {
+        let reduced = Downsampler::default().downsample(
+            &self.pixels,
+            self.layout.channels(),
+            self.width,
+            self.height,
+        );
+        NormalizedImage {
+            layout: self.layout,
+            width: reduced.width,
+            height: reduced.height,
+            pixels: reduced.pixels,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChannelConfig<const C: usize> {
+    // two original columns per thumbnail column, a trailing odd column is not used
+    original: [Vec<Column<Advice>>; C],
+    reduced: [Vec<Column<Advice>>; C],
+    remainder: [Vec<Column<Advice>>; C],
+    // parity of `reduced` under `HalfEven`
+    parity: [Vec<Option<Column<Advice>>>; C],
+    instance: [Vec<Column<Instance>>; C],
+    remainders: RemainderTable,
+    q_reduce: Selector,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ChannelCircuit<F: FieldExt, const C: usize> {
+    // interleaved samples as in `NormalizedImage::pixels`, empty without witnesses
+    pub original: Vec<Value<u8>>,
+    pub reduced: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u8>>,
+    pub _marker: std::marker::PhantomData<F>,
+}
+
+pub type GrayCircuit<F> = ChannelCircuit<F, 1>;
+pub type RgbCircuit<F> = ChannelCircuit<F, 3>;
+
+impl<F: FieldExt, const C: usize> Circuit<F> for ChannelCircuit<F, C> {
+    type Config = ChannelConfig<C>;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let s_width = *S_WIDTH.get().unwrap();
+        let columns = |meta: &mut ConstraintSystem<F>, n: usize| {
+            (0..n).map(|_| meta.advice_column()).collect::<Vec<_>>()
+        };
+
+        let original = [(); C].map(|_| columns(meta, 2 * s_width));
+        let remainder = [(); C].map(|_| columns(meta, s_width));
+        let reduced = [(); C].map(|_| columns(meta, s_width));
+        let instance = [(); C].map(|_| {
+            (0..s_width)
+                .map(|_| meta.instance_column())
+                .collect::<Vec<_>>()
+        });
+        reduced
+            .iter()
+            .flatten()
+            .for_each(|&col| meta.enable_equality(col));
+        instance
+            .iter()
+            .flatten()
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_reduce = meta.complex_selector();
+        let remainders = RemainderTable::configure(meta, rounding(), 4);
+        let parity = std::array::from_fn(|c| {
+            (0..s_width)
+                .map(|i| {
+                    remainders.range_check(
+                        meta,
+                        "channel remainder",
+                        q_reduce,
+                        reduced[c][i],
+                        remainder[c][i],
+                    )
+                })
+                .collect()
+        });
+        meta.create_gate("reduce channels", |virtual_cells| {
+            let q_reduce = virtual_cells.query_selector(q_reduce);
+            let offset = Expression::Constant(F::from(rounding().offset(4) as u64));
+            let mut constraints = Vec::with_capacity(C * s_width);
+            for c in 0..C {
+                for i in 0..s_width {
+                    let sum = virtual_cells.query_advice(original[c][2 * i], Rotation::cur())
+                        + virtual_cells.query_advice(original[c][2 * i + 1], Rotation::cur())
+                        + virtual_cells.query_advice(original[c][2 * i], Rotation::next())
+                        + virtual_cells.query_advice(original[c][2 * i + 1], Rotation::next())
+                        + offset.clone();
+                    let reduced = virtual_cells.query_advice(reduced[c][i], Rotation::cur());
+                    let remainder = virtual_cells.query_advice(remainder[c][i], Rotation::cur());
+                    constraints
+                        .push(sum - (Expression::Constant(F::from(4)) * reduced + remainder));
+                }
+            }
+            Constraints::with_selector(q_reduce, constraints)
+        });
+
+        ChannelConfig {
+            original,
+            reduced,
+            remainder,
+            parity,
+            instance,
+            remainders,
+            q_reduce,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let width = *WIDTH.get().unwrap();
+        let s_width = *S_WIDTH.get().unwrap();
+        let s_height = *S_HEIGHT.get().unwrap();
+        config.remainders.load(&mut layouter)?;
+
+        let cells = layouter.assign_region(
+            || "reduce channels",
+            |mut region| {
+                let mut cells = Vec::with_capacity(s_height * s_width * C);
+                for y in 0..s_height {
+                    config.q_reduce.enable(&mut region, 2 * y)?;
+                    for x in 0..s_width {
+                        for c in 0..C {
+                            for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                                let index = ((2 * y + dy) * width + 2 * x + dx) * C + c;
+                                region.assign_advice(
+                                    || "original",
+                                    config.original[c][2 * x + dx],
+                                    2 * y + dy,
+                                    || sample(&self.original, index),
+                                )?;
+                            }
+                            let index = (y * s_width + x) * C + c;
+                            region.assign_advice(
+                                || "remainder",
+                                config.remainder[c][x],
+                                2 * y,
+                                || sample(&self.remainder, index),
+                            )?;
+                            RemainderTable::assign_parity(
+                                &mut region,
+                                config.parity[c][x],
+                                2 * y,
+                                self.reduced
+                                    .get(index)
+                                    .copied()
+                                    .unwrap_or_else(Value::unknown),
+                            )?;
+                            cells.push((
+                                c,
+                                x,
+                                y,
+                                region.assign_advice(
+                                    || "reduced",
+                                    config.reduced[c][x],
+                                    2 * y,
+                                    || sample(&self.reduced, index),
+                                )?,
+                            ));
+                        }
+                    }
+                }
+                Ok(cells)
+            },
+        )?;
+
+        for (c, x, y, cell) in cells {
+            layouter.constrain_instance(cell.cell(), config.instance[c][x], y)?;
+        }
+        Ok(())
+    }
+}
+
+/// The circuit proving `img` reduces to the returned thumbnail, also sets the shape globals.
+pub fn channel_circuit<F: FieldExt, const C: usize>(
+    img: &NormalizedImage,
+) -> (ChannelCircuit<F, C>, NormalizedImage) {
+    assert_eq!(img.layout.channels(), C);
+    assert!(
+        set_shape(img.width, img.height),
+        "the circuit shape is already set for another image size"
+    );
+    let reduced = Downsampler::default().downsample(&img.pixels, C, img.width, img.height);
+    let known = |samples: &[u8]| samples.iter().map(|&v| Value::known(v)).collect::<Vec<_>>();
+
+    let circuit = ChannelCircuit {
+        original: known(&img.pixels),
+        reduced: known(&reduced.pixels),
+        remainder: reduced
+            .remainders
+            .iter()
+            .map(|&v| Value::known(v as u8))
+            .collect(),
+        _marker: std::marker::PhantomData,
+    };
+    let thumbnail = NormalizedImage {
+        layout: img.layout,
+        width: reduced.width,
+        height: reduced.height,
+        pixels: reduced.pixels,
+    };
+    (circuit, thumbnail)
+}
+
+// channel major, then one column of `height` samples per thumbnail column
+pub fn channel_public_input<F: FieldExt>(thumbnail: &NormalizedImage) -> Vec<Vec<F>> {
+    let channels = thumbnail.layout.channels();
+    let (width, height) = (thumbnail.width as usize, thumbnail.height as usize);
+    let mut public_input = Vec::with_capacity(channels * width);
+    for c in 0..channels {
+        for x in 0..width {
+            public_input.push(
+                (0..height)
+                    .map(|y| F::from(thumbnail.pixels[(y * width + x) * channels + c] as u64))
+                    .collect(),
+            );
+        }
+    }
+    public_input
+}
+
+fn prove_channels<const C: usize>(
+    backend: Backend,
+    img: &NormalizedImage,
+) -> (NormalizedImage, Vec<u8>) {
+    match backend {
+        Backend::Ipa => {
+            let (circuit, thumbnail) = channel_circuit::<Fp, C>(img);
+            let public_input = channel_public_input::<Fp>(&thumbnail);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = PARAMETER.get_parameter();
+            let empty_circuit = ChannelCircuit::<Fp, C>::default();
+            let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+            (thumbnail, proof)
+        }
+        Backend::Kzg => {
+            let (circuit, thumbnail) = channel_circuit::<Fr, C>(img);
+            let public_input = channel_public_input::<Fr>(&thumbnail);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = KZG_PARAMETER.get_parameter();
+            let empty_circuit = ChannelCircuit::<Fr, C>::default();
+            let proof = prove_kzg(&params, &empty_circuit, circuit, &public_input);
+            (thumbnail, proof)
+        }
+    }
+}
+
+fn verify_channels<const C: usize>(
+    backend: Backend,
+    proof: &[u8],
+    thumbnail: &NormalizedImage,
+) -> bool {
+    match backend {
+        Backend::Ipa => {
+            let public_input = channel_public_input::<Fp>(thumbnail);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = PARAMETER.get_parameter();
+            verify_ipa(
+                &params,
+                &ChannelCircuit::<Fp, C>::default(),
+                proof,
+                &public_input,
+            )
+        }
+        Backend::Kzg => {
+            let public_input = channel_public_input::<Fr>(thumbnail);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = KZG_PARAMETER.get_parameter();
+            verify_kzg(
+                &params,
+                &ChannelCircuit::<Fr, C>::default(),
+                proof,
+                &public_input,
+            )
+        }
+    }
+}
+
+/// Normalises `img` and proves its reduction with the circuit for its channel layout,
+/// returns the thumbnail (same layout) and the proof.
+pub fn create_img_proof_dynamic(
+    backend: Backend,
+    img: &DynamicImage,
+) -> (NormalizedImage, Vec<u8>) {
+    create_img_proof_normalized(backend, &normalize(img))
+}
+
+pub fn create_img_proof_normalized(
+    backend: Backend,
+    img: &NormalizedImage,
+) -> (NormalizedImage, Vec<u8>) {
+    match img.layout {
+        ChannelLayout::Gray => prove_channels::<1>(backend, img),
+        ChannelLayout::Rgb => prove_channels::<3>(backend, img),
+        ChannelLayout::Rgba => {
+            let proof = create_img_proof_with(backend, img.pixels.clone(), img.width, img.height);
+            (img.reduce(), proof)
+        }
+    }
+}
+
+/// Checks a proof of `create_img_proof_dynamic` against its thumbnail, the shape globals must be set.
+pub fn verify_img_dynamic(backend: Backend, proof: Vec<u8>, thumbnail: &NormalizedImage) -> bool {
+    let expected =
+        thumbnail.width as usize * thumbnail.height as usize * thumbnail.layout.channels();
+    if thumbnail.pixels.len() != expected {
+        return false;
+    }
+    match thumbnail.layout {
+        ChannelLayout::Gray => verify_channels::<1>(backend, &proof, thumbnail),
+        ChannelLayout::Rgb => verify_channels::<3>(backend, &proof, thumbnail),
+        ChannelLayout::Rgba => verify_img_with(
+            backend,
+            proof,
+            thumbnail.pixels.clone(),
+            thumbnail.width,
+            thumbnail.height,
+        ),
+    }
+}
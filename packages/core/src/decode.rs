@@ -0,0 +1,78 @@
+//! Canonical decoding, so every prover and verifier derives the same samples.
+//!
+//! `decode` reads the stored samples of an encoded image as they are: ICC
+//! profiles, gAMA / cHRM / sRGB chunks and EXIF orientation are ignored, and
+//! the result is normalised to gray, RGB or RGBA in that channel order
+//! (`channels::normalize`). Verifiers must read published thumbnails with it
+//! (or take the bytes from a `ProofBundle`), never through a browser canvas,
+//! which colour-manages and round-trips pixels through premultiplied alpha.
+//!
+//! Originals may come from a canvas, so before proving they are brought into
+//! `canonicalize`d form: every RGBA pixel is replaced by its premultiply /
+//! un-premultiply round trip (below). That form is a fixed point of the round
+//! trip, so a canvas copy of a canonical original has the same bytes.
+
+use image::ImageResult;
+
+use crate::channels::{normalize, ChannelLayout, NormalizedImage};
+
+// 8-bit premultiplication as done by canvas implementations, rounded to nearest
+fn premultiply(color: u8, alpha: u8) -> u8 {
+    ((color as u32 * alpha as u32 + 127) / 255) as u8
+}
+
+fn unpremultiply_sample(color: u8, alpha: u8) -> u8 {
+    if alpha == 0 {
+        0
+    } else {
+        ((color as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8
+    }
+}
+
+/// Exact samples of an encoded image (PNG, JPEG, ...), no colour management.
+pub fn decode(bytes: &[u8]) -> ImageResult<NormalizedImage> {
+    image::load_from_memory(bytes).map(|img| normalize(&img))
+}
+
+/// Straight RGBA8 from premultiplied RGBA8, transparent pixels become (0, 0, 0, 0).
+pub fn unpremultiply(buf: &[u8]) -> Vec<u8> {
+    buf.chunks(4)
+        .flat_map(|p| {
+            let alpha = p[3];
+            [
+                unpremultiply_sample(p[0], alpha),
+                unpremultiply_sample(p[1], alpha),
+                unpremultiply_sample(p[2], alpha),
+                alpha,
+            ]
+        })
+        .collect()
+}
+
+/// Replaces every RGBA8 pixel by its premultiply / un-premultiply round trip.
+/// Opaque pixels are unchanged, transparent ones become (0, 0, 0, 0).
+pub fn canonicalize_rgba(buf: &mut [u8]) {
+    for p in buf.chunks_mut(4) {
+        let alpha = p[3];
+        if alpha == 255 {
+            continue;
+        }
+        for c in &mut p[..3] {
+            *c = unpremultiply_sample(premultiply(*c, alpha), alpha);
+        }
+    }
+}
+
+/// `canonicalize_rgba` for RGBA images, gray and RGB images have nothing to canonicalise.
+pub fn canonicalize(img: &mut NormalizedImage) {
+    if img.layout == ChannelLayout::Rgba {
+        canonicalize_rgba(&mut img.pixels);
+    }
+}
+
+/// Decodes an original to be proved: `decode`, then `canonicalize`.
+pub fn decode_original(bytes: &[u8]) -> ImageResult<NormalizedImage> {
+    let mut img = decode(bytes)?;
+    canonicalize(&mut img);
+    Ok(img)
+}
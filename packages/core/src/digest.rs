@@ -0,0 +1,208 @@
+//! Poseidon digest of RGBA pixels, computed natively and in circuit.
+//!
+//! Every pixel is packed into one field element `r + 2^8 g + 2^16 b + 2^24 a`
+//! and absorbed into the chain `h = H(h, pixel)` starting from `h = 0`. The
+//! channels are range checked to bytes, otherwise a carry between two channels
+//! (`r + 256`, `g - 1`) would pack to the same element.
+
+use halo2_gadgets::poseidon::{
+    primitives::{self as poseidon, ConstantLength, P128Pow5T3},
+    Hash as PoseidonHash, Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    halo2curves::pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use crate::helpers::ByteTable;
+
+pub fn pack_pixel(pixel: &[u8]) -> Fp {
+    Fp::from(u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]) as u64)
+}
+
+pub fn hash_pair(left: Fp, right: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash([left, right])
+}
+
+// buf: RGBA8, row major
+pub fn pixels_digest(buf: &[u8]) -> Fp {
+    buf.chunks(4).fold(Fp::zero(), |digest, pixel| {
+        hash_pair(digest, pack_pixel(pixel))
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct DigestConfig {
+    channels: [Column<Advice>; 4],
+    packed: Column<Advice>,
+    q_pack: Selector,
+    bytes: ByteTable,
+    poseidon: Pow5Config<Fp, 3, 2>,
+}
+
+impl DigestConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let channels = [(); 4].map(|_| meta.advice_column());
+        let packed = meta.advice_column();
+        channels
+            .iter()
+            .chain([&packed])
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_pack = meta.complex_selector();
+        meta.create_gate("pack pixel", |virtual_cells| {
+            let q_pack = virtual_cells.query_selector(q_pack);
+            let [red, green, blue, alpha] =
+                channels.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            let packed = virtual_cells.query_advice(packed, Rotation::cur());
+
+            Constraints::with_selector(
+                q_pack,
+                [packed
+                    - (red
+                        + green * Expression::Constant(Fp::from(1 << 8))
+                        + blue * Expression::Constant(Fp::from(1 << 16))
+                        + alpha * Expression::Constant(Fp::from(1 << 24)))],
+            )
+        });
+        let bytes = ByteTable::configure(meta);
+        bytes.range_check(meta, "channel byte", q_pack, &channels);
+
+        let state = [(); 3].map(|_| meta.advice_column());
+        let partial_sbox = meta.advice_column();
+        let rc_a = [(); 3].map(|_| meta.fixed_column());
+        let rc_b = [(); 3].map(|_| meta.fixed_column());
+        meta.enable_constant(rc_b[0]);
+        let poseidon = Pow5Chip::configure::<P128Pow5T3>(meta, state, partial_sbox, rc_a, rc_b);
+
+        Self {
+            channels,
+            packed,
+            q_pack,
+            bytes,
+            poseidon,
+        }
+    }
+
+    // loads the byte table of the channels, once per circuit before any `digest`
+    pub fn load(&self, layouter: &mut impl Layouter<Fp>) -> Result<(), Error> {
+        self.bytes.load(layouter)
+    }
+
+    // the byte table loaded by `load`, for other range checks of the same circuit
+    pub(crate) fn bytes(&self) -> ByteTable {
+        self.bytes
+    }
+
+    // pixels: (red, green, blue, alpha) cells in the same order as `pixels_digest` reads the buffer
+    pub fn digest(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        pixels: &[[AssignedCell<Fp, Fp>; 4]],
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let (zero, packed) = layouter.assign_region(
+            || "pack pixels",
+            |mut region| {
+                let zero =
+                    region.assign_advice_from_constant(|| "zero", self.packed, 0, Fp::zero())?;
+
+                let mut packed = Vec::with_capacity(pixels.len());
+                for (i, pixel) in pixels.iter().enumerate() {
+                    let offset = i + 1;
+                    self.q_pack.enable(&mut region, offset)?;
+                    for (cell, &column) in pixel.iter().zip(self.channels.iter()) {
+                        cell.copy_advice(|| "channel", &mut region, column, offset)?;
+                    }
+
+                    let value = pixel[0]
+                        .value()
+                        .zip(pixel[1].value())
+                        .zip(pixel[2].value())
+                        .zip(pixel[3].value())
+                        .map(|(((&red, &green), &blue), &alpha)| {
+                            red + green * Fp::from(1 << 8)
+                                + blue * Fp::from(1 << 16)
+                                + alpha * Fp::from(1 << 24)
+                        });
+                    packed.push(region.assign_advice(
+                        || "packed",
+                        self.packed,
+                        offset,
+                        || value,
+                    )?);
+                }
+                Ok((zero, packed))
+            },
+        )?;
+
+        let mut digest = zero;
+        for (i, pixel) in packed.into_iter().enumerate() {
+            digest = self.hash_pair(
+                layouter.namespace(|| format!("absorb {}", i)),
+                digest,
+                pixel,
+            )?;
+        }
+        Ok(digest)
+    }
+
+    pub fn hash_pair(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        left: AssignedCell<Fp, Fp>,
+        right: AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let chip = Pow5Chip::construct(self.poseidon.clone());
+        let hasher = PoseidonHash::<_, _, P128Pow5T3, ConstantLength<2>, 3, 2>::init(
+            chip,
+            layouter.namespace(|| "init"),
+        )?;
+        hasher.hash(layouter.namespace(|| "hash"), [left, right])
+    }
+
+    // same tree as `merkle::MerkleTree::new(leaves).root()`
+    pub fn merkle_root(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        leaves: Vec<AssignedCell<Fp, Fp>>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let mut level = leaves;
+        let padded = level.len().max(1).next_power_of_two();
+        if level.len() < padded {
+            let zero = layouter.assign_region(
+                || "zero leaf",
+                |mut region| {
+                    region.assign_advice_from_constant(|| "zero", self.packed, 0, Fp::zero())
+                },
+            )?;
+            level.resize(padded, zero);
+        }
+
+        let mut depth = 0;
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len() / 2);
+            for (i, pair) in level.chunks(2).enumerate() {
+                next.push(self.hash_pair(
+                    layouter.namespace(|| format!("node {} {}", depth, i)),
+                    pair[0].clone(),
+                    pair[1].clone(),
+                )?);
+            }
+            level = next;
+            depth += 1;
+        }
+        Ok(level.pop().unwrap())
+    }
+}
+
+// rows one digest of `pixels` pixels occupies: packing plus one permutation per pixel
+pub fn digest_rows(pixels: usize) -> usize {
+    pixels + 1 + pixels * 70
+}
+
+// rows of `merkle_root` over `leaves` leaves: the zero padding plus one permutation per inner node
+pub fn merkle_rows(leaves: usize) -> usize {
+    1 + leaves.max(1).next_power_of_two() * 70
+}
@@ -0,0 +1,224 @@
+//! Merkle commitment of the original picture for selective disclosure.
+//!
+//! `CommittedCircuit` proves the thumbnail like `MyCircuit` and additionally
+//! exposes the Merkle root of the original's rows, every leaf being the
+//! `digest::pixels_digest` of one row. The owner can later reveal single
+//! full-resolution rows (`disclose_rows`), or single tiles of a tiled proof
+//! (`TiledProof::disclose_tile`), and anyone holding the root checks them with
+//! `Disclosure::verify_row` or `TiledProof::verify_disclosure` without seeing
+//! the rest of the original.
+
+use std::ops::Range;
+
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
+};
+use image::{ImageBuffer, Rgba};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::{digest_rows, merkle_rows, pixels_digest, DigestConfig},
+    helpers::ipa_parameter,
+    img_circuit, img_public_input,
+    merkle::{MerklePath, MerkleTree},
+    set_shape, Config, MyCircuit, Parameter, HEIGHT, SCALE_FACTOR, WIDTH,
+};
+
+// the row digests need far more rows than the pixel gate, so k follows the original size
+pub static COMMIT_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn commit_parameter() -> &'static Parameter {
+    COMMIT_PARAMETER.get_or_init(|| {
+        let width = *WIDTH.get().unwrap();
+        let rows = committed_rows(*HEIGHT.get().unwrap() as u32) as usize;
+        ipa_parameter(digest_rows(width) * rows + merkle_rows(rows))
+    })
+}
+
+// the gate consumes two rows at a time, the last row of an odd height is not committed
+fn committed_rows(height: u32) -> u32 {
+    height - height % 2
+}
+
+/// Merkle tree over the row digests of an RGBA8 original.
+pub fn row_tree(origin_buf: &[u8], width: u32, height: u32) -> MerkleTree {
+    let leaves = origin_buf
+        .chunks((width * 4) as usize)
+        .take(committed_rows(height) as usize)
+        .map(pixels_digest)
+        .collect::<Vec<_>>();
+    MerkleTree::new(&leaves)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Disclosure {
+    // RGBA8 pixels of the revealed row, or of the revealed (padded) tile
+    pub pixels: Vec<u8>,
+    // `path.index` is the row, or the tile in raster order
+    pub path: MerklePath,
+}
+
+impl Disclosure {
+    /// Checks that the pixels are a leaf under `root` (`Fp::to_repr`) at `path.index`,
+    /// in a tree over `leaves` leaves of `leaf_len` bytes each.
+    pub fn verify(&self, root: &[u8; 32], leaf_len: usize, leaves: usize) -> bool {
+        // the zero padding leaves hash like the digest of a transparent black pixel,
+        // a path ending above them must not pass for a disclosure
+        if self.pixels.len() != leaf_len
+            || self.path.index as usize >= leaves
+            || self.path.siblings.len() != MerkleTree::depth(leaves)
+        {
+            return false;
+        }
+        self.path
+            .root(pixels_digest(&self.pixels))
+            .map_or(false, |computed| computed.to_repr() == *root)
+    }
+
+    /// `verify` for a row of a `width` x `height` original, see `row_tree`.
+    pub fn verify_row(&self, root: &[u8; 32], width: u32, height: u32) -> bool {
+        self.verify(root, (width * 4) as usize, committed_rows(height) as usize)
+    }
+}
+
+/// Reveals the original rows in `rows` with inclusion proofs against `row_tree(..).root()`.
+pub fn disclose_rows(
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+    rows: Range<u32>,
+) -> Vec<Disclosure> {
+    assert!(rows.end <= committed_rows(height));
+    let tree = row_tree(origin_buf, width, height);
+    let row_len = (width * 4) as usize;
+    rows.map(|row| {
+        let start = row as usize * row_len;
+        Disclosure {
+            pixels: origin_buf[start..start + row_len].to_vec(),
+            path: tree.path(row as usize),
+        }
+    })
+    .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct CommittedConfig {
+    image: Config,
+    digest: DigestConfig,
+    root: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommittedCircuit(pub MyCircuit<Fp>);
+
+impl Circuit<Fp> for CommittedCircuit {
+    type Config = CommittedConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let image = MyCircuit::<Fp>::configure_columns(meta, true, true);
+        let digest = DigestConfig::configure(meta);
+        // after the thumbnail columns, so the root is the last instance column
+        let root = meta.instance_column();
+        meta.enable_equality(root);
+
+        CommittedConfig {
+            image,
+            digest,
+            root,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self.0.assign_rows(&config.image, &mut layouter)?;
+        config.image.expose_reduced(&mut layouter, &cells.reduced)?;
+
+        config.digest.load(&mut layouter)?;
+        let width = *WIDTH.get().unwrap();
+        let mut leaves = Vec::new();
+        for (i, row) in cells.original.chunks(width).enumerate() {
+            leaves.push(
+                config
+                    .digest
+                    .digest(layouter.namespace(|| format!("row {}", i)), row)?,
+            );
+        }
+        let root = config
+            .digest
+            .merkle_root(layouter.namespace(|| "row root"), leaves)?;
+        layouter.constrain_instance(root.cell(), config.root, 0)
+    }
+}
+
+/// Witness and public input (thumbnail columns, then the row root) of a committed proof,
+/// e.g. for `MockProver`.
+pub fn committed_circuit(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (CommittedCircuit, Vec<Vec<Fp>>) {
+    let root = row_tree(&origin_buf, width, height).root();
+    let (circuit, result) = img_circuit::<Fp>(origin_buf, width, height);
+    let mut public_input = img_public_input::<Fp>(&result);
+    public_input.push(vec![root]);
+    (CommittedCircuit(circuit), public_input)
+}
+
+/// Thumbnail proof that also commits to the original, returns the proof and the row root (`Fp::to_repr`).
+pub fn create_img_proof_committed(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (Vec<u8>, [u8; 32]) {
+    let (circuit, public_input) = committed_circuit(origin_buf, width, height);
+    let root = public_input.last().unwrap()[0];
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = commit_parameter().get_parameter();
+    let empty_circuit = CommittedCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    (proof, root.to_repr())
+}
+
+// width and height are those of the original, which fix the shape of the circuit
+pub fn verify_img_committed(
+    proof: Vec<u8>,
+    small_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    root: [u8; 32],
+) -> bool {
+    let (s_width, s_height) = (width / SCALE_FACTOR, height / SCALE_FACTOR);
+    let img: ImageBuffer<Rgba<u8>, Vec<_>> =
+        match ImageBuffer::from_raw(s_width, s_height, small_buf) {
+            Some(img) if img.len() == (s_width * s_height * 4) as usize => img,
+            _ => return false,
+        };
+    if !set_shape(width, height) {
+        return false;
+    }
+    let root = match Option::<Fp>::from(Fp::from_repr(root)) {
+        Some(root) => root,
+        None => return false,
+    };
+    let mut public_input = img_public_input::<Fp>(&img);
+    public_input.push(vec![root]);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = commit_parameter().get_parameter();
+    let empty_circuit = CommittedCircuit::default();
+    verify_ipa(&params, &empty_circuit, &proof, &public_input)
+}
@@ -0,0 +1,190 @@
+//! Reference block-averaging engine.
+//!
+//! Every thumbnail shown to users and every circuit witness comes from
+//! `Downsampler::downsample`: each `block` X `block` block of the original is
+//! replaced by its average rounded as in `Rounding`, and the remainder is kept,
+//! so the circuits check `sum + offset = block^2 * average + remainder`.
+//! Trailing rows and columns that do not fill a block are dropped.
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::{rounding, SCALE_FACTOR};
+
+/// How a block average is rounded to 8 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    // sum / n, slightly darker than most editors
+    #[default]
+    Floor,
+    // (sum + n / 2) / n
+    HalfUp,
+    // as HalfUp, but exact ties go to the even average
+    HalfEven,
+}
+
+impl Rounding {
+    /// What the gates add to a sum before dividing it by `divisor`
+    /// (`block^2` for block averages). `HalfUp` and `HalfEven` add the same
+    /// offset, the circuits tell them apart by the remainders they accept
+    /// (`helpers::RemainderTable`).
+    pub fn offset(&self, divisor: u32) -> u32 {
+        match self {
+            Rounding::Floor => 0,
+            Rounding::HalfUp | Rounding::HalfEven => divisor / 2,
+        }
+    }
+
+    // (quotient, remainder) with sum + offset = divisor * quotient + remainder
+    pub(crate) fn divide(&self, sum: u32, divisor: u32) -> (u32, u32) {
+        let shifted = sum + self.offset(divisor);
+        let (quotient, remainder) = (shifted / divisor, shifted % divisor);
+        let tie = divisor % 2 == 0 && remainder == 0;
+        if *self == Rounding::HalfEven && tie && quotient % 2 == 1 {
+            // 偶数側に切り捨てる、余りは divisor になる
+            (quotient - 1, divisor)
+        } else {
+            (quotient, remainder)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Downsampler {
+    block: u32,
+    rounding: Rounding,
+}
+
+impl Default for Downsampler {
+    // the block size and rounding the circuits are built for
+    fn default() -> Self {
+        Self::new(SCALE_FACTOR).with_rounding(rounding())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Downsampled {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    // block averages, interleaved like the input
+    pub pixels: Vec<u8>,
+    // sum + offset - block^2 * average, in the order of `pixels`
+    pub remainders: Vec<u32>,
+}
+
+impl Downsampled {
+    /// Averages of channel `c` in raster order.
+    pub fn quotients(&self, c: usize) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .skip(c)
+            .step_by(self.channels)
+            .copied()
+            .collect()
+    }
+
+    /// Remainders of channel `c` in raster order.
+    pub fn remainders(&self, c: usize) -> Vec<u32> {
+        self.remainders
+            .iter()
+            .skip(c)
+            .step_by(self.channels)
+            .copied()
+            .collect()
+    }
+
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        assert_eq!(self.channels, 4);
+        RgbaImage::from_raw(self.width, self.height, self.pixels.clone()).unwrap()
+    }
+}
+
+impl Downsampler {
+    pub fn new(block: u32) -> Self {
+        assert!(block > 0);
+        Downsampler {
+            block,
+            rounding: Rounding::Floor,
+        }
+    }
+
+    pub fn with_rounding(self, rounding: Rounding) -> Self {
+        Downsampler { rounding, ..self }
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    pub fn block(&self) -> u32 {
+        self.block
+    }
+
+    /// buf: `channels` samples per pixel, row major.
+    pub fn downsample(&self, buf: &[u8], channels: usize, width: u32, height: u32) -> Downsampled {
+        assert_eq!(buf.len(), width as usize * height as usize * channels);
+        let block = self.block as usize;
+        let (s_width, s_height) = (width / self.block, height / self.block);
+        let pixel_count = (block * block) as u32;
+
+        let len = s_width as usize * s_height as usize * channels;
+        let mut pixels = Vec::with_capacity(len);
+        let mut remainders = Vec::with_capacity(len);
+        for y in 0..s_height as usize {
+            for x in 0..s_width as usize {
+                for c in 0..channels {
+                    let mut sum = 0u32;
+                    for dy in 0..block {
+                        let row = (y * block + dy) * width as usize;
+                        for dx in 0..block {
+                            sum += buf[(row + x * block + dx) * channels + c] as u32;
+                        }
+                    }
+                    let (average, remainder) = self.rounding.divide(sum, pixel_count);
+                    pixels.push(average as u8);
+                    remainders.push(remainder);
+                }
+            }
+        }
+
+        Downsampled {
+            width: s_width,
+            height: s_height,
+            channels,
+            pixels,
+            remainders,
+        }
+    }
+
+    pub fn downsample_rgba(&self, buf: &[u8], width: u32, height: u32) -> Downsampled {
+        self.downsample(buf, 4, width, height)
+    }
+
+    /// Pixelates `buf` keeping its size: `downsample`, then `upscale`.
+    pub fn mosaic(&self, buf: &[u8], channels: usize, width: u32, height: u32) -> Vec<u8> {
+        let reduced = self.downsample(buf, channels, width, height);
+        self.upscale(&reduced, width, height)
+    }
+
+    /// Mosaic of `width` X `height` with every block filled by its average; the dropped
+    /// trailing rows and columns repeat the nearest block.
+    pub fn upscale(&self, reduced: &Downsampled, width: u32, height: u32) -> Vec<u8> {
+        let channels = reduced.channels;
+        if reduced.width == 0 || reduced.height == 0 {
+            return vec![0; width as usize * height as usize * channels];
+        }
+
+        let mut buf = Vec::with_capacity(width as usize * height as usize * channels);
+        for y in 0..height {
+            let s_y = (y / self.block).min(reduced.height - 1);
+            for x in 0..width {
+                let s_x = (x / self.block).min(reduced.width - 1);
+                let start = (s_y * reduced.width + s_x) as usize * channels;
+                buf.extend_from_slice(&reduced.pixels[start..start + channels]);
+            }
+        }
+        buf
+    }
+}
@@ -0,0 +1,140 @@
+//! Solidity verifier for KZG/BN254 image proofs.
+//!
+//! The contract is generated by snark-verifier's EVM loader and checks the
+//! same statement as `verify_img_with(Backend::Kzg, ..)`. Proofs sent to it
+//! have to use the keccak based `EvmTranscript`, see [`create_img_proof_evm`].
+
+use std::rc::Rc;
+
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fq, Fr, G1Affine},
+    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, VerifyingKey},
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverGWC, VerifierGWC},
+            strategy::SingleStrategy,
+        },
+    },
+    transcript::{Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer},
+};
+use image::{ImageBuffer, Rgba};
+use rand_core::OsRng;
+use snark_verifier::{
+    loader::evm::{self, deploy_and_call, EvmLoader},
+    pcs::kzg::{Gwc19, KzgAs},
+    system::halo2::{compile, transcript::evm::EvmTranscript, Config},
+    verifier::{self, SnarkVerifier},
+};
+
+use crate::{img_circuit, img_public_input, MyCircuit, KZG_PARAMETER};
+
+type PlonkVerifier = verifier::plonk::PlonkVerifier<KzgAs<Bn256, Gwc19>>;
+
+/// Emits the Solidity source of a verifier for `vk`, one entry of `num_instance` per instance column.
+pub fn gen_solidity_verifier(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> String {
+    let protocol = compile(
+        params,
+        vk,
+        Config::kzg().with_num_instance(num_instance.clone()),
+    );
+    let vk = (params.get_g()[0], params.g2(), params.s_g2()).into();
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let protocol = protocol.loaded(&loader);
+    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+
+    let instances = transcript.load_instances(num_instance);
+    let proof = PlonkVerifier::read_proof(&vk, &protocol, &instances, &mut transcript)
+        .expect("verifier generation should not fail");
+    PlonkVerifier::verify(&vk, &protocol, &instances, &proof)
+        .expect("verifier generation should not fail");
+
+    loader.solidity_code()
+}
+
+/// Compiles the verifier source to deployment bytecode, needs `solc` in `PATH`.
+pub fn compile_solidity(solidity: &str) -> Vec<u8> {
+    evm::compile_solidity(solidity)
+}
+
+/// ABI-less calldata understood by the generated verifier: instances column by column, then the proof.
+pub fn encode_calldata(instances: &[Vec<Fr>], proof: &[u8]) -> Vec<u8> {
+    evm::encode_calldata(instances, proof)
+}
+
+/// Deploys the verifier in an in-process EVM and calls it, returns the gas used or the revert reason.
+pub fn evm_verify(deployment_code: Vec<u8>, calldata: Vec<u8>) -> Result<u64, String> {
+    deploy_and_call(deployment_code, calldata)
+}
+
+/// Verifier contract for thumbnails of `s_width` X `s_height`, the shape globals must already be set.
+pub fn img_solidity_verifier(s_width: u32, s_height: u32) -> String {
+    let params = KZG_PARAMETER.get_parameter();
+    let empty_circuit = MyCircuit::<Fr>::default();
+    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+
+    // one instance column per thumbnail column and channel
+    let num_instance = vec![s_height as usize; 4 * s_width as usize];
+    gen_solidity_verifier(&params, &vk, num_instance)
+}
+
+/// KZG proof of the reduction using the keccak transcript, together with its public input.
+pub fn create_img_proof_evm(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (Vec<u8>, Vec<Vec<Fr>>) {
+    let (circuit, result) = img_circuit::<Fr>(origin_buf, width, height);
+    let instances = img_public_input::<Fr>(&result);
+    let public_input = instances.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = KZG_PARAMETER.get_parameter();
+    let empty_circuit = MyCircuit::<Fr>::default();
+    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+    let pk = keygen_pk(&params, vk, &empty_circuit).expect("keygen_pk should not fail");
+
+    let mut transcript = TranscriptWriterBuffer::<_, G1Affine, _>::init(Vec::new());
+    create_proof::<
+        KZGCommitmentScheme<Bn256>,
+        ProverGWC<'_, Bn256>,
+        Challenge255<_>,
+        _,
+        EvmTranscript<_, _, _, _>,
+        _,
+    >(
+        &params,
+        &pk,
+        &[circuit],
+        &[&public_input[..]],
+        OsRng,
+        &mut transcript,
+    )
+    .expect("proof generation should not fail");
+    let proof = transcript.finalize();
+
+    // the same proof checked natively, a mismatch here means the contract would reject it too
+    let mut transcript = TranscriptReadBuffer::<_, G1Affine, _>::init(&proof[..]);
+    verify_proof::<_, VerifierGWC<'_, Bn256>, _, EvmTranscript<_, _, _, _>, _>(
+        params.verifier_params(),
+        pk.get_vk(),
+        SingleStrategy::new(&params),
+        &[&public_input[..]],
+        &mut transcript,
+    )
+    .expect("proof verification should not fail");
+
+    (proof, instances)
+}
+
+/// Calldata for a published thumbnail, so a verifier can check a proof without the original.
+pub fn img_calldata(proof: &[u8], small_buf: Vec<u8>, s_width: u32, s_height: u32) -> Vec<u8> {
+    let img: ImageBuffer<Rgba<u8>, Vec<_>> =
+        ImageBuffer::from_raw(s_width, s_height, small_buf).unwrap();
+    encode_calldata(&img_public_input::<Fr>(&img), proof)
+}
@@ -0,0 +1,106 @@
+//! Thumbnail proofs exposing a single Poseidon digest instead of every reduced pixel.
+//!
+//! The only instance is `digest::pixels_digest` of the RGBA thumbnail, so the
+//! verifier's work and the instance columns no longer grow with `s_width`.
+//! The Poseidon spec is defined over Pasta, so this mode uses the IPA backend.
+
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    halo2curves::pasta::Fp,
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance},
+};
+use image::EncodableLayout;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::{digest_rows, pixels_digest, DigestConfig},
+    helpers::ipa_parameter,
+    img_circuit, set_shape, Config, MyCircuit, Parameter, SCALE_FACTOR, S_HEIGHT, S_WIDTH,
+};
+
+// the digest needs far more rows than the pixel gate, so k follows the thumbnail size
+pub static HASH_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn hash_parameter() -> &'static Parameter {
+    HASH_PARAMETER.get_or_init(|| {
+        let pixels = *S_WIDTH.get().unwrap() * *S_HEIGHT.get().unwrap();
+        ipa_parameter(digest_rows(pixels))
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct HashedConfig {
+    image: Config,
+    digest: DigestConfig,
+    instance: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HashedCircuit(pub MyCircuit<Fp>);
+
+impl Circuit<Fp> for HashedCircuit {
+    type Config = HashedConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let image = MyCircuit::<Fp>::configure_columns(meta, false, false);
+        let digest = DigestConfig::configure(meta);
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        HashedConfig {
+            image,
+            digest,
+            instance,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self.0.assign_rows(&config.image, &mut layouter)?;
+        let pixels = cells.reduced.into_iter().flatten().collect::<Vec<_>>();
+
+        config.digest.load(&mut layouter)?;
+        let digest = config
+            .digest
+            .digest(layouter.namespace(|| "thumbnail digest"), &pixels)?;
+        layouter.constrain_instance(digest.cell(), config.instance, 0)
+    }
+}
+
+pub fn create_img_proof_hashed(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+    let (circuit, result) = img_circuit::<Fp>(origin_buf, width, height);
+    let digest = pixels_digest(result.as_bytes());
+
+    let params = hash_parameter().get_parameter();
+    let empty_circuit = HashedCircuit::default();
+    prove_ipa(
+        &params,
+        &empty_circuit,
+        HashedCircuit(circuit),
+        &[&[digest]],
+    )
+}
+
+// the thumbnail is hashed outside the circuit, only its digest reaches the verifier;
+// width and height are those of the original, which fix the shape of the circuit
+pub fn verify_img_hashed(proof: Vec<u8>, small_buf: Vec<u8>, width: u32, height: u32) -> bool {
+    let (s_width, s_height) = (width / SCALE_FACTOR, height / SCALE_FACTOR);
+    if small_buf.len() != (s_width * s_height * 4) as usize || !set_shape(width, height) {
+        return false;
+    }
+    let digest = pixels_digest(&small_buf);
+
+    let params = hash_parameter().get_parameter();
+    let empty_circuit = HashedCircuit::default();
+    verify_ipa(&params, &empty_circuit, &proof, &[&[digest]])
+}
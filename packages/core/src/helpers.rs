@@ -0,0 +1,238 @@
+//! Small pieces shared by the circuits: range check tables and witness helpers.
+
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{Layouter, Region, Value},
+    halo2curves::pasta::EqAffine,
+    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Selector, TableColumn},
+    poly::{commitment::ParamsProver, ipa::commitment::ParamsIPA, Rotation},
+};
+
+use crate::{downsample::Rounding, Parameter, K};
+
+// rows halo2 reserves for blinding factors, with some margin
+const BLINDING_ROWS: usize = 16;
+
+// 2^k 行に `rows` 行と blinding の行が収まる k、MyCircuit の K より小さくはしない
+pub(crate) fn k_for_rows(rows: usize) -> u32 {
+    let rows = rows + BLINDING_ROWS;
+    (usize::BITS - rows.leading_zeros()).max(*K.get_or_init(|| 10))
+}
+
+// IPA parameters for a circuit of `rows` rows
+pub(crate) fn ipa_parameter(rows: usize) -> Parameter {
+    let params: ParamsIPA<EqAffine> = ParamsIPA::new(k_for_rows(rows));
+    Parameter::set_parameter(params)
+}
+
+// witness `index` of `samples`, unknown past the end (`without_witnesses`)
+pub(crate) fn sample<F: FieldExt, T: Copy + Into<u64>>(
+    samples: &[Value<T>],
+    index: usize,
+) -> Value<F> {
+    samples
+        .get(index)
+        .copied()
+        .unwrap_or_else(Value::unknown)
+        .map(|v| F::from(v.into()))
+}
+
+// little endian bytes
+pub(crate) fn compose<F: FieldExt>(bytes: &[Expression<F>]) -> Expression<F> {
+    bytes
+        .iter()
+        .enumerate()
+        .fold(Expression::Constant(F::zero()), |acc, (i, byte)| {
+            acc + byte.clone() * Expression::Constant(F::from(1u64 << (8 * i)))
+        })
+}
+
+/// Lookup table of the bytes `0..256`.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteTable(TableColumn);
+
+impl ByteTable {
+    pub fn configure<F: FieldExt>(meta: &mut ConstraintSystem<F>) -> Self {
+        Self(meta.lookup_table_column())
+    }
+
+    // every cell of `columns` on a row enabled by `selector` (a complex selector) is a byte
+    pub fn range_check<F: FieldExt>(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        selector: Selector,
+        columns: &[Column<Advice>],
+    ) {
+        for &col in columns {
+            meta.lookup(name, |virtual_cells| {
+                let selector = virtual_cells.query_selector(selector);
+                let byte = virtual_cells.query_advice(col, Rotation::cur());
+                vec![(selector * byte, self.0)]
+            });
+        }
+    }
+
+    // once per circuit
+    pub fn load<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "bytes",
+            |mut table| {
+                for v in 0..256u64 {
+                    table.assign_cell(
+                        || "byte",
+                        self.0,
+                        v as usize,
+                        || Value::known(F::from(v)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Range check of the remainders in `sum + offset = divisor * quotient + remainder`
+/// for one `Rounding` and divisor (`Rounding::divide`).
+///
+/// The remainder is below `divisor`, except for a `HalfEven` tie rounded down to an
+/// even quotient where it is `divisor`. With an even divisor `HalfEven` therefore
+/// also looks up the parity of the quotient: an odd quotient needs `1 <= remainder
+/// < divisor` (a remainder of 0 is a tie rounded up to it), an even one
+/// `0 <= remainder <= divisor`. The quotient lookup checks it is a byte as well.
+#[derive(Clone, Copy, Debug)]
+pub struct RemainderTable {
+    divisor: u32,
+    ties: bool,
+    // (remainder, quotient parity) pairs allowed
+    remainder: TableColumn,
+    odd: TableColumn,
+    // (byte, byte & 1)
+    byte: TableColumn,
+    bit: TableColumn,
+}
+
+impl RemainderTable {
+    pub fn configure<F: FieldExt>(
+        meta: &mut ConstraintSystem<F>,
+        rounding: Rounding,
+        divisor: u32,
+    ) -> Self {
+        Self {
+            divisor,
+            ties: rounding == Rounding::HalfEven && divisor % 2 == 0,
+            remainder: meta.lookup_table_column(),
+            odd: meta.lookup_table_column(),
+            byte: meta.lookup_table_column(),
+            bit: meta.lookup_table_column(),
+        }
+    }
+
+    // `remainder` on rows enabled by `selector` (a complex selector) belongs to `quotient`,
+    // returns the parity column to fill with `assign_parity` if the rounding needs one
+    pub fn range_check<F: FieldExt>(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        selector: Selector,
+        quotient: Column<Advice>,
+        remainder: Column<Advice>,
+    ) -> Option<Column<Advice>> {
+        if !self.ties {
+            meta.lookup(name, |virtual_cells| {
+                let selector = virtual_cells.query_selector(selector);
+                let remainder = virtual_cells.query_advice(remainder, Rotation::cur());
+                vec![(selector * remainder, self.remainder)]
+            });
+            return None;
+        }
+
+        let parity = meta.advice_column();
+        meta.lookup(name, |virtual_cells| {
+            let selector = virtual_cells.query_selector(selector);
+            let quotient = virtual_cells.query_advice(quotient, Rotation::cur());
+            let parity = virtual_cells.query_advice(parity, Rotation::cur());
+            vec![
+                (selector.clone() * quotient, self.byte),
+                (selector * parity, self.bit),
+            ]
+        });
+        meta.lookup(name, |virtual_cells| {
+            let selector = virtual_cells.query_selector(selector);
+            let remainder = virtual_cells.query_advice(remainder, Rotation::cur());
+            let parity = virtual_cells.query_advice(parity, Rotation::cur());
+            vec![
+                (selector.clone() * remainder, self.remainder),
+                (selector * parity, self.odd),
+            ]
+        });
+        Some(parity)
+    }
+
+    pub fn assign_parity<F: FieldExt>(
+        region: &mut Region<'_, F>,
+        parity: Option<Column<Advice>>,
+        row: usize,
+        quotient: Value<u8>,
+    ) -> Result<(), Error> {
+        if let Some(parity) = parity {
+            region.assign_advice(
+                || "quotient parity",
+                parity,
+                row,
+                || quotient.map(|q| F::from((q & 1) as u64)),
+            )?;
+        }
+        Ok(())
+    }
+
+    // once per circuit
+    pub fn load<F: FieldExt>(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
+        let d = self.divisor as u64;
+        let pairs: Vec<(u64, u64)> = if self.ties {
+            (0..=d)
+                .map(|r| (r, 0))
+                .chain((1..d).map(|r| (r, 1)))
+                .collect()
+        } else {
+            (0..d).map(|r| (r, 0)).collect()
+        };
+        layouter.assign_table(
+            || "remainders",
+            |mut table| {
+                for (row, &(r, odd)) in pairs.iter().enumerate() {
+                    table.assign_cell(
+                        || "remainder",
+                        self.remainder,
+                        row,
+                        || Value::known(F::from(r)),
+                    )?;
+                    table.assign_cell(|| "odd", self.odd, row, || Value::known(F::from(odd)))?;
+                }
+                Ok(())
+            },
+        )?;
+        layouter.assign_table(
+            || "byte parities",
+            |mut table| {
+                // unused without ties, but every table column must be assigned
+                let bytes = if self.ties { 256 } else { 1 };
+                for v in 0..bytes {
+                    table.assign_cell(
+                        || "byte",
+                        self.byte,
+                        v as usize,
+                        || Value::known(F::from(v)),
+                    )?;
+                    table.assign_cell(
+                        || "bit",
+                        self.bit,
+                        v as usize,
+                        || Value::known(F::from(v & 1)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
@@ -0,0 +1,160 @@
+//! Setup files for one original shape: commitment parameters plus the
+//! verifying and proving key of `MyCircuit`, as written by `cli keygen` and
+//! read back by `cli prove --keys` / `cli verify --keys`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use halo2_proofs::{
+    halo2curves::{
+        bn256::{Fr, G1Affine},
+        pasta::{EqAffine, Fp},
+    },
+    plonk::{ProvingKey, VerifyingKey},
+    poly::{commitment::Params, ipa::commitment::ParamsIPA},
+    SerdeFormat,
+};
+
+use crate::{
+    backend::{keygen_ipa, keygen_kzg, Backend},
+    load_kzg_srs, set_shape, ImgProvingKey, ImgVerifyingKey, MyCircuit, KZG_PARAMETER, PARAMETER,
+};
+
+pub const PARAMS_FILE: &str = "params.bin";
+pub const VK_FILE: &str = "vk.bin";
+pub const PK_FILE: &str = "pk.bin";
+
+fn create(dir: &Path, name: &str) -> io::Result<BufWriter<File>> {
+    File::create(dir.join(name)).map(BufWriter::new)
+}
+
+fn open(dir: &Path, name: &str) -> io::Result<BufReader<File>> {
+    File::open(dir.join(name)).map(BufReader::new)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// 回路の形を設定する、別のサイズの鍵は使えない
+fn shape(width: u32, height: u32) -> io::Result<()> {
+    if set_shape(width, height) {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "the circuit shape is already set for another size than {} X {}",
+            width, height
+        )))
+    }
+}
+
+// params.bin がこのプロセスのパラメータと同じか確かめる
+// (IPA は k だけで決まる、KZG は ceremony の SRS として読み込む)
+fn check_params(backend: Backend, dir: &Path) -> io::Result<()> {
+    let path = dir.join(PARAMS_FILE);
+    let mut reader = open(dir, PARAMS_FILE)?;
+    match backend {
+        Backend::Ipa => {
+            let k = ParamsIPA::<EqAffine>::read(&mut reader)?.k();
+            let expected = PARAMETER.get_parameter().k();
+            if k != expected {
+                return Err(invalid(format!(
+                    "{} has k = {}, the circuit needs k = {}",
+                    path.display(),
+                    k,
+                    expected
+                )));
+            }
+        }
+        Backend::Kzg => {
+            if !load_kzg_srs(&mut reader)? {
+                return Err(invalid(format!(
+                    "{} is not from the loaded KZG SRS",
+                    path.display()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes `params.bin`, `vk.bin` and `pk.bin` for originals of `width` X `height` into `dir`,
+/// the keys are only valid for the current `rounding()`.
+pub fn write_img_keys(backend: Backend, width: u32, height: u32, dir: &Path) -> io::Result<()> {
+    shape(width, height)?;
+    fs::create_dir_all(dir)?;
+
+    let (mut params_file, mut vk_file, mut pk_file) = (
+        create(dir, PARAMS_FILE)?,
+        create(dir, VK_FILE)?,
+        create(dir, PK_FILE)?,
+    );
+    match backend {
+        Backend::Ipa => {
+            let params = PARAMETER.get_parameter();
+            let pk = keygen_ipa(&params, &MyCircuit::<Fp>::default());
+            params.write(&mut params_file)?;
+            pk.get_vk().write(&mut vk_file, SerdeFormat::RawBytes)?;
+            pk.write(&mut pk_file, SerdeFormat::RawBytes)?;
+        }
+        Backend::Kzg => {
+            let params = KZG_PARAMETER.get_parameter();
+            let pk = keygen_kzg(&params, &MyCircuit::<Fr>::default());
+            params.write(&mut params_file)?;
+            pk.get_vk().write(&mut vk_file, SerdeFormat::RawBytes)?;
+            pk.write(&mut pk_file, SerdeFormat::RawBytes)?;
+        }
+    }
+    params_file.flush()?;
+    vk_file.flush()?;
+    pk_file.flush()
+}
+
+/// Reads the proving key `write_img_keys` wrote into `dir` for originals of `width` X `height`.
+pub fn read_img_proving_key(
+    backend: Backend,
+    width: u32,
+    height: u32,
+    dir: &Path,
+) -> io::Result<ImgProvingKey> {
+    shape(width, height)?;
+    check_params(backend, dir)?;
+
+    let mut reader = open(dir, PK_FILE)?;
+    Ok(match backend {
+        Backend::Ipa => ImgProvingKey::Ipa(ProvingKey::<EqAffine>::read::<_, MyCircuit<Fp>>(
+            &mut reader,
+            SerdeFormat::RawBytes,
+        )?),
+        Backend::Kzg => ImgProvingKey::Kzg(ProvingKey::<G1Affine>::read::<_, MyCircuit<Fr>>(
+            &mut reader,
+            SerdeFormat::RawBytes,
+        )?),
+    })
+}
+
+/// Reads the verifying key `write_img_keys` wrote into `dir` for originals of `width` X `height`.
+pub fn read_img_verifying_key(
+    backend: Backend,
+    width: u32,
+    height: u32,
+    dir: &Path,
+) -> io::Result<ImgVerifyingKey> {
+    shape(width, height)?;
+    check_params(backend, dir)?;
+
+    let mut reader = open(dir, VK_FILE)?;
+    Ok(match backend {
+        Backend::Ipa => ImgVerifyingKey::Ipa(VerifyingKey::<EqAffine>::read::<_, MyCircuit<Fp>>(
+            &mut reader,
+            SerdeFormat::RawBytes,
+        )?),
+        Backend::Kzg => ImgVerifyingKey::Kzg(VerifyingKey::<G1Affine>::read::<_, MyCircuit<Fr>>(
+            &mut reader,
+            SerdeFormat::RawBytes,
+        )?),
+    })
+}
@@ -1,33 +1,78 @@
-use std::{marker::PhantomData};
+use std::{fs::File, io, marker::PhantomData, path::Path};
 
 use halo2_proofs::{
     arithmetic::FieldExt,
     circuit::SimpleFloorPlanner,
     circuit::{AssignedCell, Layouter, Value},
-    pasta::{group::ff::PrimeField, EqAffine, Fp},
+    halo2curves::{
+        bn256::{Bn256, Fr, G1Affine},
+        pasta::{EqAffine, Fp},
+    },
     plonk::*,
-    poly::{commitment::Params, Rotation},
-    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+    poly::{
+        commitment::{Params, ParamsProver},
+        ipa::commitment::ParamsIPA,
+        kzg::commitment::ParamsKZG,
+        Rotation,
+    },
 };
-use image::{EncodableLayout, ImageBuffer, Rgba};
-use rand_core::OsRng;
-pub struct Parameter {
-    parameter: Params<EqAffine>,
+use image::{ImageBuffer, Rgba};
+
+// macros, declared before the modules using them
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "aggregation")]
+pub mod aggregation;
+pub mod approx;
+pub mod backend;
+pub mod blur;
+pub mod bundle;
+pub mod channels;
+pub mod decode;
+pub mod digest;
+pub mod disclosure;
+pub mod downsample;
+#[cfg(feature = "evm")]
+pub mod evm;
+pub mod hashed;
+mod helpers;
+pub mod keys;
+pub mod merkle;
+pub mod mosaic;
+pub mod phash;
+pub mod progress;
+pub mod quantize;
+pub mod similarity;
+pub mod statistics;
+pub mod tile;
+pub mod watermark;
+pub mod ycbcr;
+
+use backend::{
+    keygen_ipa, keygen_kzg, prove_ipa_with_pk, prove_kzg_with_pk, verify_ipa, verify_ipa_batch,
+    verify_ipa_with_vk, verify_kzg, verify_kzg_batch, verify_kzg_with_vk, Backend,
+};
+use downsample::{Downsampler, Rounding};
+use helpers::RemainderTable;
+use progress::{enter, CancelToken, Cancelled, NoProgress, Phase, ProgressSink};
+
+pub struct Parameter<P = ParamsIPA<EqAffine>> {
+    parameter: P,
 }
-impl Parameter {
-    fn set_parameter(param: Params<EqAffine>) -> Self {
-        let k = *K.get().unwrap();
-        let params: Params<EqAffine> = Params::new(k);
-        Parameter { parameter: params }
+impl<P: Clone> Parameter<P> {
+    fn set_parameter(param: P) -> Self {
+        Parameter { parameter: param }
     }
 
-    fn get_parameter(&self) -> Params<EqAffine> {
+    fn get_parameter(&self) -> P {
         self.parameter.clone()
     }
 }
 
 use once_cell::sync::{Lazy, OnceCell};
 pub static PARAMETER: Lazy<Parameter> = Lazy::new(|| setup());
+pub static KZG_PARAMETER: Lazy<Parameter<ParamsKZG<Bn256>>> = Lazy::new(|| setup_kzg());
 
 pub static K: OnceCell<u32> = OnceCell::new();
 pub static WIDTH: OnceCell<usize> = OnceCell::new();
@@ -35,160 +80,100 @@ pub static HEIGHT: OnceCell<usize> = OnceCell::new();
 pub static S_WIDTH: OnceCell<usize> = OnceCell::new();
 pub static S_HEIGHT: OnceCell<usize> = OnceCell::new();
 
-pub fn setup() -> Parameter {
-    let _ =  K.set(10);
-    let k = *K.get().unwrap();
-    let params: Params<EqAffine> = Params::new(k);
-    Parameter { parameter: params }
-}
+// 縮小率 (the gate averages 2 X 2 blocks)
+pub const SCALE_FACTOR: u32 = 2;
 
-pub fn exec_mosaic(
-    buf: Vec<u8>,
-    scale_factor: u32,
-    width: u32,
-    height: u32,
-) -> Vec<u8>
-{
-    let mut img: ImageBuffer<Rgba<u8>, Vec<_>> = ImageBuffer::from_raw(width, height, buf).unwrap();
-
-    let block_size = scale_factor;
-
-    let new_width = width / block_size;
-    let new_height = height / block_size;
-
-    let mut new_img = ImageBuffer::new(new_width, new_height);
-
-    let mut red_r = Vec::new();
-    let mut green_r = Vec::new();
-    let mut blue_r = Vec::new();
-    let mut alpha_r = Vec::new();
-
-    for y in 0..new_height {
-        for x in 0..new_width {
-            let mut r_sum: u32 = 0;
-            let mut g_sum: u32 = 0;
-            let mut b_sum: u32 = 0;
-            let mut a_sum: u32 = 0;
-
-            for dy in 0..block_size {
-                for dx in 0..block_size {
-                    // 元の画像の座標
-                    // x, y は縮小後の画像(ブロック)の座標
-                    // dx, dy はブロック内の座標
-                    let pixel_x = x * block_size + dx;
-                    let pixel_y = y * block_size + dy;
-
-                    // 元の画像の座標が画像の範囲内であれば、そのピクセルの値を加算する
-                    // 例外：ブロックの端のピクセルは、ブロックの範囲外になる場合がある
-                    if pixel_x < width && pixel_y < height {
-                        let pixel = img.get_pixel(pixel_x, pixel_y);
-
-                        r_sum += pixel[0] as u32;
-                        g_sum += pixel[1] as u32;
-                        b_sum += pixel[2] as u32;
-                        a_sum += pixel[3] as u32;
-                    }
-                }
-            }
+// 平均の丸め方 (回路の形の一部、未設定なら Floor)
+pub static ROUNDING: OnceCell<Rounding> = OnceCell::new();
 
-            // ブロック内のピクセル数
-            let pixel_count = block_size * block_size;
+pub fn set_rounding(rounding: Rounding) {
+    let _ = ROUNDING.set(rounding);
+}
 
-            let r_avg = (r_sum / pixel_count) as u8;
-            let g_avg = (g_sum / pixel_count) as u8;
-            let b_avg = (b_sum / pixel_count) as u8;
-            let a_avg = (a_sum / pixel_count) as u8;
+// 回路の形は一度しか設定できない: 既に別の値が入っていれば false
+pub(crate) fn set_once<T: PartialEq>(cell: &OnceCell<T>, value: T) -> bool {
+    match cell.try_insert(value) {
+        Ok(_) => true,
+        Err((stored, value)) => *stored == value,
+    }
+}
 
-            red_r.push(r_sum % pixel_count);
-            green_r.push(g_sum % pixel_count);
-            blue_r.push(b_sum % pixel_count);
-            alpha_r.push(a_sum % pixel_count);
+pub fn rounding() -> Rounding {
+    *ROUNDING.get_or_init(Rounding::default)
+}
 
-            // ブロック内のピクセルの平均値
-            let avg_pixel = Rgba([r_avg, g_avg, b_avg, a_avg]);
+// 元の画像のサイズから回路の形を設定する (証明を作らずにkeygen / verifyする場合)
+// 別のサイズが既に設定されていれば false
+pub fn set_shape(width: u32, height: u32) -> bool {
+    set_once(&WIDTH, width as usize)
+        & set_once(&HEIGHT, height as usize)
+        & set_once(&S_WIDTH, (width / SCALE_FACTOR) as usize)
+        & set_once(&S_HEIGHT, (height / SCALE_FACTOR) as usize)
+}
 
-            new_img.put_pixel(x, y, avg_pixel);
-        }
-    }
+pub fn setup() -> Parameter {
+    let _ =  K.set(10);
+    let k = *K.get().unwrap();
+    let params: ParamsIPA<EqAffine> = ParamsIPA::new(k);
+    Parameter::set_parameter(params)
+}
 
-    new_img.as_bytes().to_vec()
+pub fn setup_kzg() -> Parameter<ParamsKZG<Bn256>> {
+    let _ = K.set(10);
+    let k = *K.get().unwrap();
+    Parameter::set_parameter(kzg_srs(k))
 }
 
-pub fn exec(
-    buf: Vec<u8>,
-    scale_factor: u32,
-    width: u32,
-    height: u32,
-) -> (
-    ImageBuffer<Rgba<u8>, Vec<u8>>,
-    Vec<u32>,
-    Vec<u32>,
-    Vec<u32>,
-    Vec<u32>,
-) {
-    let mut img: ImageBuffer<Rgba<u8>, Vec<_>> = ImageBuffer::from_raw(width, height, buf).unwrap();
-
-    let block_size = scale_factor;
-
-    let new_width = width / block_size;
-    let new_height = height / block_size;
-
-    let mut new_img = ImageBuffer::new(new_width, new_height);
-
-    let mut red_r = Vec::new();
-    let mut green_r = Vec::new();
-    let mut blue_r = Vec::new();
-    let mut alpha_r = Vec::new();
-
-    for y in 0..new_height {
-        for x in 0..new_width {
-            let mut r_sum: u32 = 0;
-            let mut g_sum: u32 = 0;
-            let mut b_sum: u32 = 0;
-            let mut a_sum: u32 = 0;
-
-            for dy in 0..block_size {
-                for dx in 0..block_size {
-                    // 元の画像の座標
-                    // x, y は縮小後の画像(ブロック)の座標
-                    // dx, dy はブロック内の座標
-                    let pixel_x = x * block_size + dx;
-                    let pixel_y = y * block_size + dy;
-
-                    // 元の画像の座標が画像の範囲内であれば、そのピクセルの値を加算する
-                    // 例外：ブロックの端のピクセルは、ブロックの範囲外になる場合がある
-                    if pixel_x < width && pixel_y < height {
-                        let pixel = img.get_pixel(pixel_x, pixel_y);
-
-                        r_sum += pixel[0] as u32;
-                        g_sum += pixel[1] as u32;
-                        b_sum += pixel[2] as u32;
-                        a_sum += pixel[3] as u32;
-                    }
-                }
-            }
+// KZG の SRS は trusted setup の ceremony から読み込む (ローカルで生成しない)
+pub static KZG_SRS: OnceCell<ParamsKZG<Bn256>> = OnceCell::new();
 
-            // ブロック内のピクセル数
-            let pixel_count = block_size * block_size;
+// SRS のファイルのパスを指定する環境変数 (`load_kzg_srs` を呼ばない場合)
+pub const KZG_SRS_ENV: &str = "KZG_SRS_PATH";
 
-            let r_avg = (r_sum / pixel_count) as u8;
-            let g_avg = (g_sum / pixel_count) as u8;
-            let b_avg = (b_sum / pixel_count) as u8;
-            let a_avg = (a_sum / pixel_count) as u8;
+/// Loads the KZG SRS of a trusted setup ceremony, in the `ParamsKZG::write` format.
+/// Every KZG proof of this process uses it, downsized to the k of its circuit.
+/// Returns false if an SRS of another ceremony (another `s_g2`) is already loaded.
+pub fn load_kzg_srs(reader: &mut impl io::Read) -> io::Result<bool> {
+    let srs = ParamsKZG::<Bn256>::read(reader)?;
+    let s_g2 = srs.s_g2();
+    Ok(KZG_SRS.get_or_init(|| srs).s_g2() == s_g2)
+}
 
-            red_r.push(r_sum % pixel_count);
-            green_r.push(g_sum % pixel_count);
-            blue_r.push(b_sum % pixel_count);
-            alpha_r.push(a_sum % pixel_count);
+pub fn load_kzg_srs_file(path: impl AsRef<Path>) -> io::Result<bool> {
+    load_kzg_srs(&mut io::BufReader::new(File::open(path)?))
+}
 
-            // ブロック内のピクセルの平均値
-            let avg_pixel = Rgba([r_avg, g_avg, b_avg, a_avg]);
+// 2^k 行の回路に使う SRS、未読み込みなら KZG_SRS_PATH から読む
+pub(crate) fn kzg_srs(k: u32) -> ParamsKZG<Bn256> {
+    let srs = KZG_SRS.get_or_init(|| {
+        let path = std::env::var_os(KZG_SRS_ENV).unwrap_or_else(|| {
+            panic!(
+                "no KZG SRS loaded: call load_kzg_srs or set {}",
+                KZG_SRS_ENV
+            )
+        });
+        let mut reader = io::BufReader::new(File::open(&path).expect("KZG SRS file"));
+        ParamsKZG::read(&mut reader).expect("KZG SRS file")
+    });
+    assert!(
+        srs.k() >= k,
+        "the KZG SRS supports 2^{} rows, the circuit needs 2^{}",
+        srs.k(),
+        k
+    );
+    let mut params = srs.clone();
+    params.downsize(k);
+    params
+}
 
-            new_img.put_pixel(x, y, avg_pixel);
-        }
-    }
+// (red, green, blue, alpha) cells of one pixel
+pub type Pixel<F> = [AssignedCell<F, F>; 4];
 
-    (new_img, red_r, green_r, blue_r, alpha_r)
+pub(crate) struct ImageCells<F: FieldExt> {
+    // 縮小画像の各行のpixel
+    pub(crate) reduced: Vec<Vec<Pixel<F>>>,
+    // 元の画像のpixel (row major)、高さが奇数の場合最後の行は含まれない
+    pub(crate) original: Vec<Pixel<F>>,
 }
 
 #[derive(Clone, Debug)]
@@ -218,6 +203,10 @@ pub struct Config {
     r_green: Vec<Column<Advice>>,
     r_blue: Vec<Column<Advice>>,
     r_alpha: Vec<Column<Advice>>,
+
+    // 縮小画像の列ごとの RGBA の偶奇 (HalfEven のときだけ)
+    parity: Vec<[Option<Column<Advice>>; 4]>,
+    remainders: RemainderTable,
 }
 
 impl Config {
@@ -240,7 +229,8 @@ impl Config {
         r_blue: Vec<Column<Advice>>,
         r_alpha: Vec<Column<Advice>>,
     ) -> Self {
-        let q_bilinear = cs.selector();
+        // lookup には complex selector が要る
+        let q_bilinear = cs.complex_selector();
 
         // ---------------------------------
         // |                |               |
@@ -259,6 +249,8 @@ impl Config {
         cs.create_gate("create small pictures", |virtual_cells| {
             // selector on かどうか
             let q_bilinear = virtual_cells.query_selector(q_bilinear);
+            // 丸め方に応じて和に足す値 (Floor なら 0)
+            let offset = Expression::Constant(F::from(rounding().offset(4) as u64));
 
             let mut bilinear = |range: usize| {
                 assert!(range > 0);
@@ -301,23 +293,43 @@ impl Config {
                             virtual_cells.query_advice(reduced_alpha[i], Rotation::cur());
                         let r_alpha = virtual_cells.query_advice(r_alpha[i], Rotation::cur());
 
-                        (red_0 + red_1 + red_2 + red_3)
-                            - (Expression::Constant(F::from(4)) * reduced_red + r_red)
-                            + (green_0 + green_1 + green_2 + green_3)
-                            - (Expression::Constant(F::from(4)) * reduced_green + r_green)
-                            + (blue_0 + blue_1 + blue_2 + blue_3)
-                            - (Expression::Constant(F::from(4)) * reduced_blue + r_blue)
-                            + (alpha_0 + alpha_1 + alpha_2 + alpha_3)
-                            - (Expression::Constant(F::from(4)) * reduced_alpha + r_alpha)
+                        // 色ごとに別の制約にする (和にすると余りを色の間で融通できる)
+                        [
+                            (red_0 + red_1 + red_2 + red_3 + offset.clone())
+                                - (Expression::Constant(F::from(4)) * reduced_red + r_red),
+                            (green_0 + green_1 + green_2 + green_3 + offset.clone())
+                                - (Expression::Constant(F::from(4)) * reduced_green + r_green),
+                            (blue_0 + blue_1 + blue_2 + blue_3 + offset.clone())
+                                - (Expression::Constant(F::from(4)) * reduced_blue + r_blue),
+                            (alpha_0 + alpha_1 + alpha_2 + alpha_3 + offset.clone())
+                                - (Expression::Constant(F::from(4)) * reduced_alpha + r_alpha),
+                        ]
                     })
+                    .flatten()
                     .collect::<Vec<_>>()
             };
 
-            println!("configure is done");
+            debug_event!("configure is done");
 
             Constraints::with_selector(q_bilinear, bilinear(*S_WIDTH.get().unwrap()))
         });
 
+        // 余りは丸め方の許す範囲 (HalfEven では縮小後の値の偶奇も見る)
+        let remainders = RemainderTable::configure(cs, rounding(), 4);
+        let parity = (0..*S_WIDTH.get().unwrap())
+            .map(|i| {
+                [
+                    (&reduced_red, &r_red),
+                    (&reduced_green, &r_green),
+                    (&reduced_blue, &r_blue),
+                    (&reduced_alpha, &r_alpha),
+                ]
+                .map(|(reduced, r)| {
+                    remainders.range_check(cs, "remainder", q_bilinear, reduced[i], r[i])
+                })
+            })
+            .collect();
+
         Self {
             red,
             green,
@@ -336,6 +348,8 @@ impl Config {
             r_green,
             r_blue,
             r_alpha,
+            parity,
+            remainders,
         }
     }
 
@@ -361,6 +375,8 @@ impl Config {
             Vec<AssignedCell<F, F>>,
             Vec<AssignedCell<F, F>>,
             Vec<AssignedCell<F, F>>,
+            Vec<Pixel<F>>,
+            Vec<Pixel<F>>,
         ),
         Error,
     > {
@@ -369,13 +385,29 @@ impl Config {
             |mut region| {
                 let mut s_width = 0;
 
+                let mut top = Vec::new();
+                let mut bottom = Vec::new();
+
                 let mut acc_red = Vec::new();
                 let mut acc_green = Vec::new();
                 let mut acc_blue = Vec::new();
                 let mut acc_alpha = Vec::new();
 
-                for i in (0..*WIDTH.get().unwrap()).step_by(2) {
+                // 幅が奇数の場合、最後の列は縮小画像に含まれない (Downsampler と同じ)
+                for i in (0..2 * *S_WIDTH.get().unwrap()).step_by(2) {
                     self.q_bilinear.enable(&mut region, 0)?;
+                    for (c, reduced) in
+                        [&reduced_red, &reduced_green, &reduced_blue, &reduced_alpha]
+                            .into_iter()
+                            .enumerate()
+                    {
+                        RemainderTable::assign_parity(
+                            &mut region,
+                            self.parity[s_width][c],
+                            0,
+                            reduced[s_width],
+                        )?;
+                    }
 
                     let red = red
                         .iter()
@@ -430,96 +462,102 @@ impl Config {
                         .map(|&v| v.map(|v| F::from(v as u64)))
                         .collect::<Vec<Value<F>>>();
 
-                    region.assign_advice(|| "red", self.red[i], 0, || *red.get(i).unwrap())?;
-                    region.assign_advice(
+                    let red_0 = region.assign_advice(|| "red", self.red[i], 0, || *red.get(i).unwrap())?;
+                    let red_1 = region.assign_advice(
                         || "red",
                         self.red[i + 1],
                         0,
                         || *red.get(i + 1).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let red_2 = region.assign_advice(
                         || "red",
                         self.red[i],
                         1,
                         || *red.get(i + *WIDTH.get().unwrap()).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let red_3 = region.assign_advice(
                         || "red",
                         self.red[i + 1],
                         1,
                         || *red.get(i + *WIDTH.get().unwrap() + 1).unwrap(),
                     )?;
 
-                    region.assign_advice(
+                    let green_0 = region.assign_advice(
                         || "green",
                         self.green[i],
                         0,
                         || *green.get(i).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let green_1 = region.assign_advice(
                         || "green",
                         self.green[i + 1],
                         0,
                         || *green.get(i + 1).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let green_2 = region.assign_advice(
                         || "green",
                         self.green[i],
                         1,
                         || *green.get(i + *WIDTH.get().unwrap()).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let green_3 = region.assign_advice(
                         || "green",
                         self.green[i + 1],
                         1,
                         || *green.get(i + *WIDTH.get().unwrap() + 1).unwrap(),
                     )?;
 
-                    region.assign_advice(|| "blue", self.blue[i], 0, || *blue.get(i).unwrap())?;
-                    region.assign_advice(
+                    let blue_0 = region.assign_advice(|| "blue", self.blue[i], 0, || *blue.get(i).unwrap())?;
+                    let blue_1 = region.assign_advice(
                         || "blue",
                         self.blue[i + 1],
                         0,
                         || *blue.get(i + 1).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let blue_2 = region.assign_advice(
                         || "blue",
                         self.blue[i],
                         1,
                         || *blue.get(i + *WIDTH.get().unwrap()).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let blue_3 = region.assign_advice(
                         || "blue",
                         self.blue[i + 1],
                         1,
                         || *blue.get(i + *WIDTH.get().unwrap() + 1).unwrap(),
                     )?;
 
-                    region.assign_advice(
+                    let alpha_0 = region.assign_advice(
                         || "alpha",
                         self.alpha[i],
                         0,
                         || *alpha.get(i).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let alpha_1 = region.assign_advice(
                         || "alpha",
                         self.alpha[i + 1],
                         0,
                         || *alpha.get(i + 1).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let alpha_2 = region.assign_advice(
                         || "alpha",
                         self.alpha[i],
                         1,
                         || *alpha.get(i + *WIDTH.get().unwrap()).unwrap(),
                     )?;
-                    region.assign_advice(
+                    let alpha_3 = region.assign_advice(
                         || "alpha",
                         self.alpha[i + 1],
                         1,
                         || *alpha.get(i + *WIDTH.get().unwrap() + 1).unwrap(),
                     )?;
 
+                    // 元の画像のpixel (2 X 2) 、上の行と下の行
+                    top.push([red_0, green_0, blue_0, alpha_0]);
+                    top.push([red_1, green_1, blue_1, alpha_1]);
+                    bottom.push([red_2, green_2, blue_2, alpha_2]);
+                    bottom.push([red_3, green_3, blue_3, alpha_3]);
+
                     region.assign_advice(
                         || "red remainder",
                         self.r_red[s_width],
@@ -577,12 +615,52 @@ impl Config {
 
                     s_width += 1;
                 }
-                println!("assign is done");
-                Ok((acc_red, acc_green, acc_blue, acc_alpha))
+
+                // 最後の列も元の画像のpixelには含める (行のdigestは行全体)
+                let width = *WIDTH.get().unwrap();
+                if width % 2 == 1 {
+                    let last = width - 1;
+                    for (row, pixels) in [(0, &mut top), (1, &mut bottom)] {
+                        let mut pixel = Vec::with_capacity(4);
+                        for (name, columns, values) in [
+                            ("red", &self.red, &red),
+                            ("green", &self.green, &green),
+                            ("blue", &self.blue, &blue),
+                            ("alpha", &self.alpha, &alpha),
+                        ] {
+                            pixel.push(region.assign_advice(
+                                || name,
+                                columns[last],
+                                row,
+                                || values[row * width + last].map(|v| F::from(v as u64)),
+                            )?);
+                        }
+                        pixels.push(<[_; 4]>::try_from(pixel).unwrap());
+                    }
+                }
+                trace_event!(s_width, "assign is done");
+                Ok((acc_red, acc_green, acc_blue, acc_alpha, top, bottom))
             },
         )
     }
 
+    // 縮小画像の行 = instanceの行, 縮小画像の列 = instance column
+    pub(crate) fn expose_reduced<F: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        rows: &[Vec<Pixel<F>>],
+    ) -> Result<(), Error> {
+        for (row, pixels) in rows.iter().enumerate() {
+            for (column, pixel) in pixels.iter().enumerate() {
+                layouter.constrain_instance(pixel[0].cell(), self.instance_red[column], row)?;
+                layouter.constrain_instance(pixel[1].cell(), self.instance_green[column], row)?;
+                layouter.constrain_instance(pixel[2].cell(), self.instance_blue[column], row)?;
+                layouter.constrain_instance(pixel[3].cell(), self.instance_alpha[column], row)?;
+            }
+        }
+        Ok(())
+    }
+
     // fn expose_public<F: FieldExt> (
     //     &self,
     //     mut layouter: impl Layouter<F>,
@@ -642,18 +720,17 @@ impl<F: FieldExt> Default for MyCircuit<F> {
     }
 }
 
-impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
-    type Config = Config;
-
-    type FloorPlanner = SimpleFloorPlanner;
-
-    fn without_witnesses(&self) -> Self {
-        Self::default()
-    }
-
-    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+impl<F: FieldExt> MyCircuit<F> {
+    // instance columnは縮小画像のpixelをそのまま公開する場合 (expose_pixels) だけ作る
+    // 元の画像のcellを他のregionにcopyする場合 (copy_original) はequalityを有効にする
+    pub(crate) fn configure_columns(
+        meta: &mut ConstraintSystem<F>,
+        expose_pixels: bool,
+        copy_original: bool,
+    ) -> Config {
         let width = *WIDTH.get().unwrap();
         let s_width = *S_WIDTH.get().unwrap();
+        let n_instance = if expose_pixels { s_width } else { 0 };
         // columnの設定
         let red = (0..width)
             .map(|_| meta.advice_column())
@@ -667,6 +744,13 @@ impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
         let alpha = (0..width)
             .map(|_| meta.advice_column())
             .collect::<Vec<_>>();
+        if copy_original {
+            red.iter()
+                .chain(green.iter())
+                .chain(blue.iter())
+                .chain(alpha.iter())
+                .for_each(|&col| meta.enable_equality(col));
+        }
 
         // columnの設定 & equalityの設定
         let reduced_red = (0..s_width)
@@ -694,25 +778,25 @@ impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
             .iter()
             .for_each(|&col| meta.enable_equality(col));
 
-        let instance_red = (0..s_width)
+        let instance_red = (0..n_instance)
             .map(|_| meta.instance_column())
             .collect::<Vec<_>>();
         instance_red
             .iter()
             .for_each(|&col| meta.enable_equality(col));
-        let instance_green = (0..s_width)
+        let instance_green = (0..n_instance)
             .map(|_| meta.instance_column())
             .collect::<Vec<_>>();
         instance_green
             .iter()
             .for_each(|&col| meta.enable_equality(col));
-        let instance_blue = (0..s_width)
+        let instance_blue = (0..n_instance)
             .map(|_| meta.instance_column())
             .collect::<Vec<_>>();
         instance_blue
             .iter()
             .for_each(|&col| meta.enable_equality(col));
-        let instance_alpha = (0..s_width)
+        let instance_alpha = (0..n_instance)
             .map(|_| meta.instance_column())
             .collect::<Vec<_>>();
         instance_alpha
@@ -732,7 +816,7 @@ impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
             .map(|_| meta.advice_column())
             .collect::<Vec<_>>();
 
-        Self::Config::configure(
+        Config::configure(
             meta,
             red.try_into().unwrap(),
             green.try_into().unwrap(),
@@ -753,147 +837,137 @@ impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
         )
     }
 
-    fn synthesize(
+    // assignの呼び出し
+    // pixelを２行ずつ渡し、縮小画像と元の画像のpixelのcellを受け取る
+    pub(crate) fn assign_rows(
         &self,
-        config: Self::Config,
-        mut layouter: impl Layouter<F>,
-    ) -> Result<(), Error> {
+        config: &Config,
+        layouter: &mut impl Layouter<F>,
+    ) -> Result<ImageCells<F>, Error> {
         let width = *WIDTH.get().unwrap();
+        let height = *HEIGHT.get().unwrap();
         let s_width = *S_WIDTH.get().unwrap();
-        // assignの呼び出し
-        // pixelを２行ずつ渡し、1 X S_WIDTHのpixelを受け取る
+        config.remainders.load(layouter)?;
+
+        let mut rows = Vec::new();
+        let mut original = Vec::new();
         let mut index = 0;
         let mut s_index = 0;
-        for i in (0..*HEIGHT.get().unwrap()).step_by(2) {
-            if *HEIGHT.get().unwrap() % 2 == 0 {
-                // let (s_red,s_green, s_blue, s_alpha)
-                // = config.assign(layouter.namespace(|| "next row (even)"), [self.red[i], self.red[i + 1]], [self.green[i], self.green[i + 1]], [self.blue[i], self.blue[i + 1]],[self.alpha[i], self.alpha[i + 1]], self.reduced_red[i / 2], self.reduced_green[i / 2], self.reduced_blue[i / 2], self.reduced_alpha[i / 2], self.r_red[i / 2], self.r_green[i / 2], self.r_blue[i / 2], self.r_alpha[i / 2], self.value)?;
-
-                let (s_red, s_green, s_blue, s_alpha) = config.assign(
-                    layouter.namespace(|| "next row (even)"),
-                    self.red[index..(index + 2 * width)].to_vec(),
-                    self.green[index..(index + 2 * width)].to_vec(),
-                    self.blue[index..(index + 2 * width)].to_vec(),
-                    self.alpha[index..(index + 2 * width)].to_vec(),
-                    self.reduced_red[s_index..(s_index + s_width)].to_vec(),
-                    self.reduced_green[s_index..(s_index + s_width)].to_vec(),
-                    self.reduced_blue[s_index..(s_index + s_width)].to_vec(),
-                    self.reduced_alpha[s_index..(s_index + s_width)].to_vec(),
-                    self.r_red[s_index..(s_index + s_width)].to_vec(),
-                    self.r_green[s_index..(s_index + s_width)].to_vec(),
-                    self.r_blue[s_index..(s_index + s_width)].to_vec(),
-                    self.r_alpha[s_index..(s_index + s_width)].to_vec(),
-                )?;
-
-                for column in 0..*S_WIDTH.get().unwrap() {
-                    layouter.constrain_instance(
-                        s_red[column].cell(),
-                        config.instance_red[column],
-                        i / 2,
-                    )?;
-                    layouter.constrain_instance(
-                        s_green[column].cell(),
-                        config.instance_green[column],
-                        i / 2,
-                    )?;
-                    layouter.constrain_instance(
-                        s_blue[column].cell(),
-                        config.instance_blue[column],
-                        i / 2,
-                    )?;
-                    layouter.constrain_instance(
-                        s_alpha[column].cell(),
-                        config.instance_alpha[column],
-                        i / 2,
-                    )?;
-                }
-            } else {
-                if i == (*HEIGHT.get().unwrap() - 1) {
-                    continue;
-                } else {
-                    let (s_red, s_green, s_blue, s_alpha) = config.assign(
-                        layouter.namespace(|| "next row (even)"),
-                        self.red[index..(index + 2 * width)].to_vec(),
-                        self.green[index..(index + 2 * width)].to_vec(),
-                        self.blue[index..(index + 2 * width)].to_vec(),
-                        self.alpha[index..(index + 2 * width)].to_vec(),
-                        self.reduced_red[s_index..(s_index + s_width)].to_vec(),
-                        self.reduced_green[s_index..(s_index + s_width)].to_vec(),
-                        self.reduced_blue[s_index..(s_index + s_width)].to_vec(),
-                        self.reduced_alpha[s_index..(s_index + s_width)].to_vec(),
-                        self.r_red[s_index..(s_index + s_width)].to_vec(),
-                        self.r_green[s_index..(s_index + s_width)].to_vec(),
-                        self.r_blue[s_index..(s_index + s_width)].to_vec(),
-                        self.r_alpha[s_index..(s_index + s_width)].to_vec(),
-                    )?;
-
-                    for column in 0..s_width {
-                        layouter.constrain_instance(
-                            s_red[column].cell(),
-                            config.instance_red[column],
-                            i / 2,
-                        )?;
-                        layouter.constrain_instance(
-                            s_green[column].cell(),
-                            config.instance_green[column],
-                            i / 2,
-                        )?;
-                        layouter.constrain_instance(
-                            s_blue[column].cell(),
-                            config.instance_blue[column],
-                            i / 2,
-                        )?;
-                        layouter.constrain_instance(
-                            s_alpha[column].cell(),
-                            config.instance_alpha[column],
-                            i / 2,
-                        )?;
-                    }
-                }
+        for i in (0..height).step_by(2) {
+            // 高さが奇数の場合、最後の行は縮小画像に含まれない
+            if i + 1 == height {
+                continue;
             }
+            let (s_red, s_green, s_blue, s_alpha, top, bottom) = config.assign(
+                layouter.namespace(|| "next row"),
+                self.red[index..(index + 2 * width)].to_vec(),
+                self.green[index..(index + 2 * width)].to_vec(),
+                self.blue[index..(index + 2 * width)].to_vec(),
+                self.alpha[index..(index + 2 * width)].to_vec(),
+                self.reduced_red[s_index..(s_index + s_width)].to_vec(),
+                self.reduced_green[s_index..(s_index + s_width)].to_vec(),
+                self.reduced_blue[s_index..(s_index + s_width)].to_vec(),
+                self.reduced_alpha[s_index..(s_index + s_width)].to_vec(),
+                self.r_red[s_index..(s_index + s_width)].to_vec(),
+                self.r_green[s_index..(s_index + s_width)].to_vec(),
+                self.r_blue[s_index..(s_index + s_width)].to_vec(),
+                self.r_alpha[s_index..(s_index + s_width)].to_vec(),
+            )?;
+
+            rows.push(
+                s_red
+                    .into_iter()
+                    .zip(s_green)
+                    .zip(s_blue)
+                    .zip(s_alpha)
+                    .map(|(((red, green), blue), alpha)| [red, green, blue, alpha])
+                    .collect(),
+            );
+            original.extend(top);
+            original.extend(bottom);
+
             index += 2 * width;
             s_index += s_width;
         }
-        println!("synthesize now");
-        Ok(())
+        Ok(ImageCells {
+            reduced: rows,
+            original,
+        })
     }
 }
 
-pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+impl<F: FieldExt> Circuit<F> for MyCircuit<F> {
+    type Config = Config;
+
+    type FloorPlanner = SimpleFloorPlanner;
 
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        Self::configure_columns(meta, true, false)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let cells = self.assign_rows(&config, &mut layouter)?;
+        config.expose_reduced(&mut layouter, &cells.reduced)?;
+        debug_event!("synthesize is done");
+        Ok(())
+    }
+}
+
+// 元の画像から縮小画像とwitnessを計算し、circuitと縮小画像を返す
+pub fn img_circuit<F: FieldExt>(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (MyCircuit<F>, ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    enter_span!("witness");
     let mut img: ImageBuffer<Rgba<u8>, Vec<_>> =
         ImageBuffer::from_raw(width, height, origin_buf.clone()).unwrap();
     let width = img.width();
     let height = img.height();
-    let scale_factor = 2; // 縮小率
 
-    let (result, r_red, r_green, r_blue, r_alpha) = exec(origin_buf, scale_factor, width, height);
-    let r_red = r_red
+    let reduced = Downsampler::default().downsample_rgba(&origin_buf, width, height);
+    let r_red = reduced
+        .remainders(0)
         .iter()
         .map(|&v| Value::known(v as u8))
         .collect::<Vec<Value<u8>>>();
-    let r_green = r_green
+    let r_green = reduced
+        .remainders(1)
         .iter()
         .map(|&v| Value::known(v as u8))
         .collect::<Vec<Value<u8>>>();
-    let r_blue = r_blue
+    let r_blue = reduced
+        .remainders(2)
         .iter()
         .map(|&v| Value::known(v as u8))
         .collect::<Vec<Value<u8>>>();
-    let r_alpha = r_alpha
+    let r_alpha = reduced
+        .remainders(3)
         .iter()
         .map(|&v| Value::known(v as u8))
         .collect::<Vec<Value<u8>>>();
 
+    let result = reduced.to_rgba_image();
+
     let s_width = result.width();
     let s_height = result.height();
 
-    let _ = WIDTH.set(width as usize);
-    let _ = HEIGHT.set(height as usize);
-    let _ = S_WIDTH.set(s_width as usize);
-    let _ = S_HEIGHT.set(s_height as usize);
-    let param = &*PARAMETER;
-    let params = param.get_parameter();
+    assert!(
+        set_shape(width, height),
+        "the circuit shape is already set for another image size"
+    );
+    debug_assert_eq!(
+        (s_width, s_height),
+        (width / SCALE_FACTOR, height / SCALE_FACTOR)
+    );
 
     let mut red = Vec::new();
     let mut blue = Vec::new();
@@ -905,7 +979,7 @@ pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8>
     let mut reduced_green = Vec::new();
     let mut reduced_alpha = Vec::new();
 
-    println!("start");
+    debug_event!(width, height, "start");
 
     for i in 0..height as u32 {
         for j in 0..width as u32 {
@@ -918,7 +992,7 @@ pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8>
         }
     }
 
-    println!("got pixels");
+    debug_event!("got pixels");
 
     for i in 0..s_height {
         for j in 0..s_width {
@@ -929,11 +1003,9 @@ pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8>
             reduced_alpha.push(Value::known(pixel[3]));
         }
     }
-    let empty_circuit = MyCircuit::<Fp>::default();
-    // println!("empty circuit{:?}", empty_circuit);
 
-    println!("red: {:?}", red.len());
-    let circuit = MyCircuit::<Fp> {
+    debug_event!(pixels = red.len(), s_width, s_height, "reduced");
+    let circuit = MyCircuit::<F> {
         red,
         green,
         blue,
@@ -949,6 +1021,14 @@ pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8>
         _marker: PhantomData,
     };
 
+    (circuit, result)
+}
+
+// 縮小画像のpixelを instance column (縮小画像の列 X RGBA) の並びに変換する
+pub fn img_public_input<F: FieldExt>(img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<Vec<F>> {
+    let s_width = img.width();
+    let s_height = img.height();
+
     let mut instance_red = Vec::new();
     let mut instance_blue = Vec::new();
     let mut instance_green = Vec::new();
@@ -956,128 +1036,308 @@ pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8>
 
     for i in 0..s_width {
         for j in 0..s_height {
-            let pixel = result.get_pixel(i, j);
-            instance_red.push(Fp::from(pixel[0] as u64));
-            instance_green.push(Fp::from(pixel[1] as u64));
-            instance_blue.push(Fp::from(pixel[2] as u64));
-            instance_alpha.push(Fp::from(pixel[3] as u64));
+            let pixel = img.get_pixel(i, j);
+            instance_red.push(F::from(pixel[0] as u64));
+            instance_green.push(F::from(pixel[1] as u64));
+            instance_blue.push(F::from(pixel[2] as u64));
+            instance_alpha.push(F::from(pixel[3] as u64));
         }
     }
 
-    // let instance = Fp::from(55);
-    // let mut public_input = vec![instance];
     let mut public_input = Vec::new();
-
-    let mut index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_red[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
+    for instance in [instance_red, instance_green, instance_blue, instance_alpha] {
+        public_input.extend(instance.chunks(s_height as usize).map(|column| column.to_vec()));
     }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_green[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
+    public_input
+}
+
+// RGBA8 を scale_factor X scale_factor のブロックの平均に縮小する (Downsampler と同じ、現在の丸め方)
+pub fn exec_mosaic(buf: Vec<u8>, scale_factor: u32, width: u32, height: u32) -> Vec<u8> {
+    Downsampler::new(scale_factor)
+        .with_rounding(rounding())
+        .downsample_rgba(&buf, width, height)
+        .pixels
+}
+
+pub fn create_img_proof(origin_buf: Vec<u8>, width: u32, height: u32) -> Vec<u8> {
+    create_img_proof_with(Backend::Ipa, origin_buf, width, height)
+}
+
+pub fn create_img_proof_with(
+    backend: Backend,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    create_img_proof_with_progress(
+        backend,
+        origin_buf,
+        width,
+        height,
+        &NoProgress,
+        &CancelToken::new(),
+    )
+    .expect("a fresh token is never cancelled")
+}
+
+/// Same as `create_img_proof_with` on a pool of `threads` workers (0: one per core).
+/// A single circuit has no independent parts, but halo2 runs its FFTs and MSMs on the
+/// rayon pool, so `threads` bounds and parallelizes the whole proof; larger originals
+/// are split into tiles proved concurrently by `tile::create_tiled_proof_with_threads`.
+#[cfg(feature = "parallel")]
+pub fn create_img_proof_with_threads(
+    backend: Backend,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    threads: usize,
+) -> Vec<u8> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("thread pool should build");
+    pool.install(|| create_img_proof_with(backend, origin_buf, width, height))
+}
+
+// 同じサイズの画像をまとめて証明する場合に使い回す proving key
+pub enum ImgProvingKey {
+    Ipa(ProvingKey<EqAffine>),
+    Kzg(ProvingKey<G1Affine>),
+}
+
+impl ImgProvingKey {
+    pub fn backend(&self) -> Backend {
+        match self {
+            ImgProvingKey::Ipa(_) => Backend::Ipa,
+            ImgProvingKey::Kzg(_) => Backend::Kzg,
+        }
     }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_blue[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
+}
+
+// 回路の形は一度しか設定できないので、1つのプロセスで使えるのは1つのサイズだけ
+pub fn img_proving_key(backend: Backend, width: u32, height: u32) -> ImgProvingKey {
+    assert!(
+        set_shape(width, height),
+        "the circuit shape is already set for another image size"
+    );
+    match backend {
+        Backend::Ipa => ImgProvingKey::Ipa(keygen_ipa(
+            &PARAMETER.get_parameter(),
+            &MyCircuit::<Fp>::default(),
+        )),
+        Backend::Kzg => ImgProvingKey::Kzg(keygen_kzg(
+            &KZG_PARAMETER.get_parameter(),
+            &MyCircuit::<Fr>::default(),
+        )),
     }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_alpha[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
+}
+
+pub fn create_img_proof_with_pk(
+    pk: &ImgProvingKey,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    match pk {
+        ImgProvingKey::Ipa(pk) => {
+            let (circuit, result) = img_circuit::<Fp>(origin_buf, width, height);
+            let public_input = img_public_input::<Fp>(&result);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            prove_ipa_with_pk(&PARAMETER.get_parameter(), pk, circuit, &public_input)
+        }
+        ImgProvingKey::Kzg(pk) => {
+            let (circuit, result) = img_circuit::<Fr>(origin_buf, width, height);
+            let public_input = img_public_input::<Fr>(&result);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            prove_kzg_with_pk(&KZG_PARAMETER.get_parameter(), pk, circuit, &public_input)
+        }
     }
+}
 
-    // let params_fs = File::open("params.bin").unwrap();
-    // let params = Params::<EqAffine>::read(&mut BufReader::new(params_fs)).unwrap();
-    // let params = Params::<EqAffine>::read(&mut BufReader::new(&params_vec[..])).unwrap();
-    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
-    let pk = keygen_pk(&params, vk.clone(), &empty_circuit).expect("keygen_pk should not fail");
-    println!("Successfully generated proving key");
-
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    // Create a proof
-    halo2_proofs::plonk::create_proof(
-        &params,
-        &pk,
-        &[circuit.clone()],
-        &[&public_input[..]],
-        OsRng,
-        &mut transcript,
-    )
-    .expect("proof generation should not fail");
-    let proof: Vec<u8> = transcript.finalize();
-    proof
-    
+// 各フェーズの前に進捗を通知し、キャンセルされていれば中断する
+pub fn create_img_proof_with_progress(
+    backend: Backend,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    progress: &impl ProgressSink,
+    cancel: &CancelToken,
+) -> Result<Vec<u8>, Cancelled> {
+    let proof = match backend {
+        Backend::Ipa => {
+            enter(progress, cancel, Phase::Witness)?;
+            let (circuit, result) = img_circuit::<Fp>(origin_buf, width, height);
+            let public_input = img_public_input::<Fp>(&result);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+            enter(progress, cancel, Phase::Keygen)?;
+            let params = PARAMETER.get_parameter();
+            let pk = keygen_ipa(&params, &MyCircuit::<Fp>::default());
+
+            enter(progress, cancel, Phase::Proving)?;
+            prove_ipa_with_pk(&params, &pk, circuit, &public_input)
+        }
+        Backend::Kzg => {
+            enter(progress, cancel, Phase::Witness)?;
+            let (circuit, result) = img_circuit::<Fr>(origin_buf, width, height);
+            let public_input = img_public_input::<Fr>(&result);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+            enter(progress, cancel, Phase::Keygen)?;
+            let params = KZG_PARAMETER.get_parameter();
+            let pk = keygen_kzg(&params, &MyCircuit::<Fr>::default());
+
+            enter(progress, cancel, Phase::Proving)?;
+            prove_kzg_with_pk(&params, &pk, circuit, &public_input)
+        }
+    };
+    progress.phase(Phase::Done);
+    Ok(proof)
 }
 
 pub fn verify_img(proof: Vec<u8>, small_buf: Vec<u8>, s_width: u32, s_height: u32) -> bool {
+    verify_img_with(Backend::Ipa, proof, small_buf, s_width, s_height)
+}
 
+// 縮小画像の長さや回路の形が合わなければ証明以前に不正
+fn published_thumbnail(
+    small_buf: Vec<u8>,
+    s_width: u32,
+    s_height: u32,
+) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(s_width, s_height, small_buf)?;
+    if img.len() != s_width as usize * s_height as usize * 4 {
+        return None;
+    }
+    if !is_thumbnail_shape(s_width, s_height) {
+        return None;
+    }
+    Some(img)
+}
 
-    let mut img: ImageBuffer<Rgba<u8>, Vec<_>> =
-        ImageBuffer::from_raw(s_width, s_height, small_buf).unwrap();
+// 回路の形が設定されていない、または別の縮小画像の形なら空の回路 (MyCircuit::default) を作れない
+fn is_thumbnail_shape(s_width: u32, s_height: u32) -> bool {
+    WIDTH.get().is_some()
+        && HEIGHT.get().is_some()
+        && S_WIDTH.get() == Some(&(s_width as usize))
+        && S_HEIGHT.get() == Some(&(s_height as usize))
+}
 
-    let empty_circuit = MyCircuit::<Fp>::default();
+pub fn verify_img_with(
+    backend: Backend,
+    proof: Vec<u8>,
+    small_buf: Vec<u8>,
+    s_width: u32,
+    s_height: u32,
+) -> bool {
+    let img = match published_thumbnail(small_buf, s_width, s_height) {
+        Some(img) => img,
+        None => return false,
+    };
 
-    let mut public_input = Vec::new();
+    match backend {
+        Backend::Ipa => {
+            let public_input = img_public_input::<Fp>(&img);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
 
-    let mut instance_red = Vec::new();
-    let mut instance_blue = Vec::new();
-    let mut instance_green = Vec::new();
-    let mut instance_alpha = Vec::new();
+            let params = PARAMETER.get_parameter();
+            let empty_circuit = MyCircuit::<Fp>::default();
+            verify_ipa(&params, &empty_circuit, &proof, &public_input)
+        }
+        Backend::Kzg => {
+            let public_input = img_public_input::<Fr>(&img);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
 
-    for i in 0..s_width {
-        for j in 0..s_height {
-            let pixel = img.get_pixel(i, j);
-            instance_red.push(Fp::from(pixel[0] as u64));
-            instance_green.push(Fp::from(pixel[1] as u64));
-            instance_blue.push(Fp::from(pixel[2] as u64));
-            instance_alpha.push(Fp::from(pixel[3] as u64));
+            let params = KZG_PARAMETER.get_parameter();
+            let empty_circuit = MyCircuit::<Fr>::default();
+            verify_kzg(&params, &empty_circuit, &proof, &public_input)
         }
     }
+}
 
-    let mut index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_red[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
-    }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_green[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
-    }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_blue[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
-    }
-    index = 0;
-    for _ in 0..s_width {
-        public_input.push(&instance_alpha[index..(index + *S_HEIGHT.get().unwrap())]);
-        index += *S_HEIGHT.get().unwrap();
-    }
+// 保存しておいた verifying key (`keys::read_img_verifying_key`)
+pub enum ImgVerifyingKey {
+    Ipa(VerifyingKey<EqAffine>),
+    Kzg(VerifyingKey<G1Affine>),
+}
 
-    // let params_fs = File::open("params.bin").unwrap();
-    // let params = Params::<EqAffine>::read(&mut BufReader::new(params_fs)).unwrap();
-    // let params = Params::<EqAffine>::read(&mut BufReader::new(&params_vec[..])).unwrap();
-    let param = &*PARAMETER;
-    let params = param.get_parameter();
+/// Same as `verify_img_with` with a verifying key read once instead of regenerated.
+pub fn verify_img_with_vk(
+    vk: &ImgVerifyingKey,
+    proof: Vec<u8>,
+    small_buf: Vec<u8>,
+    s_width: u32,
+    s_height: u32,
+) -> bool {
+    let img = match published_thumbnail(small_buf, s_width, s_height) {
+        Some(img) => img,
+        None => return false,
+    };
 
-    // let proof = proof_js.into_serde::<Vec<u8>>().unwrap();
+    match vk {
+        ImgVerifyingKey::Ipa(vk) => {
+            let public_input = img_public_input::<Fp>(&img);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            verify_ipa_with_vk(&PARAMETER.get_parameter(), vk, &proof, &public_input)
+        }
+        ImgVerifyingKey::Kzg(vk) => {
+            let public_input = img_public_input::<Fr>(&img);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            verify_kzg_with_vk(&KZG_PARAMETER.get_parameter(), vk, &proof, &public_input)
+        }
+    }
+}
 
-    let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+// (proof, 縮小画像) のペアをまとめて検証する、縮小画像は全て s_width X s_height
+pub fn verify_imgs(proofs: &[(Vec<u8>, Vec<u8>)], s_width: u32, s_height: u32) -> bool {
+    verify_imgs_with(Backend::Ipa, proofs, s_width, s_height)
+}
 
-    // Check that a hardcoded proof is satisfied
-    let strategy = SingleVerifier::new(&params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+pub fn verify_imgs_with(
+    backend: Backend,
+    proofs: &[(Vec<u8>, Vec<u8>)],
+    s_width: u32,
+    s_height: u32,
+) -> bool {
+    if !is_thumbnail_shape(s_width, s_height) {
+        return false;
+    }
+    let mut imgs = Vec::new();
+    for (_, small_buf) in proofs {
+        match published_thumbnail(small_buf.clone(), s_width, s_height) {
+            Some(img) => imgs.push(img),
+            None => return false,
+        }
+    }
 
-    verify_proof(
-        &params,
-        &vk,
-        strategy,
-        &[&public_input[..]],
-        &mut transcript,
-    )
-    .is_ok()
+    match backend {
+        Backend::Ipa => {
+            let public_inputs = imgs.iter().map(img_public_input::<Fp>).collect::<Vec<_>>();
+            let batch = proofs
+                .iter()
+                .zip(public_inputs.iter())
+                .map(|((proof, _), public_input)| {
+                    (&proof[..], public_input.iter().map(|c| &c[..]).collect())
+                })
+                .collect::<Vec<_>>();
+
+            let params = PARAMETER.get_parameter();
+            let empty_circuit = MyCircuit::<Fp>::default();
+            verify_ipa_batch(&params, &empty_circuit, &batch)
+        }
+        Backend::Kzg => {
+            let public_inputs = imgs.iter().map(img_public_input::<Fr>).collect::<Vec<_>>();
+            let batch = proofs
+                .iter()
+                .zip(public_inputs.iter())
+                .map(|((proof, _), public_input)| {
+                    (&proof[..], public_input.iter().map(|c| &c[..]).collect())
+                })
+                .collect::<Vec<_>>();
+
+            let params = KZG_PARAMETER.get_parameter();
+            let empty_circuit = MyCircuit::<Fr>::default();
+            verify_kzg_batch(&params, &empty_circuit, &batch)
+        }
+    }
 }
@@ -0,0 +1,85 @@
+//! Binary Poseidon Merkle tree over field elements.
+//!
+//! Leaves are padded with zeros up to a power of two and inner nodes are
+//! `digest::hash_pair(left, right)`. A `MerklePath` shows that one leaf is
+//! included under a root without revealing the other leaves.
+
+use halo2_proofs::halo2curves::{group::ff::PrimeField, pasta::Fp};
+use serde::{Deserialize, Serialize};
+
+use crate::digest::hash_pair;
+
+#[derive(Clone, Debug)]
+pub struct MerkleTree {
+    // levels[0] are the (padded) leaves, the last level is the root
+    levels: Vec<Vec<Fp>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: &[Fp]) -> Self {
+        let mut level = leaves.to_vec();
+        level.resize(leaves.len().max(1).next_power_of_two(), Fp::zero());
+
+        let mut levels = vec![level];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| hash_pair(pair[0], pair[1]))
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Number of siblings on a path of a tree over `leaves` leaves.
+    pub fn depth(leaves: usize) -> usize {
+        leaves.max(1).next_power_of_two().trailing_zeros() as usize
+    }
+
+    pub fn root(&self) -> Fp {
+        self.levels.last().unwrap()[0]
+    }
+
+    /// Siblings of leaf `index` from the bottom up.
+    pub fn path(&self, index: usize) -> MerklePath {
+        assert!(index < self.levels[0].len());
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(depth, level)| level[(index >> depth) ^ 1].to_repr())
+            .collect();
+        MerklePath {
+            index: index as u32,
+            siblings,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerklePath {
+    pub index: u32,
+    // `Fp::to_repr` of the siblings, bottom up
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerklePath {
+    /// Root the path leads to from `leaf`, `None` if the index is outside the path
+    /// or a sibling is not a field element.
+    pub fn root(&self, leaf: Fp) -> Option<Fp> {
+        if self.siblings.len() < u32::BITS as usize && self.index >> self.siblings.len() != 0 {
+            return None;
+        }
+        let mut node = leaf;
+        for (depth, sibling) in self.siblings.iter().enumerate() {
+            let sibling = Option::<Fp>::from(Fp::from_repr(*sibling))?;
+            node = if (self.index >> depth) & 1 == 0 {
+                hash_pair(node, sibling)
+            } else {
+                hash_pair(sibling, node)
+            };
+        }
+        Some(node)
+    }
+}
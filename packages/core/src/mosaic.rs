@@ -0,0 +1,357 @@
+//! Full-resolution mosaic (pixelation) and its proof.
+//!
+//! `mosaic` replaces every `block` X `block` block of an image by its average
+//! and keeps the image size; trailing rows and columns that do not fill a
+//! block repeat the nearest block (`Downsampler::upscale`). The circuit checks
+//! `sum + offset = block^2 * average + remainder` for every block (`Rounding`),
+//! as the reduction circuits do, looks up every remainder in the range the
+//! rounding allows for a divisor of `block^2` (`RemainderTable`), and copies
+//! each average to all output pixels of its block, so the published mosaic is
+//! the instance and the original stays private.
+
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{bn256::Fr, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, prove_kzg, verify_ipa, verify_kzg, Backend},
+    channels::{channel_public_input, ChannelLayout, NormalizedImage},
+    downsample::Downsampler,
+    helpers::{sample, RemainderTable},
+    rounding, set_once, set_shape, HEIGHT, KZG_PARAMETER, PARAMETER, WIDTH,
+};
+
+// モザイクのブロックの大きさ (回路の形の一部)
+pub static BLOCK: OnceCell<usize> = OnceCell::new();
+
+// 元の画像のサイズとブロックの大きさから回路の形を設定する: 既に別の形なら false
+pub fn set_mosaic_shape(width: u32, height: u32, block: u32) -> bool {
+    set_shape(width, height) && set_once(&BLOCK, block as usize)
+}
+
+/// `img` pixelated with `block` X `block` blocks and the current `rounding`, same size and layout.
+pub fn mosaic(img: &NormalizedImage, block: u32) -> NormalizedImage {
+    let pixels = Downsampler::new(block).with_rounding(rounding()).mosaic(
+        &img.pixels,
+        img.layout.channels(),
+        img.width,
+        img.height,
+    );
+    NormalizedImage { pixels, ..*img }
+}
+
+#[derive(Clone, Debug)]
+pub struct MosaicConfig<const C: usize> {
+    // `block` original columns per block column, trailing columns are not used
+    original: [Vec<Column<Advice>>; C],
+    average: [Vec<Column<Advice>>; C],
+    remainder: [Vec<Column<Advice>>; C],
+    // parity of `average` under `HalfEven`
+    parity: [Vec<Option<Column<Advice>>>; C],
+    // one column per output column
+    instance: [Vec<Column<Instance>>; C],
+    remainders: RemainderTable,
+    q_block: Selector,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MosaicCircuit<F: FieldExt, const C: usize> {
+    // interleaved samples as in `NormalizedImage::pixels`, empty without witnesses
+    pub original: Vec<Value<u8>>,
+    // one per block and channel, as in `Downsampled`
+    pub average: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u32>>,
+    pub _marker: std::marker::PhantomData<F>,
+}
+
+impl<F: FieldExt, const C: usize> Circuit<F> for MosaicCircuit<F, C> {
+    type Config = MosaicConfig<C>;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let width = *WIDTH.get().unwrap();
+        let block = *BLOCK.get().unwrap();
+        let blocks = width / block;
+        let columns = |meta: &mut ConstraintSystem<F>, n: usize| {
+            (0..n).map(|_| meta.advice_column()).collect::<Vec<_>>()
+        };
+
+        let original = [(); C].map(|_| columns(meta, blocks * block));
+        let remainder = [(); C].map(|_| columns(meta, blocks));
+        let average = [(); C].map(|_| columns(meta, blocks));
+        let instance = [(); C].map(|_| {
+            (0..width)
+                .map(|_| meta.instance_column())
+                .collect::<Vec<_>>()
+        });
+        average
+            .iter()
+            .flatten()
+            .for_each(|&col| meta.enable_equality(col));
+        instance
+            .iter()
+            .flatten()
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_block = meta.complex_selector();
+        let remainders = RemainderTable::configure(meta, rounding(), (block * block) as u32);
+        let parity = std::array::from_fn(|c| {
+            (0..blocks)
+                .map(|i| {
+                    remainders.range_check(
+                        meta,
+                        "mosaic remainder",
+                        q_block,
+                        average[c][i],
+                        remainder[c][i],
+                    )
+                })
+                .collect()
+        });
+        meta.create_gate("mosaic blocks", |virtual_cells| {
+            let q_block = virtual_cells.query_selector(q_block);
+            let offset =
+                Expression::Constant(F::from(rounding().offset((block * block) as u32) as u64));
+            let mut constraints = Vec::with_capacity(C * blocks);
+            for c in 0..C {
+                for i in 0..blocks {
+                    let mut sum = offset.clone();
+                    for dy in 0..block {
+                        for dx in 0..block {
+                            sum = sum
+                                + virtual_cells
+                                    .query_advice(original[c][block * i + dx], Rotation(dy as i32));
+                        }
+                    }
+                    let average = virtual_cells.query_advice(average[c][i], Rotation::cur());
+                    let remainder = virtual_cells.query_advice(remainder[c][i], Rotation::cur());
+                    constraints.push(
+                        sum - (Expression::Constant(F::from((block * block) as u64)) * average
+                            + remainder),
+                    );
+                }
+            }
+            Constraints::with_selector(q_block, constraints)
+        });
+
+        MosaicConfig {
+            original,
+            average,
+            remainder,
+            parity,
+            instance,
+            remainders,
+            q_block,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let width = *WIDTH.get().unwrap();
+        let height = *HEIGHT.get().unwrap();
+        let block = *BLOCK.get().unwrap();
+        let (blocks_x, blocks_y) = (width / block, height / block);
+        config.remainders.load(&mut layouter)?;
+
+        // averages[(y * blocks_x + x) * C + c]
+        let averages = layouter.assign_region(
+            || "mosaic blocks",
+            |mut region| {
+                let mut cells = Vec::with_capacity(blocks_y * blocks_x * C);
+                for y in 0..blocks_y {
+                    config.q_block.enable(&mut region, block * y)?;
+                    for x in 0..blocks_x {
+                        for c in 0..C {
+                            for dy in 0..block {
+                                for dx in 0..block {
+                                    let index = ((block * y + dy) * width + block * x + dx) * C + c;
+                                    region.assign_advice(
+                                        || "original",
+                                        config.original[c][block * x + dx],
+                                        block * y + dy,
+                                        || sample(&self.original, index),
+                                    )?;
+                                }
+                            }
+                            let index = (y * blocks_x + x) * C + c;
+                            region.assign_advice(
+                                || "remainder",
+                                config.remainder[c][x],
+                                block * y,
+                                || sample(&self.remainder, index),
+                            )?;
+                            RemainderTable::assign_parity(
+                                &mut region,
+                                config.parity[c][x],
+                                block * y,
+                                self.average
+                                    .get(index)
+                                    .copied()
+                                    .unwrap_or_else(Value::unknown),
+                            )?;
+                            cells.push(region.assign_advice(
+                                || "average",
+                                config.average[c][x],
+                                block * y,
+                                || sample(&self.average, index),
+                            )?);
+                        }
+                    }
+                }
+                Ok(cells)
+            },
+        )?;
+
+        // 出力の各ピクセルはブロックの平均と等しい
+        for y in 0..height {
+            let block_y = (y / block).min(blocks_y - 1);
+            for x in 0..width {
+                let block_x = (x / block).min(blocks_x - 1);
+                for c in 0..C {
+                    let cell = &averages[(block_y * blocks_x + block_x) * C + c];
+                    layouter.constrain_instance(cell.cell(), config.instance[c][x], y)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The circuit proving the returned mosaic of `img`, also sets the shape globals.
+pub fn mosaic_circuit<F: FieldExt, const C: usize>(
+    img: &NormalizedImage,
+    block: u32,
+) -> (MosaicCircuit<F, C>, NormalizedImage) {
+    assert_eq!(img.layout.channels(), C);
+    assert!(block > 0 && block <= img.width && block <= img.height);
+    assert!(
+        set_mosaic_shape(img.width, img.height, block),
+        "the circuit shape is already set for another image size or block"
+    );
+    let downsampler = Downsampler::new(block).with_rounding(rounding());
+    let reduced = downsampler.downsample(&img.pixels, C, img.width, img.height);
+
+    let circuit = MosaicCircuit {
+        original: img.pixels.iter().map(|&v| Value::known(v)).collect(),
+        average: reduced.pixels.iter().map(|&v| Value::known(v)).collect(),
+        remainder: reduced
+            .remainders
+            .iter()
+            .map(|&v| Value::known(v))
+            .collect(),
+        _marker: std::marker::PhantomData,
+    };
+    let mosaic = NormalizedImage {
+        pixels: downsampler.upscale(&reduced, img.width, img.height),
+        ..*img
+    };
+    (circuit, mosaic)
+}
+
+fn prove_mosaic<const C: usize>(
+    backend: Backend,
+    img: &NormalizedImage,
+    block: u32,
+) -> (NormalizedImage, Vec<u8>) {
+    match backend {
+        Backend::Ipa => {
+            let (circuit, mosaic) = mosaic_circuit::<Fp, C>(img, block);
+            let public_input = channel_public_input::<Fp>(&mosaic);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = PARAMETER.get_parameter();
+            let empty_circuit = MosaicCircuit::<Fp, C>::default();
+            let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+            (mosaic, proof)
+        }
+        Backend::Kzg => {
+            let (circuit, mosaic) = mosaic_circuit::<Fr, C>(img, block);
+            let public_input = channel_public_input::<Fr>(&mosaic);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = KZG_PARAMETER.get_parameter();
+            let empty_circuit = MosaicCircuit::<Fr, C>::default();
+            let proof = prove_kzg(&params, &empty_circuit, circuit, &public_input);
+            (mosaic, proof)
+        }
+    }
+}
+
+fn verify_mosaic_channels<const C: usize>(
+    backend: Backend,
+    proof: &[u8],
+    mosaic: &NormalizedImage,
+) -> bool {
+    match backend {
+        Backend::Ipa => {
+            let public_input = channel_public_input::<Fp>(mosaic);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = PARAMETER.get_parameter();
+            verify_ipa(
+                &params,
+                &MosaicCircuit::<Fp, C>::default(),
+                proof,
+                &public_input,
+            )
+        }
+        Backend::Kzg => {
+            let public_input = channel_public_input::<Fr>(mosaic);
+            let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+            let params = KZG_PARAMETER.get_parameter();
+            verify_kzg(
+                &params,
+                &MosaicCircuit::<Fr, C>::default(),
+                proof,
+                &public_input,
+            )
+        }
+    }
+}
+
+/// Pixelates `img` with `block` X `block` blocks and proves it, returns the mosaic
+/// (same size and layout as `img`) and the proof.
+pub fn create_mosaic_proof(
+    backend: Backend,
+    img: &NormalizedImage,
+    block: u32,
+) -> (NormalizedImage, Vec<u8>) {
+    match img.layout {
+        ChannelLayout::Gray => prove_mosaic::<1>(backend, img, block),
+        ChannelLayout::Rgb => prove_mosaic::<3>(backend, img, block),
+        ChannelLayout::Rgba => prove_mosaic::<4>(backend, img, block),
+    }
+}
+
+/// Checks that `proof` proves `mosaic` is a `block` X `block` pixelation of some image of the same size.
+pub fn verify_mosaic(backend: Backend, proof: &[u8], mosaic: &NormalizedImage, block: u32) -> bool {
+    let expected = mosaic.width as usize * mosaic.height as usize * mosaic.layout.channels();
+    if mosaic.pixels.len() != expected
+        || block == 0
+        || block > mosaic.width
+        || block > mosaic.height
+    {
+        return false;
+    }
+    if !set_mosaic_shape(mosaic.width, mosaic.height, block) {
+        return false;
+    }
+    match mosaic.layout {
+        ChannelLayout::Gray => verify_mosaic_channels::<1>(backend, proof, mosaic),
+        ChannelLayout::Rgb => verify_mosaic_channels::<3>(backend, proof, mosaic),
+        ChannelLayout::Rgba => verify_mosaic_channels::<4>(backend, proof, mosaic),
+    }
+}
@@ -0,0 +1,556 @@
+//! Perceptual hashes (aHash / dHash) of a committed original.
+//!
+//! The original is reduced to a gray grid of 8 X 8 (aHash) or 9 X 8 (dHash)
+//! cells with `block` X `block` block averages, `block` being the largest that
+//! fits; trailing rows and columns are dropped as in `Downsampler`. A cell is
+//! `(sum of R + G + B over the block + offset) / (3 * block^2)`, checked like
+//! the averages of `MyCircuit`: `sum + offset = divisor * gray + remainder` with
+//! the remainder in the range the rounding allows (`RemainderTable`, with the
+//! parity of the gray under `HalfEven`). The summed samples are byte-checked. aHash sets bit `i` when cell
+//! `i` is above the mean of all cells, dHash when the right neighbour is
+//! brighter (as `imagehash`); the first cell is the most significant bit.
+//! Every comparison `d > 0` is shown by `d - 1` or `-d` fitting in two bytes.
+//! The 64-bit hash and the digest of the original are the only instances.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::pixels_digest,
+    helpers::{compose, ipa_parameter, sample, RemainderTable},
+    rounding, set_once,
+    similarity::{
+        reference_rows, set_similarity_shape, ReferenceConfig, ReferenceWitness, Transform,
+    },
+    Parameter, HEIGHT, WIDTH,
+};
+
+const HASH_BITS: usize = 64;
+// |64 * gray - sum of grays| < 2^14
+const DIFFERENCE_BYTES: usize = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PerceptualHash {
+    // aHash
+    #[default]
+    Average,
+    // dHash
+    Difference,
+}
+
+impl PerceptualHash {
+    // (columns, rows) of the gray grid
+    fn grid(&self) -> (u32, u32) {
+        match self {
+            PerceptualHash::Average => (8, 8),
+            PerceptualHash::Difference => (9, 8),
+        }
+    }
+
+    /// Side of the blocks averaged into one gray cell, 0 if the image is too small.
+    pub fn block(&self, width: u32, height: u32) -> u32 {
+        let (columns, rows) = self.grid();
+        (width / columns).min(height / rows)
+    }
+}
+
+/// (gray, remainder) of the cells of the gray grid, row major.
+pub fn gray_grid(kind: PerceptualHash, buf: &[u8], width: u32, height: u32) -> Vec<(u8, u32)> {
+    let (columns, rows) = kind.grid();
+    let block = kind.block(width, height);
+    assert!(block > 0, "the image is smaller than the hash grid");
+    let divisor = 3 * block * block;
+
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
+    for cy in 0..rows {
+        for cx in 0..columns {
+            let mut sum = 0;
+            for dy in 0..block {
+                for dx in 0..block {
+                    let index = (((cy * block + dy) * width + cx * block + dx) * 4) as usize;
+                    sum += buf[index..index + 3].iter().map(|&v| v as u32).sum::<u32>();
+                }
+            }
+            let (gray, remainder) = rounding().divide(sum, divisor);
+            cells.push((gray as u8, remainder));
+        }
+    }
+    cells
+}
+
+// the compared pairs (bit set when `weight * left > right`) of the gray grid
+fn comparisons(kind: PerceptualHash, grays: &[i64]) -> Vec<(i64, i64)> {
+    match kind {
+        PerceptualHash::Average => {
+            let total = grays.iter().sum::<i64>();
+            grays.iter().map(|&gray| (gray, total)).collect()
+        }
+        PerceptualHash::Difference => grays
+            .chunks(9)
+            .flat_map(|row| row.windows(2).map(|pair| (pair[1], pair[0])))
+            .collect(),
+    }
+}
+
+fn weight(kind: PerceptualHash) -> i64 {
+    match kind {
+        PerceptualHash::Average => HASH_BITS as i64,
+        PerceptualHash::Difference => 1,
+    }
+}
+
+/// aHash or dHash of a RGBA8 image.
+pub fn perceptual_hash(kind: PerceptualHash, buf: &[u8], width: u32, height: u32) -> u64 {
+    let grays = gray_grid(kind, buf, width, height)
+        .into_iter()
+        .map(|(gray, _)| gray as i64)
+        .collect::<Vec<_>>();
+    comparisons(kind, &grays)
+        .into_iter()
+        .fold(0, |hash, (left, right)| {
+            (hash << 1) | (weight(kind) * left > right) as u64
+        })
+}
+
+/// Number of differing bits, small distances mean similar images.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// 回路の形の一部: ハッシュの種類
+pub static HASH_KIND: OnceCell<PerceptualHash> = OnceCell::new();
+
+// 既に別の形なら false
+pub fn set_hash_shape(kind: PerceptualHash, width: u32, height: u32) -> bool {
+    set_similarity_shape(Transform::Identity, width, height) & set_once(&HASH_KIND, kind)
+}
+
+pub static PHASH_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn phash_parameter() -> &'static Parameter {
+    PHASH_PARAMETER.get_or_init(|| {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+        let kind = *HASH_KIND.get().unwrap();
+        let block = kind.block(width as u32, height as u32) as usize;
+        let (columns, rows) = kind.grid();
+        let cells = (columns * rows) as usize;
+        // gray cells, the sum of the grays and the comparisons
+        ipa_parameter(
+            reference_rows(width * height, 0)
+                + cells * (block * block + 1)
+                + (cells + 1)
+                + (HASH_BITS + 1),
+        )
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct HashConfig {
+    reference: ReferenceConfig,
+    // one pixel of a block per row, then the gray cell
+    rgb: [Column<Advice>; 3],
+    sum: Column<Advice>,
+    q_sum: Selector,
+    gray: Column<Advice>,
+    remainder: Column<Advice>,
+    // parity of `gray` under `HalfEven`
+    parity: Option<Column<Advice>>,
+    q_gray: Selector,
+    remainders: RemainderTable,
+    // aHash: running sum of the grays
+    total: Column<Advice>,
+    q_total: Selector,
+    // one comparison per row
+    left: Column<Advice>,
+    right: Column<Advice>,
+    bit: Column<Advice>,
+    difference: [Column<Advice>; DIFFERENCE_BYTES],
+    hash: Column<Advice>,
+    q_bit: Selector,
+    // row 0: hash, row 1: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HashCircuit {
+    pub reference: ReferenceWitness,
+    // row major gray grid
+    pub grays: Vec<Value<u8>>,
+    pub remainders: Vec<Value<u32>>,
+}
+
+// the low `n` bytes of a small field element
+fn low_bytes(value: Value<Fp>, n: usize) -> Vec<Value<Fp>> {
+    (0..n)
+        .map(|i| value.map(|v| Fp::from(v.to_repr().as_ref()[i] as u64)))
+        .collect()
+}
+
+impl Circuit<Fp> for HashCircuit {
+    type Config = HashConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let kind = *HASH_KIND.get().unwrap();
+        let block = kind.block(*WIDTH.get().unwrap() as u32, *HEIGHT.get().unwrap() as u32);
+        let divisor = 3 * block * block;
+        let constant = |v: u64| Expression::Constant(Fp::from(v));
+
+        // the digest enables a constant column for the zero sums
+        let reference = ReferenceConfig::configure(meta);
+        let rgb = [(); 3].map(|_| meta.advice_column());
+        let sum = meta.advice_column();
+        let gray = meta.advice_column();
+        let remainder = meta.advice_column();
+        let total = meta.advice_column();
+        let left = meta.advice_column();
+        let right = meta.advice_column();
+        let bit = meta.advice_column();
+        let difference = [(); DIFFERENCE_BYTES].map(|_| meta.advice_column());
+        let hash = meta.advice_column();
+        let statement = meta.instance_column();
+        rgb.iter()
+            .chain([&sum, &gray, &total, &left, &right, &hash])
+            .for_each(|&col| meta.enable_equality(col));
+        meta.enable_equality(statement);
+        let bytes = reference.bytes();
+
+        let q_sum = meta.complex_selector();
+        meta.create_gate("block sum", |virtual_cells| {
+            let q_sum = virtual_cells.query_selector(q_sum);
+            let pixel = rgb.iter().fold(constant(0), |acc, &col| {
+                acc + virtual_cells.query_advice(col, Rotation::cur())
+            });
+            let sum_cur = virtual_cells.query_advice(sum, Rotation::cur());
+            let sum_next = virtual_cells.query_advice(sum, Rotation::next());
+            Constraints::with_selector(q_sum, [sum_next - (sum_cur + pixel)])
+        });
+        // copies of the original, which the digest checks too
+        bytes.range_check(meta, "rgb byte", q_sum, &rgb);
+
+        let q_gray = meta.complex_selector();
+        meta.create_gate("gray cell", |virtual_cells| {
+            let q_gray = virtual_cells.query_selector(q_gray);
+            let offset = constant(rounding().offset(divisor) as u64);
+            let sum = virtual_cells.query_advice(sum, Rotation::cur());
+            let gray = virtual_cells.query_advice(gray, Rotation::cur());
+            let remainder = virtual_cells.query_advice(remainder, Rotation::cur());
+            Constraints::with_selector(
+                q_gray,
+                [sum + offset - (constant(divisor as u64) * gray + remainder)],
+            )
+        });
+        // 余りは丸め方の許す範囲 (HalfEven では gray の偶奇も見る)
+        let remainders = RemainderTable::configure(meta, rounding(), divisor);
+        let parity = remainders.range_check(meta, "gray remainder", q_gray, gray, remainder);
+
+        let q_total = meta.selector();
+        meta.create_gate("gray total", |virtual_cells| {
+            let q_total = virtual_cells.query_selector(q_total);
+            let gray = virtual_cells.query_advice(left, Rotation::cur());
+            let total_cur = virtual_cells.query_advice(total, Rotation::cur());
+            let total_next = virtual_cells.query_advice(total, Rotation::next());
+            Constraints::with_selector(q_total, [total_next - (total_cur + gray)])
+        });
+
+        let q_bit = meta.complex_selector();
+        meta.create_gate("hash bit", |virtual_cells| {
+            let q_bit = virtual_cells.query_selector(q_bit);
+            let one = constant(1);
+            let left = virtual_cells.query_advice(left, Rotation::cur());
+            let right = virtual_cells.query_advice(right, Rotation::cur());
+            let bit = virtual_cells.query_advice(bit, Rotation::cur());
+            let difference = difference.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            let hash_cur = virtual_cells.query_advice(hash, Rotation::cur());
+            let hash_next = virtual_cells.query_advice(hash, Rotation::next());
+            // bit = 1: d - 1 >= 0, bit = 0: -d >= 0
+            let d = constant(weight(kind) as u64) * left - right;
+            let shown = bit.clone() * (d.clone() - one.clone()) - (one.clone() - bit.clone()) * d;
+            Constraints::with_selector(
+                q_bit,
+                [
+                    bit.clone() * (one - bit.clone()),
+                    shown - compose(&difference),
+                    hash_next - (constant(2) * hash_cur + bit),
+                ],
+            )
+        });
+        bytes.range_check(meta, "difference byte", q_bit, &difference);
+
+        HashConfig {
+            reference,
+            rgb,
+            sum,
+            q_sum,
+            gray,
+            remainder,
+            parity,
+            q_gray,
+            remainders,
+            total,
+            q_total,
+            left,
+            right,
+            bit,
+            difference,
+            hash,
+            q_bit,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let kind = *HASH_KIND.get().unwrap();
+        let width = *WIDTH.get().unwrap();
+        let block = kind.block(width as u32, *HEIGHT.get().unwrap() as u32) as usize;
+        let (columns, rows) = kind.grid();
+        let (columns, rows) = (columns as usize, rows as usize);
+
+        let (digest, original) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            Transform::Identity,
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 1)?;
+        config.remainders.load(&mut layouter)?;
+
+        let grays = layouter.assign_region(
+            || "gray cells",
+            |mut region| {
+                let mut cells = Vec::with_capacity(columns * rows);
+                let mut offset = 0;
+                for cy in 0..rows {
+                    for cx in 0..columns {
+                        let index = cy * columns + cx;
+                        let mut sum = region.assign_advice_from_constant(
+                            || "zero",
+                            config.sum,
+                            offset,
+                            Fp::zero(),
+                        )?;
+                        for dy in 0..block {
+                            for dx in 0..block {
+                                config.q_sum.enable(&mut region, offset)?;
+                                let pixel = (cy * block + dy) * width + cx * block + dx;
+                                let mut next = sum.value().copied();
+                                for (c, &col) in config.rgb.iter().enumerate() {
+                                    let cell = original[4 * pixel + c].copy_advice(
+                                        || "rgb",
+                                        &mut region,
+                                        col,
+                                        offset,
+                                    )?;
+                                    next = next + cell.value().copied();
+                                }
+                                offset += 1;
+                                sum =
+                                    region.assign_advice(|| "sum", config.sum, offset, || next)?;
+                            }
+                        }
+
+                        config.q_gray.enable(&mut region, offset)?;
+                        region.assign_advice(
+                            || "remainder",
+                            config.remainder,
+                            offset,
+                            || sample(&self.remainders, index),
+                        )?;
+                        RemainderTable::assign_parity(
+                            &mut region,
+                            config.parity,
+                            offset,
+                            self.grays
+                                .get(index)
+                                .copied()
+                                .unwrap_or_else(Value::unknown),
+                        )?;
+                        cells.push(region.assign_advice(
+                            || "gray",
+                            config.gray,
+                            offset,
+                            || sample(&self.grays, index),
+                        )?);
+                        offset += 1;
+                    }
+                }
+                Ok(cells)
+            },
+        )?;
+
+        let pairs: Vec<(AssignedCell<Fp, Fp>, AssignedCell<Fp, Fp>)> = match kind {
+            PerceptualHash::Average => {
+                let total = layouter.assign_region(
+                    || "gray total",
+                    |mut region| {
+                        let mut total = region.assign_advice_from_constant(
+                            || "zero",
+                            config.total,
+                            0,
+                            Fp::zero(),
+                        )?;
+                        for (i, gray) in grays.iter().enumerate() {
+                            config.q_total.enable(&mut region, i)?;
+                            let gray = gray.copy_advice(|| "gray", &mut region, config.left, i)?;
+                            let next = total.value().copied() + gray.value().copied();
+                            total =
+                                region.assign_advice(|| "total", config.total, i + 1, || next)?;
+                        }
+                        Ok(total)
+                    },
+                )?;
+                grays
+                    .iter()
+                    .map(|gray| (gray.clone(), total.clone()))
+                    .collect()
+            }
+            PerceptualHash::Difference => grays
+                .chunks(columns)
+                .flat_map(|row| {
+                    row.windows(2)
+                        .map(|pair| (pair[1].clone(), pair[0].clone()))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+        };
+
+        let hash = layouter.assign_region(
+            || "hash bits",
+            |mut region| {
+                let weight = Fp::from(weight(kind) as u64);
+                let mut hash =
+                    region.assign_advice_from_constant(|| "zero", config.hash, 0, Fp::zero())?;
+                for (i, (left, right)) in pairs.iter().enumerate() {
+                    config.q_bit.enable(&mut region, i)?;
+                    let left = left.copy_advice(|| "left", &mut region, config.left, i)?;
+                    let right = right.copy_advice(|| "right", &mut region, config.right, i)?;
+                    let d = left.value().map(|&l| weight * l) - right.value().copied();
+                    // |d| < 2^14: d > 0 iff it is nonzero with zero high bytes
+                    let positive = d.map(|d| {
+                        d != Fp::zero()
+                            && d.to_repr().as_ref()[DIFFERENCE_BYTES..]
+                                .iter()
+                                .all(|&b| b == 0)
+                    });
+                    let bit = positive.map(|p| Fp::from(p as u64));
+                    let shown = positive
+                        .zip(d)
+                        .map(|(p, d)| if p { d - Fp::one() } else { -d });
+                    region.assign_advice(|| "bit", config.bit, i, || bit)?;
+                    for (&col, byte) in config
+                        .difference
+                        .iter()
+                        .zip(low_bytes(shown, DIFFERENCE_BYTES))
+                    {
+                        region.assign_advice(|| "difference byte", col, i, || byte)?;
+                    }
+                    let next = hash.value().copied() * Value::known(Fp::from(2)) + bit;
+                    hash = region.assign_advice(|| "hash", config.hash, i + 1, || next)?;
+                }
+                Ok(hash)
+            },
+        )?;
+        layouter.constrain_instance(hash.cell(), config.statement, 0)
+    }
+}
+
+/// The circuit hashing the RGBA8 original and the hash, also sets the shape
+/// globals; None if the image is smaller than the grid.
+pub fn hash_circuit(
+    kind: PerceptualHash,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+) -> Option<(HashCircuit, u64)> {
+    if kind.block(width, height) == 0 {
+        return None;
+    }
+    assert!(
+        set_hash_shape(kind, width, height),
+        "the circuit shape is already set for another image size or hash"
+    );
+
+    let cells = gray_grid(kind, origin_buf, width, height);
+    let hash = perceptual_hash(kind, origin_buf, width, height);
+    let circuit = HashCircuit {
+        reference: ReferenceWitness::new(Transform::Identity, origin_buf, width, height),
+        grays: cells.iter().map(|&(gray, _)| Value::known(gray)).collect(),
+        remainders: cells
+            .iter()
+            .map(|&(_, remainder)| Value::known(remainder))
+            .collect(),
+    };
+    Some((circuit, hash))
+}
+
+/// [[hash, digest of the original]]
+pub fn hash_public_input(hash: u64, digest: Fp) -> Vec<Vec<Fp>> {
+    vec![vec![Fp::from(hash), digest]]
+}
+
+/// Hashes the RGBA8 original and proves it; returns the hash, the proof and the
+/// digest of the original (`Fp::to_repr`), or None if the image is smaller than the grid.
+pub fn create_hash_proof(
+    kind: PerceptualHash,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Option<(u64, Vec<u8>, [u8; 32])> {
+    let (circuit, hash) = hash_circuit(kind, &origin_buf, width, height)?;
+    let digest = pixels_digest(&origin_buf);
+    let public_input = hash_public_input(hash, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = phash_parameter().get_parameter();
+    let empty_circuit = HashCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    Some((hash, proof, digest.to_repr()))
+}
+
+/// Checks that `hash` is the perceptual hash of the original of `width` X `height`
+/// with digest `digest`.
+pub fn verify_hash(
+    kind: PerceptualHash,
+    proof: &[u8],
+    width: u32,
+    height: u32,
+    hash: u64,
+    digest: [u8; 32],
+) -> bool {
+    if kind.block(width, height) == 0 {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_hash_shape(kind, width, height) {
+        return false;
+    }
+
+    let public_input = hash_public_input(hash, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = phash_parameter().get_parameter();
+    let empty_circuit = HashCircuit::default();
+    verify_ipa(&params, &empty_circuit, proof, &public_input)
+}
@@ -0,0 +1,96 @@
+//! Progress reporting and cancellation for long running proofs.
+//!
+//! Proving functions with a `_with_progress` suffix report every phase to a
+//! `ProgressSink` and check a `CancelToken` before entering it. A phase itself
+//! (e.g. `create_proof`) is not interrupted, cancellation takes effect at the
+//! next phase boundary.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    // reference reduction and circuit witness
+    Witness,
+    Keygen,
+    Proving,
+    // tile `index` of `total` (raster order) is being proved
+    Tile { index: u32, total: u32 },
+    Done,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Witness => "witness",
+            Phase::Keygen => "keygen",
+            Phase::Proving => "proving",
+            Phase::Tile { .. } => "tile",
+            Phase::Done => "done",
+        }
+    }
+}
+
+pub trait ProgressSink {
+    fn phase(&self, phase: Phase);
+}
+
+/// Discards every report, used by the functions without a sink.
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn phase(&self, _phase: Phase) {}
+}
+
+impl<F: Fn(Phase)> ProgressSink for F {
+    fn phase(&self, phase: Phase) {
+        self(phase)
+    }
+}
+
+/// Shared flag, clones observe the same cancellation.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "proof generation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+// called at every phase boundary
+pub(crate) fn enter(
+    progress: &impl ProgressSink,
+    cancel: &CancelToken,
+    phase: Phase,
+) -> Result<(), Cancelled> {
+    if cancel.is_cancelled() {
+        return Err(Cancelled);
+    }
+    progress.phase(phase);
+    Ok(())
+}
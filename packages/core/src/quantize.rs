@@ -0,0 +1,353 @@
+//! Bit-depth reduction (colour quantization) and its proof.
+//!
+//! Every 8-bit sample is reduced to `bits` bits: `level = sample >> (8 - bits)`,
+//! or rounded with the current `Rounding` and clamped to the largest level.
+//! The published image stores `level << (8 - bits)`, so it can be viewed as a
+//! normal 8-bit image. The circuit keeps one sample per row and checks
+//! `sample + offset = 2^(8 - bits) * quotient + dropped`, with lookups proving
+//! that `dropped` is in the range the rounding allows for `2^(8 - bits)`
+//! (`RemainderTable`) and that `level = min(quotient, 2^bits - 1)`.
+//!
+//! The proof is about an RGBA8 original: the samples are copied from
+//! `ReferenceConfig` (`Transform::Identity`), which range checks them and binds
+//! them to `digest::pixels_digest` of the original, the second public input.
+//! The digest is Poseidon over Pasta, so only the IPA backend is supported.
+
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector, TableColumn,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    channels::{ChannelLayout, NormalizedImage},
+    digest::pixels_digest,
+    downsample::Rounding,
+    helpers::{ipa_parameter, sample, RemainderTable},
+    rounding, set_once, set_shape,
+    similarity::{reference_rows, ReferenceConfig, ReferenceWitness, Transform},
+    Parameter, HEIGHT, WIDTH,
+};
+
+// 回路の形: 元の画像のサイズ (WIDTH / HEIGHT) と出力のビット数
+pub static BITS: OnceCell<u32> = OnceCell::new();
+
+// the digest of the original dominates the rows, so k follows the original size
+pub static QUANTIZE_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn quantize_parameter() -> &'static Parameter {
+    QUANTIZE_PARAMETER.get_or_init(|| {
+        let pixels = *WIDTH.get().unwrap() * *HEIGHT.get().unwrap();
+        // the digest, the original and one row per sample
+        ipa_parameter(reference_rows(pixels, 0) + 4 * pixels)
+    })
+}
+
+// 既に別の形なら false
+pub fn set_quantize_shape(width: u32, height: u32, bits: u32) -> bool {
+    set_shape(width, height) & set_once(&BITS, bits)
+}
+
+fn shift(bits: u32) -> u32 {
+    assert!((1..=8).contains(&bits));
+    8 - bits
+}
+
+// (quotient, level, dropped) of one sample
+fn quantize_sample(sample: u8, bits: u32, rounding: Rounding) -> (u32, u8, u32) {
+    let (quotient, dropped) = rounding.divide(sample as u32, 1 << shift(bits));
+    let level = quotient.min((1 << bits) - 1);
+    (quotient, level as u8, dropped)
+}
+
+/// `img` with every sample reduced to `bits` bits (1 to 8) and stored as `level << (8 - bits)`.
+pub fn quantize(img: &NormalizedImage, bits: u32) -> NormalizedImage {
+    let rounding = rounding();
+    let pixels = img
+        .pixels
+        .iter()
+        .map(|&sample| quantize_sample(sample, bits, rounding).1 << shift(bits))
+        .collect();
+    NormalizedImage { pixels, ..*img }
+}
+
+#[derive(Clone, Debug)]
+pub struct QuantizeConfig {
+    reference: ReferenceConfig,
+    sample: Column<Advice>,
+    quotient: Column<Advice>,
+    level: Column<Advice>,
+    dropped: Column<Advice>,
+    // parity of `quotient` under `HalfEven`
+    parity: Option<Column<Advice>>,
+    instance: Column<Instance>,
+    // row 0: digest of the original
+    statement: Column<Instance>,
+    q_quantize: Selector,
+    dropped_table: RemainderTable,
+    // (quotient, min(quotient, 2^bits - 1)) for quotient in 0..=2^bits
+    quotient_table: TableColumn,
+    level_table: TableColumn,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QuantizeCircuit {
+    pub reference: ReferenceWitness,
+    // one per RGBA sample of the original, empty without witnesses
+    pub quotient: Vec<Value<u32>>,
+    pub level: Vec<Value<u8>>,
+    pub dropped: Vec<Value<u32>>,
+}
+
+impl Circuit<Fp> for QuantizeCircuit {
+    type Config = QuantizeConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let bits = *BITS.get().unwrap();
+        let divisor = 1u32 << shift(bits);
+
+        let reference = ReferenceConfig::configure(meta);
+        let sample = meta.advice_column();
+        let quotient = meta.advice_column();
+        let level = meta.advice_column();
+        let dropped = meta.advice_column();
+        let instance = meta.instance_column();
+        let statement = meta.instance_column();
+        meta.enable_equality(sample);
+        meta.enable_equality(level);
+        meta.enable_equality(instance);
+        meta.enable_equality(statement);
+
+        // lookups need the selector inside the expressions
+        let q_quantize = meta.complex_selector();
+        let dropped_table = RemainderTable::configure(meta, rounding(), divisor);
+        let quotient_table = meta.lookup_table_column();
+        let level_table = meta.lookup_table_column();
+
+        meta.create_gate("quantize", |virtual_cells| {
+            let q_quantize = virtual_cells.query_selector(q_quantize);
+            let offset = Expression::Constant(Fp::from(rounding().offset(divisor) as u64));
+            let sample = virtual_cells.query_advice(sample, Rotation::cur());
+            let quotient = virtual_cells.query_advice(quotient, Rotation::cur());
+            let dropped = virtual_cells.query_advice(dropped, Rotation::cur());
+            Constraints::with_selector(
+                q_quantize,
+                [sample + offset
+                    - (Expression::Constant(Fp::from(divisor as u64)) * quotient + dropped)],
+            )
+        });
+
+        // 選択されていない行は (0, 0, 0) で、どの表にも含まれる
+        let parity = dropped_table.range_check(meta, "dropped bits", q_quantize, quotient, dropped);
+        meta.lookup("clamped level", |virtual_cells| {
+            let q_quantize = virtual_cells.query_selector(q_quantize);
+            let quotient = virtual_cells.query_advice(quotient, Rotation::cur());
+            let level = virtual_cells.query_advice(level, Rotation::cur());
+            vec![
+                (q_quantize.clone() * quotient, quotient_table),
+                (q_quantize * level, level_table),
+            ]
+        });
+
+        QuantizeConfig {
+            reference,
+            sample,
+            quotient,
+            level,
+            dropped,
+            parity,
+            instance,
+            statement,
+            q_quantize,
+            dropped_table,
+            quotient_table,
+            level_table,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let bits = *BITS.get().unwrap();
+        let max_level = (1u64 << bits) - 1;
+
+        // the samples are range checked by the digest of the original
+        let (digest, samples) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            Transform::Identity,
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+
+        config.dropped_table.load(&mut layouter)?;
+        layouter.assign_table(
+            || "clamped level",
+            |mut table| {
+                for v in 0..=max_level + 1 {
+                    table.assign_cell(
+                        || "quotient",
+                        config.quotient_table,
+                        v as usize,
+                        || Value::known(Fp::from(v)),
+                    )?;
+                    table.assign_cell(
+                        || "level",
+                        config.level_table,
+                        v as usize,
+                        || Value::known(Fp::from(v.min(max_level))),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        let levels = layouter.assign_region(
+            || "quantize",
+            |mut region| {
+                let mut levels = Vec::with_capacity(samples.len());
+                for (i, cell) in samples.iter().enumerate() {
+                    config.q_quantize.enable(&mut region, i)?;
+                    cell.copy_advice(|| "sample", &mut region, config.sample, i)?;
+                    region.assign_advice(
+                        || "quotient",
+                        config.quotient,
+                        i,
+                        || sample(&self.quotient, i),
+                    )?;
+                    region.assign_advice(
+                        || "dropped",
+                        config.dropped,
+                        i,
+                        || sample(&self.dropped, i),
+                    )?;
+                    // quotient <= 2^bits, a byte whenever there are ties (bits < 8)
+                    RemainderTable::assign_parity(
+                        &mut region,
+                        config.parity,
+                        i,
+                        self.quotient
+                            .get(i)
+                            .copied()
+                            .unwrap_or_else(Value::unknown)
+                            .map(|q| q as u8),
+                    )?;
+                    levels.push(region.assign_advice(
+                        || "level",
+                        config.level,
+                        i,
+                        || sample(&self.level, i),
+                    )?);
+                }
+                Ok(levels)
+            },
+        )?;
+
+        for (i, level) in levels.iter().enumerate() {
+            layouter.constrain_instance(level.cell(), config.instance, i)?;
+        }
+        Ok(())
+    }
+}
+
+/// The circuit proving the returned quantization of the RGBA8 image `img`, also sets
+/// the shape globals.
+pub fn quantize_circuit(img: &NormalizedImage, bits: u32) -> (QuantizeCircuit, NormalizedImage) {
+    assert_eq!(img.layout, ChannelLayout::Rgba);
+    assert!(
+        set_quantize_shape(img.width, img.height, bits),
+        "the circuit shape is already set for another image size or bit depth"
+    );
+    let rounding = rounding();
+    let mut circuit = QuantizeCircuit {
+        reference: ReferenceWitness::new(Transform::Identity, &img.pixels, img.width, img.height),
+        ..Default::default()
+    };
+    let mut pixels = Vec::with_capacity(img.pixels.len());
+    for &v in &img.pixels {
+        let (quotient, level, dropped) = quantize_sample(v, bits, rounding);
+        circuit.quotient.push(Value::known(quotient));
+        circuit.level.push(Value::known(level));
+        circuit.dropped.push(Value::known(dropped));
+        pixels.push(level << shift(bits));
+    }
+    (circuit, NormalizedImage { pixels, ..*img })
+}
+
+/// [levels in sample order, [digest of the original]]
+pub fn quantize_public_input(quantized: &NormalizedImage, bits: u32, digest: Fp) -> Vec<Vec<Fp>> {
+    vec![
+        quantized
+            .pixels
+            .iter()
+            .map(|&v| Fp::from((v >> shift(bits)) as u64))
+            .collect(),
+        vec![digest],
+    ]
+}
+
+/// Reduces the RGBA8 image `img` to `bits` bits per sample and proves it, returns the
+/// quantized image (same size and layout, samples stored as `level << (8 - bits)`),
+/// the proof and the digest of `img` (`Fp::to_repr`).
+pub fn create_quantize_proof(
+    img: &NormalizedImage,
+    bits: u32,
+) -> (NormalizedImage, Vec<u8>, [u8; 32]) {
+    let (circuit, quantized) = quantize_circuit(img, bits);
+    let digest = pixels_digest(&img.pixels);
+    let public_input = quantize_public_input(&quantized, bits, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = quantize_parameter().get_parameter();
+    let empty_circuit = QuantizeCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    (quantized, proof, digest.to_repr())
+}
+
+/// Checks that `proof` proves the RGBA8 `quantized` is the original with digest `digest`
+/// reduced to `bits` bits.
+pub fn verify_quantize(
+    proof: &[u8],
+    quantized: &NormalizedImage,
+    bits: u32,
+    digest: [u8; 32],
+) -> bool {
+    let expected = quantized.width as usize * quantized.height as usize * 4;
+    if !(1..=8).contains(&bits)
+        || quantized.layout != ChannelLayout::Rgba
+        || quantized.pixels.len() != expected
+        || expected == 0
+    {
+        return false;
+    }
+    // 落としたビットは 0 のはず
+    let mask = (1u32 << shift(bits)) as u8 - 1;
+    if quantized.pixels.iter().any(|&v| v & mask != 0) {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_quantize_shape(quantized.width, quantized.height, bits) {
+        return false;
+    }
+    let public_input = quantize_public_input(quantized, bits, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = quantize_parameter().get_parameter();
+    verify_ipa(&params, &QuantizeCircuit::default(), proof, &public_input)
+}
@@ -0,0 +1,608 @@
+//! Aggregate similarity: the mean squared error between a transform of a
+//! committed original and a public image is at most a public threshold.
+//!
+//! The original (RGBA8) is committed with `digest::pixels_digest`, the public
+//! image has the shape of `Transform::apply`. An MSE accumulator adds
+//! `(published - reference)^2` row by row, and the last row proves
+//! `max_mse * samples - sum = slack` with `slack` split into bytes that are
+//! range-checked by a lookup, i.e. `sum / samples <= max_mse`.
+//! `max_mse_for_psnr` turns a PSNR bound into such a threshold.
+//! The Poseidon spec is defined over Pasta, so this mode uses the IPA backend.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::{digest_rows, pixels_digest, DigestConfig},
+    downsample::Downsampler,
+    helpers::{compose, ipa_parameter, sample, ByteTable, RemainderTable},
+    rounding, set_once, set_shape, Parameter, HEIGHT, SCALE_FACTOR, WIDTH,
+};
+
+// slack < 2^48, far above 255^2 * samples for any image the circuit fits
+const SLACK_BYTES: usize = 6;
+
+/// The transform applied to the original before comparing it with the public image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    // the original itself
+    Identity,
+    // `Downsampler::default()`, as proved by `MyCircuit`
+    #[default]
+    Downsample,
+}
+
+impl Transform {
+    /// (RGBA8 buffer, width, height) of the transformed original.
+    pub fn apply(&self, origin_buf: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        match self {
+            Transform::Identity => (origin_buf.to_vec(), width, height),
+            Transform::Downsample => {
+                let reduced = Downsampler::default().downsample_rgba(origin_buf, width, height);
+                (reduced.pixels, reduced.width, reduced.height)
+            }
+        }
+    }
+
+    /// Shape of the transformed original.
+    pub fn shape(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Identity => (width, height),
+            Transform::Downsample => (width / SCALE_FACTOR, height / SCALE_FACTOR),
+        }
+    }
+}
+
+pub fn sum_squared_error(reference: &[u8], published: &[u8]) -> u64 {
+    reference
+        .iter()
+        .zip(published)
+        .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+        .sum()
+}
+
+/// Largest integer MSE whose PSNR (peak 255) is at least `db`.
+pub fn max_mse_for_psnr(db: f64) -> u32 {
+    (255.0f64.powi(2) / 10f64.powf(db / 10.0)).floor() as u32
+}
+
+// 回路の形: 元の画像のサイズ (WIDTH / HEIGHT) と変換
+pub static TRANSFORM: OnceCell<Transform> = OnceCell::new();
+
+// 元の画像のサイズと変換から回路の形を設定する: 既に別の形なら false
+pub fn set_similarity_shape(transform: Transform, width: u32, height: u32) -> bool {
+    set_shape(width, height) & set_once(&TRANSFORM, transform)
+}
+
+// (original pixels, compared samples)
+fn similarity_size() -> (usize, usize) {
+    let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+    let (s_width, s_height) = TRANSFORM.get().unwrap().shape(width as u32, height as u32);
+    (width * height, s_width as usize * s_height as usize * 4)
+}
+
+// the digest of the original dominates the rows, so k follows the original size
+pub static SIMILARITY_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn similarity_parameter() -> &'static Parameter {
+    SIMILARITY_PARAMETER.get_or_init(|| {
+        let (pixels, samples) = similarity_size();
+        // the digest, the original and the accumulator
+        ipa_parameter(reference_rows(pixels, samples) + samples)
+    })
+}
+
+/// Accumulates `(published - reference)^2` and compares the sum with `max_mse * samples`.
+#[derive(Clone, Debug)]
+pub struct MseConfig {
+    reference: Column<Advice>,
+    published: Column<Advice>,
+    sum: Column<Advice>,
+    max_mse: Column<Advice>,
+    slack: [Column<Advice>; SLACK_BYTES],
+    q_accumulate: Selector,
+    q_threshold: Selector,
+}
+
+impl MseConfig {
+    // `bytes` is loaded by the circuit (`ReferenceConfig::bytes`)
+    pub fn configure(meta: &mut ConstraintSystem<Fp>, samples: usize, bytes: ByteTable) -> Self {
+        let reference = meta.advice_column();
+        let published = meta.advice_column();
+        let sum = meta.advice_column();
+        let max_mse = meta.advice_column();
+        let slack = [(); SLACK_BYTES].map(|_| meta.advice_column());
+        // the sum starts from a constant 0
+        let constant = meta.fixed_column();
+        meta.enable_constant(constant);
+        [reference, published, sum, max_mse]
+            .iter()
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_accumulate = meta.selector();
+        meta.create_gate("accumulate squared error", |virtual_cells| {
+            let q_accumulate = virtual_cells.query_selector(q_accumulate);
+            let reference = virtual_cells.query_advice(reference, Rotation::cur());
+            let published = virtual_cells.query_advice(published, Rotation::cur());
+            let sum_cur = virtual_cells.query_advice(sum, Rotation::cur());
+            let sum_next = virtual_cells.query_advice(sum, Rotation::next());
+            let error = published - reference;
+            Constraints::with_selector(q_accumulate, [sum_next - (sum_cur + error.clone() * error)])
+        });
+
+        // lookups need the selector inside the expressions
+        let q_threshold = meta.complex_selector();
+        meta.create_gate("mse threshold", |virtual_cells| {
+            let q_threshold = virtual_cells.query_selector(q_threshold);
+            let sum = virtual_cells.query_advice(sum, Rotation::cur());
+            let max_mse = virtual_cells.query_advice(max_mse, Rotation::cur());
+            let slack = slack.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            Constraints::with_selector(
+                q_threshold,
+                [
+                    max_mse * Expression::Constant(Fp::from(samples as u64))
+                        - sum
+                        - compose(&slack),
+                ],
+            )
+        });
+        bytes.range_check(meta, "slack byte", q_threshold, &slack);
+
+        Self {
+            reference,
+            published,
+            sum,
+            max_mse,
+            slack,
+            q_accumulate,
+            q_threshold,
+        }
+    }
+
+    // returns the published cells; `max_mse` is copied from `threshold` row 0
+    pub fn accumulate(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        references: &[AssignedCell<Fp, Fp>],
+        published: &[Value<u8>],
+        threshold: Column<Instance>,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        layouter.assign_region(
+            || "mse",
+            |mut region| {
+                let mut sum =
+                    region.assign_advice_from_constant(|| "zero", self.sum, 0, Fp::zero())?;
+                let mut cells = Vec::with_capacity(references.len());
+                for (i, reference) in references.iter().enumerate() {
+                    self.q_accumulate.enable(&mut region, i)?;
+                    let reference =
+                        reference.copy_advice(|| "reference", &mut region, self.reference, i)?;
+                    let cell = region.assign_advice(
+                        || "published",
+                        self.published,
+                        i,
+                        || sample(published, i),
+                    )?;
+                    let error = cell.value().copied() - reference.value().copied();
+                    let next = sum.value().copied() + error * error;
+                    sum = region.assign_advice(|| "sum", self.sum, i + 1, || next)?;
+                    cells.push(cell);
+                }
+
+                let row = references.len();
+                self.q_threshold.enable(&mut region, row)?;
+                let max_mse = region.assign_advice_from_instance(
+                    || "max mse",
+                    threshold,
+                    0,
+                    self.max_mse,
+                    row,
+                )?;
+                let slack = max_mse.value().copied()
+                    * Value::known(Fp::from(references.len() as u64))
+                    - sum.value().copied();
+                // slack の下位バイトから順に
+                let bytes = slack.map(|slack| {
+                    let repr = slack.to_repr();
+                    let mut bytes = [0u8; SLACK_BYTES];
+                    bytes.copy_from_slice(&repr.as_ref()[..SLACK_BYTES]);
+                    bytes
+                });
+                for (i, &col) in self.slack.iter().enumerate() {
+                    region.assign_advice(
+                        || "slack byte",
+                        col,
+                        row,
+                        || bytes.map(|bytes| Fp::from(bytes[i] as u64)),
+                    )?;
+                }
+                Ok(cells)
+            },
+        )
+    }
+}
+
+/// The committed original and its `Transform`: the original pixels are copied
+/// into the digest and, for `Transform::Downsample`, into block averages.
+#[derive(Clone, Debug)]
+pub struct ReferenceConfig {
+    // original pixels (one per row), then the blocks of `Transform::Downsample`
+    channels: [Column<Advice>; 4],
+    remainder: Column<Advice>,
+    average: Column<Advice>,
+    // parity of `average` under `HalfEven`
+    parity: Option<Column<Advice>>,
+    q_reduce: Selector,
+    remainders: RemainderTable,
+    digest: DigestConfig,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReferenceWitness {
+    // RGBA8 original, row major, empty without witnesses
+    pub original: Vec<Value<u8>>,
+    // `Transform::Downsample` only, as in `Downsampled`
+    pub average: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u32>>,
+}
+
+impl ReferenceWitness {
+    pub fn new(transform: Transform, origin_buf: &[u8], width: u32, height: u32) -> Self {
+        let (average, remainder) = match transform {
+            Transform::Identity => (Vec::new(), Vec::new()),
+            Transform::Downsample => {
+                let reduced = Downsampler::default().downsample_rgba(origin_buf, width, height);
+                (
+                    reduced.pixels.iter().map(|&v| Value::known(v)).collect(),
+                    reduced
+                        .remainders
+                        .iter()
+                        .map(|&v| Value::known(v))
+                        .collect(),
+                )
+            }
+        };
+        Self {
+            original: origin_buf.iter().map(|&v| Value::known(v)).collect(),
+            average,
+            remainder,
+        }
+    }
+}
+
+// rows of `ReferenceConfig::assign` for an original of `pixels` pixels
+pub(crate) fn reference_rows(pixels: usize, samples: usize) -> usize {
+    digest_rows(pixels) + pixels + samples
+}
+
+impl ReferenceConfig {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let block = (SCALE_FACTOR * SCALE_FACTOR) as u64;
+
+        let channels = [(); 4].map(|_| meta.advice_column());
+        let remainder = meta.advice_column();
+        let average = meta.advice_column();
+        channels
+            .iter()
+            .chain([&average])
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_reduce = meta.complex_selector();
+        let remainders = RemainderTable::configure(meta, rounding(), block as u32);
+        meta.create_gate("reference average", |virtual_cells| {
+            let q_reduce = virtual_cells.query_selector(q_reduce);
+            let offset = Expression::Constant(Fp::from(rounding().offset(block as u32) as u64));
+            let sum = channels.iter().fold(offset, |sum, &col| {
+                sum + virtual_cells.query_advice(col, Rotation::cur())
+            });
+            let average = virtual_cells.query_advice(average, Rotation::cur());
+            let remainder = virtual_cells.query_advice(remainder, Rotation::cur());
+            Constraints::with_selector(
+                q_reduce,
+                [sum - (Expression::Constant(Fp::from(block)) * average + remainder)],
+            )
+        });
+        let parity = remainders.range_check(meta, "remainder", q_reduce, average, remainder);
+
+        Self {
+            channels,
+            remainder,
+            average,
+            parity,
+            q_reduce,
+            remainders,
+            digest: DigestConfig::configure(meta),
+        }
+    }
+
+    // the byte table of the digest, loaded by `assign`
+    pub(crate) fn bytes(&self) -> ByteTable {
+        self.digest.bytes()
+    }
+
+    // the remainder table of the 2 X 2 block averages, loaded by `assign`
+    pub(crate) fn remainders(&self) -> RemainderTable {
+        self.remainders
+    }
+
+    // (digest of the original, RGBA samples of `transform` of the original in raster order)
+    // for an original of WIDTH X HEIGHT
+    #[allow(clippy::type_complexity)]
+    pub fn assign(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        transform: Transform,
+        witness: &ReferenceWitness,
+    ) -> Result<(AssignedCell<Fp, Fp>, Vec<AssignedCell<Fp, Fp>>), Error> {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+
+        let original = layouter.assign_region(
+            || "original",
+            |mut region| {
+                let mut cells = Vec::with_capacity(width * height);
+                for i in 0..width * height {
+                    let mut pixel = Vec::with_capacity(4);
+                    for (c, &col) in self.channels.iter().enumerate() {
+                        let value = sample(&witness.original, 4 * i + c);
+                        pixel.push(region.assign_advice(|| "original", col, i, || value)?);
+                    }
+                    cells.push(<[_; 4]>::try_from(pixel).unwrap());
+                }
+                Ok(cells)
+            },
+        )?;
+        self.digest.load(&mut layouter)?;
+        let digest = self
+            .digest
+            .digest(layouter.namespace(|| "original digest"), &original)?;
+
+        // loaded for `Transform::Identity` too, the lookups are always configured
+        self.remainders.load(&mut layouter)?;
+
+        let references = match transform {
+            Transform::Identity => original.iter().flatten().cloned().collect::<Vec<_>>(),
+            Transform::Downsample => {
+                let (s_width, s_height) = transform.shape(width as u32, height as u32);
+                self.reduce(
+                    &mut layouter,
+                    witness,
+                    &original,
+                    width,
+                    s_width as usize,
+                    s_height as usize,
+                )?
+            }
+        };
+        Ok((digest, references))
+    }
+
+    // block averages of the original cells, in the sample order of the thumbnail
+    fn reduce(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        witness: &ReferenceWitness,
+        original: &[[AssignedCell<Fp, Fp>; 4]],
+        width: usize,
+        s_width: usize,
+        s_height: usize,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let block = SCALE_FACTOR as usize;
+
+        layouter.assign_region(
+            || "reference average",
+            |mut region| {
+                let mut cells = Vec::with_capacity(s_width * s_height * 4);
+                for y in 0..s_height {
+                    for x in 0..s_width {
+                        for c in 0..4 {
+                            let row = (y * s_width + x) * 4 + c;
+                            self.q_reduce.enable(&mut region, row)?;
+                            for (k, &col) in self.channels.iter().enumerate() {
+                                let (dy, dx) = (k / block, k % block);
+                                original[(block * y + dy) * width + block * x + dx][c]
+                                    .copy_advice(|| "block", &mut region, col, row)?;
+                            }
+                            region.assign_advice(
+                                || "remainder",
+                                self.remainder,
+                                row,
+                                || sample(&witness.remainder, row),
+                            )?;
+                            RemainderTable::assign_parity(
+                                &mut region,
+                                self.parity,
+                                row,
+                                witness
+                                    .average
+                                    .get(row)
+                                    .copied()
+                                    .unwrap_or_else(Value::unknown),
+                            )?;
+                            cells.push(region.assign_advice(
+                                || "average",
+                                self.average,
+                                row,
+                                || sample(&witness.average, row),
+                            )?);
+                        }
+                    }
+                }
+                Ok(cells)
+            },
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimilarityConfig {
+    reference: ReferenceConfig,
+    mse: MseConfig,
+    published: Column<Instance>,
+    // row 0: max_mse, row 1: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SimilarityCircuit {
+    pub reference: ReferenceWitness,
+    pub published: Vec<Value<u8>>,
+}
+
+impl Circuit<Fp> for SimilarityCircuit {
+    type Config = SimilarityConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let (_, samples) = similarity_size();
+        let reference = ReferenceConfig::configure(meta);
+        let mse = MseConfig::configure(meta, samples, reference.bytes());
+        let published = meta.instance_column();
+        let statement = meta.instance_column();
+        meta.enable_equality(published);
+        meta.enable_equality(statement);
+
+        SimilarityConfig {
+            reference,
+            mse,
+            published,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let (digest, references) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            *TRANSFORM.get().unwrap(),
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 1)?;
+
+        let published = config.mse.accumulate(
+            layouter.namespace(|| "mse"),
+            &references,
+            &self.published,
+            config.statement,
+        )?;
+        for (i, cell) in published.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.published, i)?;
+        }
+        Ok(())
+    }
+}
+
+fn digest_bytes(digest: Fp) -> [u8; 32] {
+    digest.to_repr()
+}
+
+/// The circuit proving the MSE between `transform` of the RGBA8 original and
+/// `published` is at most `max_mse`, also sets the shape globals; None if the
+/// MSE is larger or the shapes differ.
+pub fn similarity_circuit(
+    transform: Transform,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+    published: &[u8],
+    max_mse: u32,
+) -> Option<SimilarityCircuit> {
+    if origin_buf.len() != (width * height * 4) as usize {
+        return None;
+    }
+    let (reference, _, _) = transform.apply(origin_buf, width, height);
+    let bound = max_mse as u64 * reference.len() as u64;
+    // the slack has to fit in `SLACK_BYTES` bytes
+    if reference.len() != published.len()
+        || bound >= 1 << (8 * SLACK_BYTES)
+        || sum_squared_error(&reference, published) > bound
+    {
+        return None;
+    }
+    assert!(
+        set_similarity_shape(transform, width, height),
+        "the circuit shape is already set for another image size or transform"
+    );
+    Some(SimilarityCircuit {
+        reference: ReferenceWitness::new(transform, origin_buf, width, height),
+        published: published.iter().map(|&v| Value::known(v)).collect(),
+    })
+}
+
+/// [published samples, [max_mse, digest of the original]]
+pub fn similarity_public_input(published: &[u8], max_mse: u32, digest: Fp) -> Vec<Vec<Fp>> {
+    vec![
+        published.iter().map(|&v| Fp::from(v as u64)).collect(),
+        vec![Fp::from(max_mse as u64), digest],
+    ]
+}
+
+/// Proves that the MSE between `transform` of the RGBA8 original and `published`
+/// is at most `max_mse`; returns the proof and the digest of the original
+/// (`Fp::to_repr`), or None if the MSE is larger or the shapes differ.
+pub fn create_similarity_proof(
+    transform: Transform,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    published: &[u8],
+    max_mse: u32,
+) -> Option<(Vec<u8>, [u8; 32])> {
+    let circuit = similarity_circuit(transform, &origin_buf, width, height, published, max_mse)?;
+    let digest = pixels_digest(&origin_buf);
+    let public_input = similarity_public_input(published, max_mse, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = similarity_parameter().get_parameter();
+    let empty_circuit = SimilarityCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    Some((proof, digest_bytes(digest)))
+}
+
+/// Checks a proof of `create_similarity_proof` for an original of `width` X `height`
+/// with digest `digest` against the public image.
+pub fn verify_similarity(
+    transform: Transform,
+    proof: &[u8],
+    width: u32,
+    height: u32,
+    published: &[u8],
+    max_mse: u32,
+    digest: [u8; 32],
+) -> bool {
+    let (s_width, s_height) = transform.shape(width, height);
+    if published.len() != (s_width * s_height * 4) as usize {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_similarity_shape(transform, width, height) {
+        return false;
+    }
+    let public_input = similarity_public_input(published, max_mse, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = similarity_parameter().get_parameter();
+    let empty_circuit = SimilarityCircuit::default();
+    verify_ipa(&params, &empty_circuit, proof, &public_input)
+}
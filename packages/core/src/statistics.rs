@@ -0,0 +1,539 @@
+//! Public statistics of a hidden image: per-channel means or histograms.
+//!
+//! Only the statistic and the digest of the original are instances. Every
+//! channel of a `Transform` of the original is walked one sample per row:
+//! a running sum for the means, and one-hot bin indicators with running counts
+//! for the histogram, the bin being tied to the sample by a `(sample, bin)`
+//! lookup. A mean satisfies `sum + offset = samples * mean + remainder` with
+//! the current `rounding` and `0 <= remainder <= max` shown with byte lookups.
+//! The samples are range checked as bytes by the bin lookup, or by a byte
+//! lookup for the means.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Region, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector, TableColumn,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::pixels_digest,
+    downsample::Rounding,
+    helpers::{compose, ipa_parameter},
+    rounding, set_once,
+    similarity::{
+        reference_rows, set_similarity_shape, ReferenceConfig, ReferenceWitness, Transform,
+        TRANSFORM,
+    },
+    Parameter, HEIGHT, WIDTH,
+};
+
+const CHANNELS: usize = 4;
+// remainders of the means and their gaps to the largest remainder, < 2^24 samples
+const REMAINDER_BYTES: usize = 3;
+pub const MAX_BINS: u32 = 32;
+
+/// What the proof publishes about each RGBA channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Statistic {
+    // one mean per channel
+    Means,
+    // `bins` counts per channel, bin `sample * bins / 256`
+    Histogram { bins: u32 },
+}
+
+impl Statistic {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Statistic::Means => true,
+            Statistic::Histogram { bins } => bins.is_power_of_two() && *bins <= MAX_BINS,
+        }
+    }
+
+    fn bins(&self) -> usize {
+        match self {
+            Statistic::Means => 0,
+            Statistic::Histogram { bins } => *bins as usize,
+        }
+    }
+
+    /// The public values of a RGBA8 image, channel major.
+    pub fn compute(&self, buf: &[u8]) -> Vec<u32> {
+        match self {
+            Statistic::Means => channel_means(buf)
+                .into_iter()
+                .map(|(mean, _)| mean as u32)
+                .collect(),
+            Statistic::Histogram { bins } => histogram(buf, *bins),
+        }
+    }
+}
+
+// 平均は 1 チャンネルのサンプル数で割る
+fn largest_remainder(samples: usize) -> u64 {
+    match rounding() {
+        Rounding::HalfEven if samples % 2 == 0 => samples as u64,
+        _ => (samples as u64).saturating_sub(1),
+    }
+}
+
+/// (mean, remainder) of every channel of a RGBA8 image with the current `rounding`.
+pub fn channel_means(buf: &[u8]) -> [(u8, u32); CHANNELS] {
+    let samples = (buf.len() / CHANNELS) as u32;
+    let mut sums = [0u32; CHANNELS];
+    for pixel in buf.chunks(CHANNELS) {
+        for (sum, &v) in sums.iter_mut().zip(pixel) {
+            *sum += v as u32;
+        }
+    }
+    sums.map(|sum| {
+        if samples == 0 {
+            return (0, 0);
+        }
+        let (mean, remainder) = rounding().divide(sum, samples);
+        (mean as u8, remainder)
+    })
+}
+
+/// Counts of `bins` equal bins per channel of a RGBA8 image, channel major.
+pub fn histogram(buf: &[u8], bins: u32) -> Vec<u32> {
+    let shift = 8 - bins.trailing_zeros();
+    let mut counts = vec![0; CHANNELS * bins as usize];
+    for pixel in buf.chunks(CHANNELS) {
+        for (c, &v) in pixel.iter().enumerate() {
+            counts[c * bins as usize + (v >> shift) as usize] += 1;
+        }
+    }
+    counts
+}
+
+/// Published statistics of a committed original.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicStatistics {
+    pub transform: Transform,
+    pub statistic: Statistic,
+    // shape of the original
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<u32>,
+}
+
+// 回路の形の一部: 公開する統計量
+pub static STATISTIC: OnceCell<Statistic> = OnceCell::new();
+
+// 既に別の形なら false
+pub fn set_statistics_shape(
+    transform: Transform,
+    width: u32,
+    height: u32,
+    statistic: Statistic,
+) -> bool {
+    set_similarity_shape(transform, width, height) & set_once(&STATISTIC, statistic)
+}
+
+// pixels of the transformed original
+fn statistics_pixels() -> usize {
+    let (width, height) = (*WIDTH.get().unwrap() as u32, *HEIGHT.get().unwrap() as u32);
+    let (s_width, s_height) = TRANSFORM.get().unwrap().shape(width, height);
+    (s_width * s_height) as usize
+}
+
+pub static STATISTICS_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn statistics_parameter() -> &'static Parameter {
+    STATISTICS_PARAMETER.get_or_init(|| {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+        let pixels = statistics_pixels();
+        // one region of pixels + 1 rows per channel
+        ipa_parameter(reference_rows(width * height, pixels * CHANNELS) + CHANNELS * (pixels + 1))
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct StatisticsConfig {
+    reference: ReferenceConfig,
+    // one sample of one channel per row, then the totals
+    sample: Column<Advice>,
+    sum: Column<Advice>,
+    indicator: Vec<Column<Advice>>,
+    count: Vec<Column<Advice>>,
+    q_sample: Selector,
+    sample_table: TableColumn,
+    bin_table: TableColumn,
+    mean: Column<Advice>,
+    remainder: [Column<Advice>; REMAINDER_BYTES],
+    gap: [Column<Advice>; REMAINDER_BYTES],
+    q_mean: Selector,
+    // means or histogram, channel major
+    public: Column<Instance>,
+    // row 0: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StatisticsCircuit {
+    pub reference: ReferenceWitness,
+    // RGBA samples of the transformed original
+    pub samples: Vec<Value<u8>>,
+    // means only
+    pub means: [Value<u8>; CHANNELS],
+    pub remainders: [Value<u32>; CHANNELS],
+}
+
+impl Circuit<Fp> for StatisticsCircuit {
+    type Config = StatisticsConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let bins = STATISTIC.get().unwrap().bins();
+        let samples = statistics_pixels();
+
+        // the digest enables a constant column for the zero sums
+        let reference = ReferenceConfig::configure(meta);
+        let sample = meta.advice_column();
+        let sum = meta.advice_column();
+        let indicator = (0..bins).map(|_| meta.advice_column()).collect::<Vec<_>>();
+        let count = (0..bins).map(|_| meta.advice_column()).collect::<Vec<_>>();
+        let mean = meta.advice_column();
+        let remainder = [(); REMAINDER_BYTES].map(|_| meta.advice_column());
+        let gap = [(); REMAINDER_BYTES].map(|_| meta.advice_column());
+        let public = meta.instance_column();
+        let statement = meta.instance_column();
+        [sample, sum, mean]
+            .iter()
+            .chain(&count)
+            .for_each(|&col| meta.enable_equality(col));
+        meta.enable_equality(public);
+        meta.enable_equality(statement);
+
+        let q_sample = meta.complex_selector();
+        let sample_table = meta.lookup_table_column();
+        let bin_table = meta.lookup_table_column();
+        meta.create_gate("channel sum", |virtual_cells| {
+            let q_sample = virtual_cells.query_selector(q_sample);
+            let sample = virtual_cells.query_advice(sample, Rotation::cur());
+            let sum_cur = virtual_cells.query_advice(sum, Rotation::cur());
+            let sum_next = virtual_cells.query_advice(sum, Rotation::next());
+            Constraints::with_selector(q_sample, [sum_next - (sum_cur + sample)])
+        });
+        if bins > 0 {
+            meta.create_gate("histogram bin", |virtual_cells| {
+                let q_sample = virtual_cells.query_selector(q_sample);
+                let one = Expression::Constant(Fp::one());
+                let mut constraints = Vec::with_capacity(2 * bins + 1);
+                let mut total = Expression::Constant(Fp::zero());
+                for (&indicator, &count) in indicator.iter().zip(&count) {
+                    let indicator = virtual_cells.query_advice(indicator, Rotation::cur());
+                    let count_cur = virtual_cells.query_advice(count, Rotation::cur());
+                    let count_next = virtual_cells.query_advice(count, Rotation::next());
+                    constraints.push(indicator.clone() * (one.clone() - indicator.clone()));
+                    constraints.push(count_next - (count_cur + indicator.clone()));
+                    total = total + indicator;
+                }
+                constraints.push(total - one);
+                Constraints::with_selector(q_sample, constraints)
+            });
+            meta.lookup("sample bin", |virtual_cells| {
+                let q_sample = virtual_cells.query_selector(q_sample);
+                let sample = virtual_cells.query_advice(sample, Rotation::cur());
+                let bin = indicator.iter().enumerate().fold(
+                    Expression::Constant(Fp::zero()),
+                    |bin, (b, &col)| {
+                        bin + virtual_cells.query_advice(col, Rotation::cur())
+                            * Expression::Constant(Fp::from(b as u64))
+                    },
+                );
+                vec![
+                    (q_sample.clone() * sample, sample_table),
+                    (q_sample * bin, bin_table),
+                ]
+            });
+        }
+
+        let q_mean = meta.complex_selector();
+        meta.create_gate("channel mean", |virtual_cells| {
+            let q_mean = virtual_cells.query_selector(q_mean);
+            let constant = |v: u64| Expression::Constant(Fp::from(v));
+            let offset = constant(rounding().offset(samples as u32) as u64);
+            let sum = virtual_cells.query_advice(sum, Rotation::cur());
+            let mean = virtual_cells.query_advice(mean, Rotation::cur());
+            let remainder = remainder.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            let gap = gap.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            Constraints::with_selector(
+                q_mean,
+                [
+                    sum + offset - (constant(samples as u64) * mean + compose(&remainder)),
+                    constant(largest_remainder(samples)) - (compose(&remainder) + compose(&gap)),
+                ],
+            )
+        });
+        let bytes = reference.bytes();
+        // the bin lookup range checks the samples of a histogram
+        if bins == 0 {
+            bytes.range_check(meta, "sample byte", q_sample, &[sample]);
+        }
+        bytes.range_check(meta, "mean remainder byte", q_mean, &remainder);
+        bytes.range_check(meta, "mean remainder byte", q_mean, &gap);
+
+        StatisticsConfig {
+            reference,
+            sample,
+            sum,
+            indicator,
+            count,
+            q_sample,
+            sample_table,
+            bin_table,
+            mean,
+            remainder,
+            gap,
+            q_mean,
+            public,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let statistic = *STATISTIC.get().unwrap();
+        let bins = statistic.bins();
+        let pixels = statistics_pixels();
+
+        let (digest, references) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            *TRANSFORM.get().unwrap(),
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+
+        // 統計量によらず両方の表を埋める
+        layouter.assign_table(
+            || "sample bins",
+            |mut table| {
+                let shift = 8 - (bins.max(1) as u32).trailing_zeros();
+                for v in 0..256u64 {
+                    table.assign_cell(
+                        || "sample",
+                        config.sample_table,
+                        v as usize,
+                        || Value::known(Fp::from(v)),
+                    )?;
+                    table.assign_cell(
+                        || "bin",
+                        config.bin_table,
+                        v as usize,
+                        || Value::known(Fp::from(v >> shift)),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        for c in 0..CHANNELS {
+            let totals = layouter.assign_region(
+                || format!("channel {}", c),
+                |mut region| self.assign_channel(&config, &mut region, &references, c, pixels),
+            )?;
+            match statistic {
+                Statistic::Means => {
+                    layouter.constrain_instance(totals[0].cell(), config.public, c)?
+                }
+                Statistic::Histogram { .. } => {
+                    for (b, count) in totals.iter().enumerate() {
+                        layouter.constrain_instance(count.cell(), config.public, c * bins + b)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StatisticsCircuit {
+    // returns the mean, or the counts of the bins, of channel `c`
+    fn assign_channel(
+        &self,
+        config: &StatisticsConfig,
+        region: &mut Region<'_, Fp>,
+        references: &[AssignedCell<Fp, Fp>],
+        c: usize,
+        pixels: usize,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let statistic = *STATISTIC.get().unwrap();
+        let bins = statistic.bins();
+        let shift = 8 - (bins.max(1) as u32).trailing_zeros();
+
+        let zero = |region: &mut Region<'_, Fp>, col| {
+            region.assign_advice_from_constant(|| "zero", col, 0, Fp::zero())
+        };
+        let mut sum = zero(region, config.sum)?;
+        let mut counts = config
+            .count
+            .iter()
+            .map(|&col| zero(region, col))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for i in 0..pixels {
+            config.q_sample.enable(region, i)?;
+            let sample =
+                references[CHANNELS * i + c].copy_advice(|| "sample", region, config.sample, i)?;
+            let next = sum.value().copied() + sample.value().copied();
+            sum = region.assign_advice(|| "sum", config.sum, i + 1, || next)?;
+
+            let value = self
+                .samples
+                .get(CHANNELS * i + c)
+                .copied()
+                .unwrap_or_else(Value::unknown);
+            for (b, (&indicator, count)) in
+                config.indicator.iter().zip(counts.iter_mut()).enumerate()
+            {
+                let hit = value.map(|v| Fp::from(((v >> shift) as usize == b) as u64));
+                region.assign_advice(|| "indicator", indicator, i, || hit)?;
+                let next = count.value().copied() + hit;
+                *count = region.assign_advice(|| "count", config.count[b], i + 1, || next)?;
+            }
+        }
+
+        if statistic != Statistic::Means {
+            return Ok(counts);
+        }
+        config.q_mean.enable(region, pixels)?;
+        let remainder = self.remainders[c];
+        // a remainder beyond the largest fails the gate
+        let gap = remainder.map(|r| largest_remainder(pixels).saturating_sub(r as u64));
+        for (columns, value) in [
+            (&config.remainder, remainder.map(|r| r as u64)),
+            (&config.gap, gap),
+        ] {
+            for (i, &col) in columns.iter().enumerate() {
+                region.assign_advice(
+                    || "byte",
+                    col,
+                    pixels,
+                    || value.map(|v| Fp::from((v >> (8 * i)) & 0xff)),
+                )?;
+            }
+        }
+        let mean = region.assign_advice(
+            || "mean",
+            config.mean,
+            pixels,
+            || self.means[c].map(|v| Fp::from(v as u64)),
+        )?;
+        Ok(vec![mean])
+    }
+}
+
+/// The circuit computing `statistic` of `transform` of the RGBA8 original and the
+/// public statistics, also sets the shape globals.
+pub fn statistics_circuit(
+    transform: Transform,
+    statistic: Statistic,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+) -> (StatisticsCircuit, PublicStatistics) {
+    assert!(statistic.is_valid());
+    assert!(
+        set_statistics_shape(transform, width, height, statistic),
+        "the circuit shape is already set for another image size, transform or statistic"
+    );
+    let (samples, _, _) = transform.apply(origin_buf, width, height);
+    assert!(samples.len() / CHANNELS < 1 << (8 * REMAINDER_BYTES));
+
+    let means = channel_means(&samples);
+    let statistics = PublicStatistics {
+        transform,
+        statistic,
+        width,
+        height,
+        values: statistic.compute(&samples),
+    };
+    let circuit = StatisticsCircuit {
+        reference: ReferenceWitness::new(transform, origin_buf, width, height),
+        samples: samples.iter().map(|&v| Value::known(v)).collect(),
+        means: means.map(|(mean, _)| Value::known(mean)),
+        remainders: means.map(|(_, remainder)| Value::known(remainder)),
+    };
+    (circuit, statistics)
+}
+
+/// [public values, [digest of the original]]
+pub fn statistics_public_input(statistics: &PublicStatistics, digest: Fp) -> Vec<Vec<Fp>> {
+    vec![public_values(statistics), vec![digest]]
+}
+
+/// Computes `statistic` of `transform` of the RGBA8 original and proves it; returns
+/// the public statistics, the proof and the digest of the original (`Fp::to_repr`).
+pub fn create_statistics_proof(
+    transform: Transform,
+    statistic: Statistic,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (PublicStatistics, Vec<u8>, [u8; 32]) {
+    let (circuit, statistics) =
+        statistics_circuit(transform, statistic, &origin_buf, width, height);
+    let digest = pixels_digest(&origin_buf);
+    let public_input = statistics_public_input(&statistics, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = statistics_parameter().get_parameter();
+    let empty_circuit = StatisticsCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    (statistics, proof, digest.to_repr())
+}
+
+fn public_values(statistics: &PublicStatistics) -> Vec<Fp> {
+    statistics
+        .values
+        .iter()
+        .map(|&v| Fp::from(v as u64))
+        .collect()
+}
+
+/// Checks that `statistics` are those of the original with digest `digest`.
+pub fn verify_statistics(proof: &[u8], statistics: &PublicStatistics, digest: [u8; 32]) -> bool {
+    let statistic = statistics.statistic;
+    let expected = match statistic {
+        Statistic::Means => CHANNELS,
+        Statistic::Histogram { bins } => CHANNELS * bins as usize,
+    };
+    if !statistic.is_valid() || statistics.values.len() != expected {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_statistics_shape(
+        statistics.transform,
+        statistics.width,
+        statistics.height,
+        statistic,
+    ) {
+        return false;
+    }
+    let public_input = statistics_public_input(statistics, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = statistics_parameter().get_parameter();
+    let empty_circuit = StatisticsCircuit::default();
+    verify_ipa(&params, &empty_circuit, proof, &public_input)
+}
@@ -0,0 +1,493 @@
+//! Tiled proving for photos far larger than a single `MyCircuit`.
+//!
+//! The original is split into block aligned `tile_width` X `tile_height` tiles,
+//! edge tiles are padded with transparent black. Every tile proves its own
+//! reduction and exposes a Poseidon commitment of its original pixels, and the
+//! commitments are the leaves of a single Merkle root of the original. The
+//! verifier checks every tile proof, the root, and that the tile thumbnails
+//! reassemble into the published thumbnail.
+
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    halo2curves::{
+        group::ff::PrimeField,
+        pasta::{EqAffine, Fp},
+    },
+    plonk::{Circuit, Column, ConstraintSystem, Error, Instance, ProvingKey},
+    poly::ipa::commitment::ParamsIPA,
+};
+use image::EncodableLayout;
+use once_cell::sync::OnceCell;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{keygen_ipa, prove_ipa_with_pk, verify_ipa_batch},
+    digest::{digest_rows, pixels_digest, DigestConfig},
+    disclosure::Disclosure,
+    helpers::ipa_parameter,
+    img_circuit, img_public_input,
+    merkle::MerkleTree,
+    progress::{enter, CancelToken, Cancelled, NoProgress, Phase, ProgressSink},
+    set_shape, Config, MyCircuit, Parameter, HEIGHT, SCALE_FACTOR, WIDTH,
+};
+
+// 32 X 32 tiles keep the commitment of a tile within 2^17 rows
+pub const DEFAULT_TILE_SIZE: u32 = 32;
+
+// every tile has the same shape, so one parameter (k follows the tile size) serves all of them
+pub static TILE_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn tile_parameter() -> &'static Parameter {
+    TILE_PARAMETER.get_or_init(|| {
+        let pixels = *WIDTH.get().unwrap() * *HEIGHT.get().unwrap();
+        ipa_parameter(digest_rows(pixels))
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileLayout {
+    // shape of the original picture
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+impl TileLayout {
+    pub fn new(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Self {
+        let layout = TileLayout {
+            width,
+            height,
+            tile_width,
+            tile_height,
+        };
+        assert!(layout.is_valid());
+        layout
+    }
+
+    /// Whether the original is not empty and the tile sides are positive multiples of
+    /// `SCALE_FACTOR`; a deserialized layout is checked before any other method.
+    pub fn is_valid(&self) -> bool {
+        // tiles must not split a block, and the gate consumes two original rows at a time
+        self.width > 0
+            && self.height > 0
+            && self.tile_width > 0
+            && self.tile_width % SCALE_FACTOR == 0
+            && self.tile_height > 0
+            && self.tile_height % SCALE_FACTOR == 0
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.width / self.tile_width + (self.width % self.tile_width != 0) as u32
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.height / self.tile_height + (self.height % self.tile_height != 0) as u32
+    }
+
+    // tiles in raster order
+    pub fn tiles(&self) -> impl Iterator<Item = (u32, u32)> {
+        let columns = self.columns();
+        (0..self.rows()).flat_map(move |row| (0..columns).map(move |column| (column, row)))
+    }
+
+    pub fn s_width(&self) -> u32 {
+        self.width / SCALE_FACTOR
+    }
+
+    pub fn s_height(&self) -> u32 {
+        self.height / SCALE_FACTOR
+    }
+
+    pub fn s_tile_width(&self) -> u32 {
+        self.tile_width / SCALE_FACTOR
+    }
+
+    pub fn s_tile_height(&self) -> u32 {
+        self.tile_height / SCALE_FACTOR
+    }
+
+    /// RGBA8 pixels of one tile, pixels outside the original are transparent black.
+    pub fn tile(&self, buf: &[u8], column: u32, row: u32) -> Vec<u8> {
+        let mut tile = vec![0; (self.tile_width * self.tile_height * 4) as usize];
+        for y in 0..self.tile_height {
+            let origin_y = row * self.tile_height + y;
+            if origin_y >= self.height {
+                break;
+            }
+            let origin_x = column * self.tile_width;
+            let len = self.tile_width.min(self.width - origin_x);
+            let src = ((origin_y * self.width + origin_x) * 4) as usize;
+            let dst = (y * self.tile_width * 4) as usize;
+            tile[dst..dst + (len * 4) as usize]
+                .copy_from_slice(&buf[src..src + (len * 4) as usize]);
+        }
+        tile
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileProof {
+    pub column: u32,
+    pub row: u32,
+    // Poseidon commitment of the tile's original pixels (`Fp::to_repr`)
+    pub commitment: [u8; 32],
+    // reduced tile, RGBA8; edge tiles include pixels of the padding
+    pub thumbnail: Vec<u8>,
+    pub proof: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TiledProof {
+    pub layout: TileLayout,
+    // Merkle root of the tile commitments (`Fp::to_repr`)
+    pub root: [u8; 32],
+    pub tiles: Vec<TileProof>,
+}
+
+impl TiledProof {
+    /// Reveals the original pixels of one tile with an inclusion proof against `root`.
+    pub fn disclose_tile(&self, origin_buf: &[u8], column: u32, row: u32) -> Disclosure {
+        let layout = self.layout;
+        assert!(column < layout.columns() && row < layout.rows());
+        let commitments = self
+            .tiles
+            .iter()
+            .map(|tile| Fp::from_repr(tile.commitment).unwrap())
+            .collect::<Vec<_>>();
+        Disclosure {
+            pixels: layout.tile(origin_buf, column, row),
+            path: MerkleTree::new(&commitments).path((row * layout.columns() + column) as usize),
+        }
+    }
+
+    /// Checks a disclosed tile against `root`.
+    pub fn verify_disclosure(&self, disclosure: &Disclosure) -> bool {
+        let layout = self.layout;
+        layout.is_valid()
+            && disclosure.verify(
+                &self.root,
+                layout.tile_width as usize * layout.tile_height as usize * 4,
+                layout.columns() as usize * layout.rows() as usize,
+            )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TileConfig {
+    image: Config,
+    digest: DigestConfig,
+    commitment: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TileCircuit(pub MyCircuit<Fp>);
+
+impl Circuit<Fp> for TileCircuit {
+    type Config = TileConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let image = MyCircuit::<Fp>::configure_columns(meta, true, true);
+        let digest = DigestConfig::configure(meta);
+        // after the thumbnail columns, so the commitment is the last instance column
+        let commitment = meta.instance_column();
+        meta.enable_equality(commitment);
+
+        TileConfig {
+            image,
+            digest,
+            commitment,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let cells = self.0.assign_rows(&config.image, &mut layouter)?;
+        config.image.expose_reduced(&mut layouter, &cells.reduced)?;
+
+        config.digest.load(&mut layouter)?;
+        let commitment = config
+            .digest
+            .digest(layouter.namespace(|| "tile commitment"), &cells.original)?;
+        layouter.constrain_instance(commitment.cell(), config.commitment, 0)
+    }
+}
+
+fn tile_public_input(
+    thumbnail: &[u8],
+    layout: &TileLayout,
+    commitment: Fp,
+) -> Option<Vec<Vec<Fp>>> {
+    let thumbnail = image::RgbaImage::from_raw(
+        layout.s_tile_width(),
+        layout.s_tile_height(),
+        thumbnail.to_vec(),
+    )?;
+    let mut public_input = img_public_input::<Fp>(&thumbnail);
+    public_input.push(vec![commitment]);
+    Some(public_input)
+}
+
+// every tile is one `MyCircuit` of tile_width X tile_height, false if another shape is set
+fn set_tile_shape(layout: &TileLayout) -> bool {
+    set_shape(layout.tile_width, layout.tile_height)
+}
+
+// (witness, thumbnail of the tile, commitment of its original pixels)
+fn tile_witness(
+    layout: &TileLayout,
+    origin_buf: &[u8],
+    column: u32,
+    row: u32,
+) -> (TileCircuit, Vec<u8>, Fp) {
+    let tile = layout.tile(origin_buf, column, row);
+    let commitment = pixels_digest(&tile);
+    let (circuit, thumbnail) = img_circuit::<Fp>(tile, layout.tile_width, layout.tile_height);
+    (
+        TileCircuit(circuit),
+        thumbnail.as_bytes().to_vec(),
+        commitment,
+    )
+}
+
+/// Witness and public input (thumbnail columns, then the commitment) of one tile,
+/// e.g. for `MockProver`.
+pub fn tile_circuit(
+    layout: &TileLayout,
+    origin_buf: &[u8],
+    column: u32,
+    row: u32,
+) -> (TileCircuit, Vec<Vec<Fp>>) {
+    assert!(set_tile_shape(layout), "the circuit shape is already set");
+    let (circuit, thumbnail, commitment) = tile_witness(layout, origin_buf, column, row);
+    let public_input = tile_public_input(&thumbnail, layout, commitment).unwrap();
+    (circuit, public_input)
+}
+
+fn prove_tile(
+    layout: &TileLayout,
+    origin_buf: &[u8],
+    (column, row): (u32, u32),
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    progress: &(impl ProgressSink + Sync),
+    cancel: &CancelToken,
+) -> Result<(Fp, TileProof), Cancelled> {
+    let total = layout.columns() * layout.rows();
+    let index = row * layout.columns() + column;
+    enter(progress, cancel, Phase::Tile { index, total })?;
+
+    let (circuit, thumbnail, commitment) = tile_witness(layout, origin_buf, column, row);
+    let public_input = tile_public_input(&thumbnail, layout, commitment).unwrap();
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let proof = prove_ipa_with_pk(params, pk, circuit, &public_input);
+
+    let tile = TileProof {
+        column,
+        row,
+        commitment: commitment.to_repr(),
+        thumbnail,
+        proof,
+    };
+    Ok((commitment, tile))
+}
+
+#[cfg(not(feature = "parallel"))]
+fn prove_tiles(
+    layout: &TileLayout,
+    origin_buf: &[u8],
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    progress: &(impl ProgressSink + Sync),
+    cancel: &CancelToken,
+) -> Result<Vec<(Fp, TileProof)>, Cancelled> {
+    layout
+        .tiles()
+        .map(|tile| prove_tile(layout, origin_buf, tile, params, pk, progress, cancel))
+        .collect()
+}
+
+// tiles are independent, collecting an indexed parallel iterator keeps the raster order
+#[cfg(feature = "parallel")]
+fn prove_tiles(
+    layout: &TileLayout,
+    origin_buf: &[u8],
+    params: &ParamsIPA<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    progress: &(impl ProgressSink + Sync),
+    cancel: &CancelToken,
+) -> Result<Vec<(Fp, TileProof)>, Cancelled> {
+    layout
+        .tiles()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|tile| prove_tile(layout, origin_buf, tile, params, pk, progress, cancel))
+        .collect()
+}
+
+pub fn create_tiled_proof(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+) -> TiledProof {
+    create_tiled_proof_with_progress(
+        origin_buf,
+        width,
+        height,
+        tile_width,
+        tile_height,
+        &NoProgress,
+        &CancelToken::new(),
+    )
+    .expect("a fresh token is never cancelled")
+}
+
+/// Reports keygen and every tile to `progress`, stops before the next tile once `cancel` is set.
+pub fn create_tiled_proof_with_progress(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    progress: &(impl ProgressSink + Sync),
+    cancel: &CancelToken,
+) -> Result<TiledProof, Cancelled> {
+    let layout = TileLayout::new(width, height, tile_width, tile_height);
+    assert!(
+        set_tile_shape(&layout),
+        "the circuit shape is already set for another tile size"
+    );
+
+    enter(progress, cancel, Phase::Keygen)?;
+    let params = &tile_parameter().parameter;
+    let pk = keygen_ipa(params, &TileCircuit::default());
+
+    let (commitments, tiles): (Vec<_>, Vec<_>) =
+        prove_tiles(&layout, &origin_buf, params, &pk, progress, cancel)?
+            .into_iter()
+            .unzip();
+    progress.phase(Phase::Done);
+
+    Ok(TiledProof {
+        layout,
+        root: MerkleTree::new(&commitments).root().to_repr(),
+        tiles,
+    })
+}
+
+/// Same as `create_tiled_proof` on a pool of `threads` workers (0: one per core).
+/// halo2's own parallel FFTs and MSMs run on the same pool, so `threads` bounds the whole proof.
+#[cfg(feature = "parallel")]
+pub fn create_tiled_proof_with_threads(
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    threads: usize,
+) -> TiledProof {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("thread pool should build");
+    pool.install(|| create_tiled_proof(origin_buf, width, height, tile_width, tile_height))
+}
+
+/// Checks every tile proof, the Merkle root and that the tiles reassemble into the RGBA8
+/// thumbnail `small_buf` of `s_width` X `s_height`.
+pub fn verify_tiled_proof(
+    tiled: &TiledProof,
+    small_buf: &[u8],
+    s_width: u32,
+    s_height: u32,
+) -> bool {
+    // the layout is deserialized, nothing below may divide by zero or split a block
+    let layout = tiled.layout;
+    if !layout.is_valid() || (layout.s_width(), layout.s_height()) != (s_width, s_height) {
+        return false;
+    }
+    let (s_tile_width, s_tile_height) = (layout.s_tile_width(), layout.s_tile_height());
+    if small_buf.len() != s_width as usize * s_height as usize * 4 {
+        return false;
+    }
+    if !layout
+        .tiles()
+        .eq(tiled.tiles.iter().map(|tile| (tile.column, tile.row)))
+    {
+        return false;
+    }
+
+    let mut commitments = Vec::new();
+    for tile in &tiled.tiles {
+        match Option::<Fp>::from(Fp::from_repr(tile.commitment)) {
+            Some(commitment) => commitments.push(commitment),
+            None => return false,
+        }
+    }
+    if MerkleTree::new(&commitments).root().to_repr() != tiled.root {
+        return false;
+    }
+
+    // reassemble the thumbnail, padding pixels of edge tiles fall outside of it
+    let mut assembled = vec![0; small_buf.len()];
+    for tile in &tiled.tiles {
+        if tile.thumbnail.len() != s_tile_width as usize * s_tile_height as usize * 4 {
+            return false;
+        }
+        for y in 0..s_tile_height {
+            let s_y = tile.row * s_tile_height + y;
+            if s_y >= s_height {
+                break;
+            }
+            let s_x = tile.column * s_tile_width;
+            if s_x >= s_width {
+                continue;
+            }
+            let len = s_tile_width.min(s_width - s_x);
+            let src = (y * s_tile_width * 4) as usize;
+            let dst = ((s_y * s_width + s_x) * 4) as usize;
+            assembled[dst..dst + (len * 4) as usize]
+                .copy_from_slice(&tile.thumbnail[src..src + (len * 4) as usize]);
+        }
+    }
+    if assembled != small_buf {
+        return false;
+    }
+
+    if !set_tile_shape(&layout) {
+        return false;
+    }
+    let public_inputs = tiled
+        .tiles
+        .iter()
+        .zip(commitments)
+        .map(|(tile, commitment)| tile_public_input(&tile.thumbnail, &layout, commitment).unwrap())
+        .collect::<Vec<_>>();
+    let batch = tiled
+        .tiles
+        .iter()
+        .zip(public_inputs.iter())
+        .map(|(tile, public_input)| {
+            (
+                &tile.proof[..],
+                public_input.iter().map(|c| &c[..]).collect(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    verify_ipa_batch(&tile_parameter().parameter, &TileCircuit::default(), &batch)
+}
@@ -0,0 +1,37 @@
+//! Optional `tracing` instrumentation.
+//!
+//! With the `tracing` feature every proving phase (witness, keygen_vk,
+//! keygen_pk, create_proof, verify) runs in an `INFO` span, so a subscriber
+//! can record its busy time (e.g. `tracing_subscriber::fmt` with
+//! `FmtSpan::CLOSE`). Without the feature the macros expand to nothing and
+//! the library prints nothing.
+
+// enters a span until the end of the enclosing block
+macro_rules! enter_span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name).entered();
+    };
+}
+
+// evaluates `$body` inside a span
+macro_rules! in_span {
+    ($name:literal, $body:expr) => {{
+        enter_span!($name);
+        $body
+    }};
+}
+
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
@@ -0,0 +1,409 @@
+//! Watermark (logo overlay) compositing over a committed original.
+//!
+//! A public RGBA8 watermark is placed at a public position over a `Transform`
+//! of the original (the original itself, or its thumbnail) and blended with
+//! its own alpha as 8-bit fixed point: `(base * (255 - a) + mark * a) / 255`
+//! with the current `rounding`. The alpha channel of the base is kept. The
+//! circuit checks `base * (255 - a) + mark * a + offset = 255 * out + remainder`
+//! with a lookup on the remainder for every covered pixel and copies the other
+//! samples, so the published image is tied to the digest of the original.
+//! The watermark is part of the circuit (fixed columns), like a blur kernel.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Fixed, Instance,
+        Selector, TableColumn,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::pixels_digest,
+    helpers::{ipa_parameter, sample},
+    rounding, set_once,
+    similarity::{
+        reference_rows, set_similarity_shape, ReferenceConfig, ReferenceWitness, Transform,
+        TRANSFORM,
+    },
+    Parameter, HEIGHT, WIDTH,
+};
+
+// alpha の固定小数点の分母
+const OPAQUE: u32 = 255;
+
+/// RGBA8 watermark placed with its top left corner at (`x`, `y`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watermark {
+    pub width: u32,
+    pub height: u32,
+    // RGBA8, row major
+    pub pixels: Vec<u8>,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Watermark {
+    pub fn new(pixels: Vec<u8>, width: u32, height: u32, x: u32, y: u32) -> Self {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        Self {
+            width,
+            height,
+            pixels,
+            x,
+            y,
+        }
+    }
+
+    /// Whether the watermark lies inside a `width` X `height` image.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.x + self.width <= width && self.y + self.height <= height
+    }
+
+    // (index of the covered pixel in the base, watermark pixel), raster order
+    fn covered(&self, base_width: u32) -> impl Iterator<Item = (usize, &[u8])> + '_ {
+        self.pixels.chunks(4).enumerate().map(move |(i, mark)| {
+            let (dx, dy) = (i as u32 % self.width, i as u32 / self.width);
+            (((self.y + dy) * base_width + self.x + dx) as usize, mark)
+        })
+    }
+}
+
+// (blended sample, remainder)
+fn blend(base: u8, mark: u8, alpha: u8) -> (u8, u32) {
+    let (alpha, sum) = (alpha as u32, base as u32 * (OPAQUE - alpha as u32));
+    let (quotient, remainder) = rounding().divide(sum + mark as u32 * alpha, OPAQUE);
+    (quotient as u8, remainder)
+}
+
+// (composited RGBA8 buffer, remainders of the covered pixels, 3 per pixel)
+fn overlay_with_remainders(buf: &[u8], width: u32, watermark: &Watermark) -> (Vec<u8>, Vec<u32>) {
+    let mut out = buf.to_vec();
+    let mut remainders = Vec::with_capacity(watermark.pixels.len() / 4 * 3);
+    for (index, mark) in watermark.covered(width) {
+        for c in 0..3 {
+            let (sample, remainder) = blend(buf[4 * index + c], mark[c], mark[3]);
+            out[4 * index + c] = sample;
+            remainders.push(remainder);
+        }
+    }
+    (out, remainders)
+}
+
+/// `watermark` blended over a RGBA8 image of the given width.
+pub fn overlay(buf: &[u8], width: u32, watermark: &Watermark) -> Vec<u8> {
+    overlay_with_remainders(buf, width, watermark).0
+}
+
+/// (RGBA8 buffer, width, height) of `watermark` over `transform` of the original.
+pub fn composite(
+    transform: Transform,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+    watermark: &Watermark,
+) -> (Vec<u8>, u32, u32) {
+    let (base, s_width, s_height) = transform.apply(origin_buf, width, height);
+    (overlay(&base, s_width, watermark), s_width, s_height)
+}
+
+// 回路の形の一部: 透かしと位置
+pub static WATERMARK: OnceCell<Watermark> = OnceCell::new();
+
+// 既に別の形なら false
+pub fn set_watermark_shape(
+    transform: Transform,
+    width: u32,
+    height: u32,
+    watermark: &Watermark,
+) -> bool {
+    set_similarity_shape(transform, width, height) & set_once(&WATERMARK, watermark.clone())
+}
+
+pub static WATERMARK_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn watermark_parameter() -> &'static Parameter {
+    WATERMARK_PARAMETER.get_or_init(|| {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+        let (s_width, s_height) = TRANSFORM.get().unwrap().shape(width as u32, height as u32);
+        let samples = s_width as usize * s_height as usize * 4;
+        let covered = WATERMARK.get().unwrap().pixels.len() / 4;
+        ipa_parameter(reference_rows(width * height, samples) + covered)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct WatermarkConfig {
+    reference: ReferenceConfig,
+    // one covered pixel per row
+    base: [Column<Advice>; 3],
+    mark: [Column<Fixed>; 3],
+    alpha: Column<Fixed>,
+    out: [Column<Advice>; 3],
+    remainder: [Column<Advice>; 3],
+    q_blend: Selector,
+    remainder_table: TableColumn,
+    // composited RGBA samples, raster order
+    published: Column<Instance>,
+    // row 0: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WatermarkCircuit {
+    pub reference: ReferenceWitness,
+    // 3 per covered pixel, raster order of the watermark
+    pub out: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u32>>,
+}
+
+impl Circuit<Fp> for WatermarkCircuit {
+    type Config = WatermarkConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let reference = ReferenceConfig::configure(meta);
+        let base = [(); 3].map(|_| meta.advice_column());
+        let mark = [(); 3].map(|_| meta.fixed_column());
+        let alpha = meta.fixed_column();
+        let out = [(); 3].map(|_| meta.advice_column());
+        let remainder = [(); 3].map(|_| meta.advice_column());
+        let published = meta.instance_column();
+        let statement = meta.instance_column();
+        base.iter()
+            .chain(out.iter())
+            .for_each(|&col| meta.enable_equality(col));
+        meta.enable_equality(published);
+        meta.enable_equality(statement);
+
+        let q_blend = meta.complex_selector();
+        let remainder_table = meta.lookup_table_column();
+        meta.create_gate("blend watermark", |virtual_cells| {
+            let q_blend = virtual_cells.query_selector(q_blend);
+            let opaque = Expression::Constant(Fp::from(OPAQUE as u64));
+            let offset = Expression::Constant(Fp::from(rounding().offset(OPAQUE) as u64));
+            let alpha = virtual_cells.query_fixed(alpha, Rotation::cur());
+            let constraints = (0..3)
+                .map(|c| {
+                    let base = virtual_cells.query_advice(base[c], Rotation::cur());
+                    let mark = virtual_cells.query_fixed(mark[c], Rotation::cur());
+                    let out = virtual_cells.query_advice(out[c], Rotation::cur());
+                    let remainder = virtual_cells.query_advice(remainder[c], Rotation::cur());
+                    base * (opaque.clone() - alpha.clone()) + mark * alpha.clone() + offset.clone()
+                        - (opaque.clone() * out + remainder)
+                })
+                .collect::<Vec<_>>();
+            Constraints::with_selector(q_blend, constraints)
+        });
+        for &col in &remainder {
+            meta.lookup("blend remainder", |virtual_cells| {
+                let q_blend = virtual_cells.query_selector(q_blend);
+                let remainder = virtual_cells.query_advice(col, Rotation::cur());
+                vec![(q_blend * remainder, remainder_table)]
+            });
+        }
+
+        WatermarkConfig {
+            reference,
+            base,
+            mark,
+            alpha,
+            out,
+            remainder,
+            q_blend,
+            remainder_table,
+            published,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let width = *WIDTH.get().unwrap() as u32;
+        let transform = *TRANSFORM.get().unwrap();
+        let watermark = WATERMARK.get().unwrap();
+        let (s_width, _) = transform.shape(width, *HEIGHT.get().unwrap() as u32);
+
+        let (digest, mut published) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            transform,
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+
+        // 255 で割った余り、奇数なので HalfEven でも divisor にはならない
+        layouter.assign_table(
+            || "blend remainder",
+            |mut table| {
+                for v in 0..OPAQUE as usize {
+                    table.assign_cell(
+                        || "remainder",
+                        config.remainder_table,
+                        v,
+                        || Value::known(Fp::from(v as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        let blended: Vec<(usize, Vec<AssignedCell<Fp, Fp>>)> = layouter.assign_region(
+            || "blend watermark",
+            |mut region| {
+                let mut cells = Vec::with_capacity(watermark.pixels.len() / 4);
+                for (row, (index, mark)) in watermark.covered(s_width).enumerate() {
+                    config.q_blend.enable(&mut region, row)?;
+                    region.assign_fixed(
+                        || "alpha",
+                        config.alpha,
+                        row,
+                        || Value::known(Fp::from(mark[3] as u64)),
+                    )?;
+                    let mut out = Vec::with_capacity(3);
+                    for c in 0..3 {
+                        published[4 * index + c].copy_advice(
+                            || "base",
+                            &mut region,
+                            config.base[c],
+                            row,
+                        )?;
+                        region.assign_fixed(
+                            || "mark",
+                            config.mark[c],
+                            row,
+                            || Value::known(Fp::from(mark[c] as u64)),
+                        )?;
+                        region.assign_advice(
+                            || "remainder",
+                            config.remainder[c],
+                            row,
+                            || sample(&self.remainder, 3 * row + c),
+                        )?;
+                        out.push(region.assign_advice(
+                            || "out",
+                            config.out[c],
+                            row,
+                            || sample(&self.out, 3 * row + c),
+                        )?);
+                    }
+                    cells.push((index, out));
+                }
+                Ok(cells)
+            },
+        )?;
+
+        // 透かしの下のピクセルは合成した値、それ以外は元のまま公開する
+        for (index, out) in blended {
+            for (c, cell) in out.into_iter().enumerate() {
+                published[4 * index + c] = cell;
+            }
+        }
+        for (i, cell) in published.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.published, i)?;
+        }
+        Ok(())
+    }
+}
+
+/// The circuit blending `watermark` over `transform` of the RGBA8 original and the
+/// composited image, also sets the shape globals; None if the watermark does not fit.
+pub fn watermark_circuit(
+    transform: Transform,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+    watermark: &Watermark,
+) -> Option<(WatermarkCircuit, Vec<u8>)> {
+    let (base, s_width, s_height) = transform.apply(origin_buf, width, height);
+    if !watermark.fits(s_width, s_height) {
+        return None;
+    }
+    assert!(
+        set_watermark_shape(transform, width, height, watermark),
+        "the circuit shape is already set for another image size, transform or watermark"
+    );
+
+    let (composited, remainders) = overlay_with_remainders(&base, s_width, watermark);
+    let out = watermark
+        .covered(s_width)
+        .flat_map(|(index, _)| composited[4 * index..4 * index + 3].to_vec())
+        .map(Value::known)
+        .collect();
+    let circuit = WatermarkCircuit {
+        reference: ReferenceWitness::new(transform, origin_buf, width, height),
+        out,
+        remainder: remainders.into_iter().map(Value::known).collect(),
+    };
+    Some((circuit, composited))
+}
+
+/// [composited samples, [digest of the original]]
+pub fn watermark_public_input(published: &[u8], digest: Fp) -> Vec<Vec<Fp>> {
+    vec![
+        published.iter().map(|&v| Fp::from(v as u64)).collect(),
+        vec![digest],
+    ]
+}
+
+/// Blends `watermark` over `transform` of the RGBA8 original and proves it; returns
+/// the composited image, the proof and the digest of the original (`Fp::to_repr`),
+/// or None if the watermark does not fit.
+pub fn create_watermark_proof(
+    transform: Transform,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    watermark: &Watermark,
+) -> Option<(Vec<u8>, Vec<u8>, [u8; 32])> {
+    let (circuit, composited) =
+        watermark_circuit(transform, &origin_buf, width, height, watermark)?;
+    let digest = pixels_digest(&origin_buf);
+    let public_input = watermark_public_input(&composited, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = watermark_parameter().get_parameter();
+    let empty_circuit = WatermarkCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    Some((composited, proof, digest.to_repr()))
+}
+
+/// Checks that `published` is `watermark` over `transform` of the original of
+/// `width` X `height` with digest `digest`.
+pub fn verify_watermark(
+    transform: Transform,
+    proof: &[u8],
+    width: u32,
+    height: u32,
+    published: &[u8],
+    watermark: &Watermark,
+    digest: [u8; 32],
+) -> bool {
+    let (s_width, s_height) = transform.shape(width, height);
+    if published.len() != (s_width * s_height * 4) as usize || !watermark.fits(s_width, s_height) {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_watermark_shape(transform, width, height, watermark) {
+        return false;
+    }
+    let public_input = watermark_public_input(published, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = watermark_parameter().get_parameter();
+    let empty_circuit = WatermarkCircuit::default();
+    verify_ipa(&params, &empty_circuit, proof, &public_input)
+}
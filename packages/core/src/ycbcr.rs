@@ -0,0 +1,601 @@
+//! RGB → YCbCr (JFIF) conversion of a committed original, 4:4:4 or 4:2:0.
+//!
+//! The conversion is libjpeg's fixed point one: every component is
+//! `(sum(coefficient * sample) + offset) >> 16` with the coefficients below,
+//! and the circuit checks `sum + offset = 65536 * component + lo + 256 * hi`
+//! with byte lookups on `component`, `lo` and `hi`. For 4:2:0 the chroma planes
+//! are reduced with 2 X 2 block averages as in `Downsampler` (current `rounding`,
+//! trailing odd rows and columns dropped), whose remainders are checked with the
+//! table of the reference (`RemainderTable`). The input is a `Transform` of the original
+//! and the alpha channel is ignored; the planes are tied to the digest of the
+//! original as in `similarity`.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    halo2curves::{group::ff::PrimeField, pasta::Fp},
+    plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Constraints, Error, Expression, Instance,
+        Selector,
+    },
+    poly::Rotation,
+};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    backend::{prove_ipa, verify_ipa},
+    digest::pixels_digest,
+    downsample::Downsampler,
+    helpers::{ipa_parameter, sample, RemainderTable},
+    rounding, set_once,
+    similarity::{
+        reference_rows, set_similarity_shape, ReferenceConfig, ReferenceWitness, Transform,
+        TRANSFORM,
+    },
+    Parameter, HEIGHT, SCALE_FACTOR, WIDTH,
+};
+
+const SCALE_BITS: u32 = 16;
+
+// (R, G, B) coefficients scaled by 2^16 and the offset, for Y, Cb and Cr (jccolor.c)
+const COEFFICIENTS: [([i64; 3], i64); 3] = [
+    ([19595, 38470, 7471], 1 << 15),
+    ([-11059, -21709, 32768], (128 << 16) + (1 << 15) - 1),
+    ([32768, -27439, -5329], (128 << 16) + (1 << 15) - 1),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Subsampling {
+    #[default]
+    Chroma444,
+    // chroma planes halved in both directions
+    Chroma420,
+}
+
+/// Planar YCbCr image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YCbCrImage {
+    pub width: u32,
+    pub height: u32,
+    pub subsampling: Subsampling,
+    pub y: Vec<u8>,
+    pub cb: Vec<u8>,
+    pub cr: Vec<u8>,
+}
+
+impl YCbCrImage {
+    pub fn chroma_shape(width: u32, height: u32, subsampling: Subsampling) -> (u32, u32) {
+        match subsampling {
+            Subsampling::Chroma444 => (width, height),
+            Subsampling::Chroma420 => (width / SCALE_FACTOR, height / SCALE_FACTOR),
+        }
+    }
+
+    /// Y, Cb and Cr planes one after another, as in a raw `.yuv` file.
+    pub fn to_planar(&self) -> Vec<u8> {
+        [&self.y[..], &self.cb, &self.cr].concat()
+    }
+
+    pub fn from_planar(
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        subsampling: Subsampling,
+    ) -> Option<Self> {
+        let luma = (width * height) as usize;
+        let (c_width, c_height) = Self::chroma_shape(width, height, subsampling);
+        let chroma = (c_width * c_height) as usize;
+        if buf.len() != luma + 2 * chroma {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            subsampling,
+            y: buf[..luma].to_vec(),
+            cb: buf[luma..luma + chroma].to_vec(),
+            cr: buf[luma + chroma..].to_vec(),
+        })
+    }
+}
+
+// (component, remainder of the shift) for Y, Cb and Cr
+fn convert_pixel(rgb: &[u8]) -> [(u8, u32); 3] {
+    COEFFICIENTS.map(|(weights, offset)| {
+        let sum = weights
+            .iter()
+            .zip(rgb)
+            .fold(offset, |sum, (&w, &v)| sum + w * v as i64);
+        ((sum >> SCALE_BITS) as u8, (sum & 0xffff) as u32)
+    })
+}
+
+pub fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let [(y, _), (cb, _), (cr, _)] = convert_pixel(&[r, g, b]);
+    (y, cb, cr)
+}
+
+// the image and the witnesses: shift remainders (3 per pixel) and, for 4:2:0,
+// full resolution chroma planes and the remainders of their block averages
+struct Converted {
+    image: YCbCrImage,
+    remainders: Vec<u32>,
+    chroma: [Vec<u8>; 2],
+    chroma_remainders: [Vec<u32>; 2],
+}
+
+fn convert_with_witness(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    subsampling: Subsampling,
+) -> Converted {
+    let mut planes = [(); 3].map(|_| Vec::with_capacity((width * height) as usize));
+    let mut remainders = Vec::with_capacity((width * height * 3) as usize);
+    for pixel in buf.chunks(4) {
+        for (plane, (component, remainder)) in planes.iter_mut().zip(convert_pixel(&pixel[..3])) {
+            plane.push(component);
+            remainders.push(remainder);
+        }
+    }
+    let [y, cb, cr] = planes;
+    let (image, chroma_remainders) = match subsampling {
+        Subsampling::Chroma444 => (
+            YCbCrImage {
+                width,
+                height,
+                subsampling,
+                y,
+                cb: cb.clone(),
+                cr: cr.clone(),
+            },
+            [Vec::new(), Vec::new()],
+        ),
+        Subsampling::Chroma420 => {
+            let [cb_reduced, cr_reduced] =
+                [&cb, &cr].map(|plane| Downsampler::default().downsample(plane, 1, width, height));
+            (
+                YCbCrImage {
+                    width,
+                    height,
+                    subsampling,
+                    y,
+                    cb: cb_reduced.pixels,
+                    cr: cr_reduced.pixels,
+                },
+                [cb_reduced.remainders, cr_reduced.remainders],
+            )
+        }
+    };
+    Converted {
+        image,
+        remainders,
+        chroma: [cb, cr],
+        chroma_remainders,
+    }
+}
+
+/// YCbCr planes of a RGBA8 image.
+pub fn convert(buf: &[u8], width: u32, height: u32, subsampling: Subsampling) -> YCbCrImage {
+    convert_with_witness(buf, width, height, subsampling).image
+}
+
+// 回路の形の一部: クロマのサブサンプリング
+pub static SUBSAMPLING: OnceCell<Subsampling> = OnceCell::new();
+
+// 既に別の形なら false
+pub fn set_ycbcr_shape(
+    transform: Transform,
+    width: u32,
+    height: u32,
+    subsampling: Subsampling,
+) -> bool {
+    set_similarity_shape(transform, width, height) & set_once(&SUBSAMPLING, subsampling)
+}
+
+// (width, height) of the converted image
+fn converted_shape() -> (usize, usize) {
+    let (width, height) = (*WIDTH.get().unwrap() as u32, *HEIGHT.get().unwrap() as u32);
+    let (s_width, s_height) = TRANSFORM.get().unwrap().shape(width, height);
+    (s_width as usize, s_height as usize)
+}
+
+pub static YCBCR_PARAMETER: OnceCell<Parameter> = OnceCell::new();
+
+fn ycbcr_parameter() -> &'static Parameter {
+    YCBCR_PARAMETER.get_or_init(|| {
+        let (width, height) = (*WIDTH.get().unwrap(), *HEIGHT.get().unwrap());
+        let (s_width, s_height) = converted_shape();
+        let pixels = s_width * s_height;
+        // 4:2:0 のクロマは 2 平面 X 1/4
+        ipa_parameter(reference_rows(width * height, pixels * 4) + pixels + pixels / 2)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct YCbCrConfig {
+    reference: ReferenceConfig,
+    // one pixel per row
+    rgb: [Column<Advice>; 3],
+    // Y, Cb, Cr and the low and high bytes of their shift remainders
+    component: [Column<Advice>; 3],
+    lo: [Column<Advice>; 3],
+    hi: [Column<Advice>; 3],
+    q_convert: Selector,
+    // 4:2:0, one chroma sample per row
+    block: [Column<Advice>; 4],
+    average: Column<Advice>,
+    remainder: Column<Advice>,
+    // parity of `average` under `HalfEven`
+    parity: Option<Column<Advice>>,
+    q_chroma: Selector,
+    // Y, Cb and Cr planes
+    planes: [Column<Instance>; 3],
+    // row 0: digest of the original
+    statement: Column<Instance>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct YCbCrCircuit {
+    pub reference: ReferenceWitness,
+    // 3 per pixel (Y, Cb, Cr)
+    pub component: Vec<Value<u8>>,
+    pub remainder: Vec<Value<u32>>,
+    // 4:2:0 only: Cb and Cr averages and their remainders
+    pub chroma: [Vec<Value<u8>>; 2],
+    pub chroma_remainder: [Vec<Value<u32>>; 2],
+}
+
+fn constant(v: i64) -> Expression<Fp> {
+    let magnitude = Fp::from(v.unsigned_abs());
+    Expression::Constant(if v < 0 { -magnitude } else { magnitude })
+}
+
+impl Circuit<Fp> for YCbCrCircuit {
+    type Config = YCbCrConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let reference = ReferenceConfig::configure(meta);
+        let columns = |meta: &mut ConstraintSystem<Fp>| [(); 3].map(|_| meta.advice_column());
+        let rgb = columns(meta);
+        let component = columns(meta);
+        let lo = columns(meta);
+        let hi = columns(meta);
+        let block = [(); 4].map(|_| meta.advice_column());
+        let average = meta.advice_column();
+        let remainder = meta.advice_column();
+        let planes = [(); 3].map(|_| meta.instance_column());
+        let statement = meta.instance_column();
+        rgb.iter()
+            .chain(&component)
+            .chain(&block)
+            .chain([&average])
+            .for_each(|&col| meta.enable_equality(col));
+        planes
+            .iter()
+            .chain([&statement])
+            .for_each(|&col| meta.enable_equality(col));
+
+        let q_convert = meta.complex_selector();
+        meta.create_gate("rgb to ycbcr", |virtual_cells| {
+            let q_convert = virtual_cells.query_selector(q_convert);
+            let rgb = rgb.map(|col| virtual_cells.query_advice(col, Rotation::cur()));
+            let constraints = COEFFICIENTS
+                .iter()
+                .enumerate()
+                .map(|(i, (weights, offset))| {
+                    let sum = weights
+                        .iter()
+                        .zip(rgb.iter())
+                        .fold(constant(*offset), |sum, (&w, v)| {
+                            sum + constant(w) * v.clone()
+                        });
+                    let component = virtual_cells.query_advice(component[i], Rotation::cur());
+                    let lo = virtual_cells.query_advice(lo[i], Rotation::cur());
+                    let hi = virtual_cells.query_advice(hi[i], Rotation::cur());
+                    sum - (constant(1 << SCALE_BITS) * component + lo + constant(256) * hi)
+                })
+                .collect::<Vec<_>>();
+            Constraints::with_selector(q_convert, constraints)
+        });
+        let bytes = reference.bytes();
+        bytes.range_check(meta, "remainder byte", q_convert, &lo);
+        bytes.range_check(meta, "remainder byte", q_convert, &hi);
+        // the full resolution chroma of 4:2:0 is not public
+        bytes.range_check(meta, "component byte", q_convert, &component);
+
+        let q_chroma = meta.complex_selector();
+        meta.create_gate("chroma average", |virtual_cells| {
+            let q_chroma = virtual_cells.query_selector(q_chroma);
+            let divisor = (SCALE_FACTOR * SCALE_FACTOR) as i64;
+            let offset = constant(rounding().offset(divisor as u32) as i64);
+            let sum = block.iter().fold(offset, |sum, &col| {
+                sum + virtual_cells.query_advice(col, Rotation::cur())
+            });
+            let average = virtual_cells.query_advice(average, Rotation::cur());
+            let remainder = virtual_cells.query_advice(remainder, Rotation::cur());
+            Constraints::with_selector(q_chroma, [sum - (constant(divisor) * average + remainder)])
+        });
+        // same divisor as the reference averages
+        let parity = reference.remainders().range_check(
+            meta,
+            "chroma remainder",
+            q_chroma,
+            average,
+            remainder,
+        );
+
+        YCbCrConfig {
+            reference,
+            rgb,
+            component,
+            lo,
+            hi,
+            q_convert,
+            block,
+            average,
+            remainder,
+            parity,
+            q_chroma,
+            planes,
+            statement,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let subsampling = *SUBSAMPLING.get().unwrap();
+        let (width, height) = converted_shape();
+
+        let (digest, references) = config.reference.assign(
+            layouter.namespace(|| "reference"),
+            *TRANSFORM.get().unwrap(),
+            &self.reference,
+        )?;
+        layouter.constrain_instance(digest.cell(), config.statement, 0)?;
+
+        // components[c][pixel]
+        let components = layouter.assign_region(
+            || "rgb to ycbcr",
+            |mut region| {
+                let mut cells = [(); 3].map(|_| Vec::with_capacity(width * height));
+                for row in 0..width * height {
+                    config.q_convert.enable(&mut region, row)?;
+                    for c in 0..3 {
+                        references[4 * row + c].copy_advice(
+                            || "rgb",
+                            &mut region,
+                            config.rgb[c],
+                            row,
+                        )?;
+                        let remainder = self
+                            .remainder
+                            .get(3 * row + c)
+                            .copied()
+                            .unwrap_or_else(Value::unknown);
+                        for (i, col) in [config.lo[c], config.hi[c]].into_iter().enumerate() {
+                            region.assign_advice(
+                                || "remainder byte",
+                                col,
+                                row,
+                                || remainder.map(|v| Fp::from(((v >> (8 * i)) & 0xff) as u64)),
+                            )?;
+                        }
+                        cells[c].push(region.assign_advice(
+                            || "component",
+                            config.component[c],
+                            row,
+                            || sample(&self.component, 3 * row + c),
+                        )?);
+                    }
+                }
+                Ok(cells)
+            },
+        )?;
+        let [y, cb, cr] = components;
+
+        let chroma = match subsampling {
+            Subsampling::Chroma444 => [cb, cr],
+            Subsampling::Chroma420 => {
+                let mut reduced = Vec::with_capacity(2);
+                for (i, plane) in [cb, cr].iter().enumerate() {
+                    reduced.push(self.reduce_chroma(
+                        &config,
+                        &mut layouter,
+                        plane,
+                        i,
+                        width,
+                        height,
+                    )?);
+                }
+                <[_; 2]>::try_from(reduced).unwrap()
+            }
+        };
+
+        for (plane, cells) in [&y, &chroma[0], &chroma[1]].into_iter().enumerate() {
+            for (i, cell) in cells.iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.planes[plane], i)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl YCbCrCircuit {
+    // 2 X 2 block averages of a full resolution chroma plane, as `Downsampler::default()`
+    fn reduce_chroma(
+        &self,
+        config: &YCbCrConfig,
+        layouter: &mut impl Layouter<Fp>,
+        plane: &[AssignedCell<Fp, Fp>],
+        index: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let block = SCALE_FACTOR as usize;
+        let (c_width, c_height) = (width / block, height / block);
+
+        layouter.assign_region(
+            || "chroma average",
+            |mut region| {
+                let mut cells = Vec::with_capacity(c_width * c_height);
+                for y in 0..c_height {
+                    for x in 0..c_width {
+                        let row = y * c_width + x;
+                        config.q_chroma.enable(&mut region, row)?;
+                        for (k, &col) in config.block.iter().enumerate() {
+                            let (dy, dx) = (k / block, k % block);
+                            plane[(block * y + dy) * width + block * x + dx].copy_advice(
+                                || "chroma",
+                                &mut region,
+                                col,
+                                row,
+                            )?;
+                        }
+                        region.assign_advice(
+                            || "remainder",
+                            config.remainder,
+                            row,
+                            || sample(&self.chroma_remainder[index], row),
+                        )?;
+                        RemainderTable::assign_parity(
+                            &mut region,
+                            config.parity,
+                            row,
+                            self.chroma[index]
+                                .get(row)
+                                .copied()
+                                .unwrap_or_else(Value::unknown),
+                        )?;
+                        cells.push(region.assign_advice(
+                            || "average",
+                            config.average,
+                            row,
+                            || sample(&self.chroma[index], row),
+                        )?);
+                    }
+                }
+                Ok(cells)
+            },
+        )
+    }
+}
+
+fn public_planes(image: &YCbCrImage) -> [Vec<Fp>; 3] {
+    [&image.y, &image.cb, &image.cr]
+        .map(|plane| plane.iter().map(|&v| Fp::from(v as u64)).collect())
+}
+
+/// The circuit converting `transform` of the RGBA8 original to YCbCr and the
+/// planes, also sets the shape globals.
+pub fn ycbcr_circuit(
+    transform: Transform,
+    subsampling: Subsampling,
+    origin_buf: &[u8],
+    width: u32,
+    height: u32,
+) -> (YCbCrCircuit, YCbCrImage) {
+    assert!(
+        set_ycbcr_shape(transform, width, height, subsampling),
+        "the circuit shape is already set for another image size, transform or subsampling"
+    );
+    let (base, s_width, s_height) = transform.apply(origin_buf, width, height);
+    let converted = convert_with_witness(&base, s_width, s_height, subsampling);
+
+    let known = |samples: &[u8]| samples.iter().map(|&v| Value::known(v)).collect::<Vec<_>>();
+    let component = converted
+        .image
+        .y
+        .iter()
+        .zip(&converted.chroma[0])
+        .zip(&converted.chroma[1])
+        .flat_map(|((&y, &cb), &cr)| [y, cb, cr])
+        .map(Value::known)
+        .collect();
+    let (chroma, chroma_remainder) = match subsampling {
+        Subsampling::Chroma444 => Default::default(),
+        Subsampling::Chroma420 => (
+            [known(&converted.image.cb), known(&converted.image.cr)],
+            converted
+                .chroma_remainders
+                .clone()
+                .map(|remainders| remainders.into_iter().map(Value::known).collect()),
+        ),
+    };
+    let circuit = YCbCrCircuit {
+        reference: ReferenceWitness::new(transform, origin_buf, width, height),
+        component,
+        remainder: converted.remainders.into_iter().map(Value::known).collect(),
+        chroma,
+        chroma_remainder,
+    };
+    (circuit, converted.image)
+}
+
+/// [Y plane, Cb plane, Cr plane, [digest of the original]]
+pub fn ycbcr_public_input(image: &YCbCrImage, digest: Fp) -> Vec<Vec<Fp>> {
+    let [y, cb, cr] = public_planes(image);
+    vec![y, cb, cr, vec![digest]]
+}
+
+/// Converts `transform` of the RGBA8 original to YCbCr and proves it; returns the
+/// planes, the proof and the digest of the original (`Fp::to_repr`).
+pub fn create_ycbcr_proof(
+    transform: Transform,
+    subsampling: Subsampling,
+    origin_buf: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> (YCbCrImage, Vec<u8>, [u8; 32]) {
+    let (circuit, image) = ycbcr_circuit(transform, subsampling, &origin_buf, width, height);
+    let digest = pixels_digest(&origin_buf);
+    let public_input = ycbcr_public_input(&image, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = ycbcr_parameter().get_parameter();
+    let empty_circuit = YCbCrCircuit::default();
+    let proof = prove_ipa(&params, &empty_circuit, circuit, &public_input);
+    (image, proof, digest.to_repr())
+}
+
+/// Checks that `image` is the YCbCr conversion of `transform` of the original of
+/// `width` X `height` with digest `digest`.
+pub fn verify_ycbcr(
+    transform: Transform,
+    proof: &[u8],
+    width: u32,
+    height: u32,
+    image: &YCbCrImage,
+    digest: [u8; 32],
+) -> bool {
+    let (s_width, s_height) = transform.shape(width, height);
+    let (c_width, c_height) = YCbCrImage::chroma_shape(s_width, s_height, image.subsampling);
+    if (image.width, image.height) != (s_width, s_height)
+        || image.y.len() != (s_width * s_height) as usize
+        || image.cb.len() != (c_width * c_height) as usize
+        || image.cr.len() != image.cb.len()
+    {
+        return false;
+    }
+    let digest = match Option::<Fp>::from(Fp::from_repr(digest)) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    if !set_ycbcr_shape(transform, width, height, image.subsampling) {
+        return false;
+    }
+    let public_input = ycbcr_public_input(image, digest);
+    let public_input = public_input.iter().map(|c| &c[..]).collect::<Vec<_>>();
+
+    let params = ycbcr_parameter().get_parameter();
+    let empty_circuit = YCbCrCircuit::default();
+    verify_ipa(&params, &empty_circuit, proof, &public_input)
+}
@@ -0,0 +1,79 @@
+// Needs 2^21 rows and `solc` in PATH: cargo test -p core --release --features aggregation
+#![cfg(feature = "aggregation")]
+
+use core::{
+    aggregation::{
+        aggregate_img_proofs, aggregation_verifier, create_img_proof_aggregatable,
+        verify_aggregated, AGG_K,
+    },
+    downsample::Downsampler,
+    load_kzg_srs,
+};
+use halo2_proofs::{
+    halo2curves::bn256::Bn256,
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::commitment::ParamsKZG,
+    },
+};
+use image::EncodableLayout;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+
+// (proof, thumbnail) of a gradient shifted by `seed`
+fn img_proof(seed: u32) -> (Vec<u8>, Vec<u8>) {
+    let origin: Vec<u8> = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5 + seed) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect();
+    let thumbnail = Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .to_rgba_image()
+        .as_bytes()
+        .to_vec();
+    (
+        create_img_proof_aggregatable(origin, WIDTH, HEIGHT),
+        thumbnail,
+    )
+}
+
+#[test]
+fn aggregated_proof_is_checked_against_the_trusted_verifier() {
+    // test-only SRS, real deployments load a ceremony
+    let mut srs = Vec::new();
+    ParamsKZG::<Bn256>::new(AGG_K).write(&mut srs).unwrap();
+    assert!(load_kzg_srs(&mut &srs[..]).unwrap());
+
+    let (s_width, s_height) = (WIDTH / 2, HEIGHT / 2);
+    let proofs = [img_proof(0), img_proof(7)];
+    let thumbnails = proofs.iter().map(|(_, t)| t.clone()).collect::<Vec<_>>();
+
+    // the verifier derives its contract from proofs of its own
+    let trusted = aggregation_verifier(&[img_proof(1), img_proof(2)], s_width, s_height).unwrap();
+    let aggregated = aggregate_img_proofs(&proofs, s_width, s_height);
+    assert!(verify_aggregated(
+        &trusted,
+        &aggregated,
+        &thumbnails,
+        s_width,
+        s_height
+    ));
+
+    // an aggregation of another circuit (one thumbnail) passes its own contract only
+    let single = aggregate_img_proofs(&proofs[..1], s_width, s_height);
+    let own = aggregation_verifier(&proofs[..1], s_width, s_height).unwrap();
+    assert!(verify_aggregated(
+        &own,
+        &single,
+        &thumbnails[..1],
+        s_width,
+        s_height
+    ));
+    assert!(!verify_aggregated(
+        &trusted,
+        &single,
+        &thumbnails[..1],
+        s_width,
+        s_height
+    ));
+}
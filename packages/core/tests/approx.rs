@@ -0,0 +1,77 @@
+// Approximate reductions of one 4 X 2 RGBA original with tolerance 2 for the whole process.
+use core::{
+    approx::{approx_circuit, approx_public_input, set_approx_shape},
+    digest::pixels_digest,
+    downsample::Downsampler,
+};
+use halo2_proofs::{circuit::Value, dev::MockProver};
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+const TOLERANCE: u8 = 2;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 9) as u8, 255])
+        .collect()
+}
+
+// the reference reduction, moved by up to the tolerance (recompressed)
+fn published() -> Vec<u8> {
+    Downsampler::default()
+        .downsample_rgba(&origin(), WIDTH, HEIGHT)
+        .pixels
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| match i % 3 {
+            0 => v.saturating_add(TOLERANCE),
+            1 => v.saturating_sub(TOLERANCE),
+            _ => v,
+        })
+        .collect()
+}
+
+#[test]
+fn approx_circuit_is_satisfied() {
+    let circuit = approx_circuit(&origin(), WIDTH, HEIGHT, &published(), TOLERANCE).unwrap();
+    let instance = approx_public_input(&published(), pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn published_beyond_tolerance_is_rejected() {
+    let mut circuit = approx_circuit(&origin(), WIDTH, HEIGHT, &published(), TOLERANCE).unwrap();
+    let mut tampered = published();
+    // the green sample of the first pixel is TOLERANCE below the reference
+    tampered[1] -= 1;
+    circuit.published[1] = Value::known(tampered[1]);
+    let instance = approx_public_input(&tampered, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_digest_is_rejected() {
+    let circuit = approx_circuit(&origin(), WIDTH, HEIGHT, &published(), TOLERANCE).unwrap();
+    let mut other = origin();
+    other[0] ^= 1;
+    let instance = approx_public_input(&published(), pixels_digest(&other));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn approx_circuit_needs_the_tolerance() {
+    let mut tampered = published();
+    tampered[1] -= 1;
+    assert!(approx_circuit(&origin(), WIDTH, HEIGHT, &tampered, TOLERANCE).is_none());
+}
+
+#[test]
+fn another_tolerance_is_rejected() {
+    assert!(set_approx_shape(WIDTH, HEIGHT, TOLERANCE));
+    assert!(!set_approx_shape(WIDTH, HEIGHT, TOLERANCE + 1));
+}
@@ -0,0 +1,102 @@
+// Blur circuits of one 6 X 4 RGBA image with the 3 X 3 gaussian for the whole process.
+use core::{
+    blur::{blur, blur_circuit, blur_public_input, set_blur_shape, Kernel},
+    channels::{ChannelLayout, NormalizedImage},
+    digest::pixels_digest,
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 6;
+const HEIGHT: u32 = 4;
+// the digest of the original needs more rows
+const K: u32 = 11;
+
+// samples below 128, so that an output can grow by a whole 64
+fn image() -> NormalizedImage {
+    let pixels = (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i * 37 % 128) as u8)
+        .collect();
+    NormalizedImage {
+        layout: ChannelLayout::Rgba,
+        width: WIDTH,
+        height: HEIGHT,
+        pixels,
+    }
+}
+
+#[test]
+fn blur_circuit_is_satisfied() {
+    let kernel = Kernel::gaussian3();
+    let (circuit, blurred) = blur_circuit(&image(), &kernel);
+    assert_eq!(blurred.pixels, blur(&image(), &kernel).pixels);
+    let public_input = blur_public_input(&blurred, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_blur_is_rejected() {
+    let (circuit, blurred) = blur_circuit(&image(), &Kernel::gaussian3());
+    let mut tampered = blurred.clone();
+    tampered.pixels[0] ^= 1;
+    let public_input = blur_public_input(&tampered, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_original_is_rejected() {
+    let (circuit, blurred) = blur_circuit(&image(), &Kernel::gaussian3());
+    let mut other = image().pixels;
+    other[0] ^= 1;
+    let public_input = blur_public_input(&blurred, pixels_digest(&other));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn non_byte_original_is_rejected() {
+    // 256 more in an inner red sample moves every output it touches by a whole
+    // 256 * weight / 16, so the gate and the remainders still hold; the range
+    // check of the original rejects it
+    let kernel = Kernel::gaussian3();
+    let (mut circuit, blurred) = blur_circuit(&image(), &kernel);
+    let (x, y) = (2, 1);
+    let index = (y * WIDTH as usize + x) * 4;
+    circuit.original[index] = circuit.original[index].map(|v| v + 256);
+    let mut tampered = blurred.clone();
+    for dy in 0..3 {
+        for dx in 0..3 {
+            // the output whose neighbour (dx, dy) is (x, y)
+            let out = ((y + 1 - dy) * WIDTH as usize + x + 1 - dx) * 4;
+            let shift = (256 * kernel.weights()[dy * 3 + dx] / kernel.divisor()) as u8;
+            tampered.pixels[out] += shift;
+            circuit.blurred[out] = circuit.blurred[out].map(|v| v + shift);
+        }
+    }
+    let public_input = blur_public_input(&tampered, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn remainder_of_the_divisor_is_rejected() {
+    // one less with a remainder of the divisor (16) more still satisfies the
+    // gate, the remainder lookup rejects it
+    let kernel = Kernel::gaussian3();
+    let (mut circuit, blurred) = blur_circuit(&image(), &kernel);
+    let index = blurred.pixels.iter().position(|&v| v > 0).unwrap();
+    let mut tampered = blurred.clone();
+    tampered.pixels[index] -= 1;
+    circuit.blurred[index] = circuit.blurred[index].map(|v| v - 1);
+    circuit.remainder[index] = circuit.remainder[index].map(|r| r + kernel.divisor());
+    let public_input = blur_public_input(&tampered, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_kernel_is_rejected() {
+    assert!(set_blur_shape(WIDTH, HEIGHT, &Kernel::gaussian3()));
+    assert!(!set_blur_shape(WIDTH, HEIGHT, &Kernel::box_blur(3)));
+}
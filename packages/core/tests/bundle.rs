@@ -0,0 +1,77 @@
+// One proof shared by all tests: the circuit shape is fixed for the whole process.
+use core::{
+    bundle::{BundleError, ProofBundle, BUNDLE_VERSION},
+    downsample::Rounding,
+};
+use once_cell::sync::Lazy;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+
+static BUNDLE: Lazy<ProofBundle> = Lazy::new(|| {
+    let origin = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect();
+    ProofBundle::create(origin, WIDTH, HEIGHT)
+});
+
+#[test]
+fn bundle_round_trips_through_json_and_cbor() {
+    let json = BUNDLE.to_json();
+    let from_json = ProofBundle::from_json(&json).unwrap();
+    assert_eq!(from_json, *BUNDLE);
+    assert!(from_json.verify());
+
+    let from_cbor = ProofBundle::from_cbor(&BUNDLE.to_cbor()).unwrap();
+    assert_eq!(from_cbor, *BUNDLE);
+    assert!(from_cbor.verify());
+}
+
+#[test]
+fn malformed_thumbnail_is_rejected_without_panicking() {
+    let mut truncated = BUNDLE.clone();
+    truncated.thumbnail.pixels.pop();
+    assert!(!truncated.verify());
+
+    let mut reshaped = BUNDLE.clone();
+    reshaped.thumbnail.width += 1;
+    assert!(!reshaped.verify());
+
+    let mut tampered = BUNDLE.clone();
+    tampered.thumbnail.pixels[0] ^= 1;
+    assert!(!tampered.verify());
+
+    // the original does not match the shape fixed by the first bundle
+    let mut other_shape = BUNDLE.clone();
+    other_shape.width += 2;
+    other_shape.thumbnail.width += 1;
+    other_shape.thumbnail.pixels = vec![0; (other_shape.thumbnail.width * 3 * 4) as usize];
+    assert!(!other_shape.verify());
+
+    let mut other_rounding = BUNDLE.clone();
+    other_rounding.rounding = Rounding::HalfEven;
+    assert!(!other_rounding.verify());
+}
+
+#[test]
+fn malformed_documents_are_errors() {
+    assert!(matches!(
+        ProofBundle::from_json("{\"version\": 1}"),
+        Err(BundleError::Json(_))
+    ));
+    assert!(matches!(
+        ProofBundle::from_cbor(&[0xff, 0x00]),
+        Err(BundleError::Cbor(_))
+    ));
+
+    let mut json: serde_json::Value = serde_json::from_str(&BUNDLE.to_json()).unwrap();
+    json["proof"] = "not base64!".into();
+    assert!(ProofBundle::from_json(&json.to_string()).is_err());
+
+    let mut future = BUNDLE.clone();
+    future.version = BUNDLE_VERSION + 1;
+    assert!(matches!(
+        ProofBundle::from_json(&future.to_json()),
+        Err(BundleError::UnsupportedVersion(v)) if v == BUNDLE_VERSION + 1
+    ));
+}
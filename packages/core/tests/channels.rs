@@ -0,0 +1,78 @@
+// Gray and RGB circuits of one 6 X 4 shape for the whole process.
+use core::channels::{
+    channel_circuit, channel_public_input, normalize, ChannelLayout, GrayCircuit, NormalizedImage,
+    RgbCircuit,
+};
+use halo2_proofs::{dev::MockProver, halo2curves::pasta::Fp};
+use image::{DynamicImage, ImageBuffer, LumaA, Rgb, Rgba};
+
+const WIDTH: u32 = 6;
+const HEIGHT: u32 = 4;
+const K: u32 = 10;
+
+fn image(layout: ChannelLayout) -> NormalizedImage {
+    let pixels = (0..WIDTH * HEIGHT * layout.channels() as u32)
+        .map(|i| (i * 37 % 256) as u8)
+        .collect();
+    NormalizedImage {
+        layout,
+        width: WIDTH,
+        height: HEIGHT,
+        pixels,
+    }
+}
+
+#[test]
+fn normalize_follows_the_documented_rules() {
+    let gray_alpha = ImageBuffer::from_pixel(1, 1, LumaA([7u8, 200]));
+    let normalized = normalize(&DynamicImage::ImageLumaA8(gray_alpha));
+    assert_eq!(normalized.layout, ChannelLayout::Rgba);
+    assert_eq!(normalized.pixels, vec![7, 7, 7, 200]);
+
+    // (v * 255 + 32767) / 65535
+    let deep = ImageBuffer::from_raw(2, 1, vec![0u16, 128, 65535, 257, 32896, 65278]).unwrap();
+    let normalized = normalize(&DynamicImage::ImageRgb16(deep));
+    assert_eq!(normalized.layout, ChannelLayout::Rgb);
+    assert_eq!(normalized.pixels, vec![0, 0, 255, 1, 128, 254]);
+
+    // clamped, then round(v * 255)
+    let float = ImageBuffer::from_pixel(1, 1, Rgba([-1.0f32, 0.5, 2.0, 0.002]));
+    let normalized = normalize(&DynamicImage::ImageRgba32F(float));
+    assert_eq!(normalized.pixels, vec![0, 128, 255, 1]);
+
+    let rgb = ImageBuffer::from_pixel(2, 3, Rgb([1u8, 2, 3]));
+    let normalized = normalize(&DynamicImage::ImageRgb8(rgb));
+    assert_eq!((normalized.width, normalized.height), (2, 3));
+    assert_eq!(normalized.pixels, [1u8, 2, 3].repeat(6));
+}
+
+#[test]
+fn channel_circuits_are_satisfied() {
+    let (circuit, thumbnail) = channel_circuit::<Fp, 1>(&image(ChannelLayout::Gray));
+    let prover = MockProver::run(K, &circuit, channel_public_input(&thumbnail)).unwrap();
+    prover.assert_satisfied();
+
+    let (circuit, thumbnail) = channel_circuit::<Fp, 3>(&image(ChannelLayout::Rgb));
+    let prover = MockProver::run(K, &circuit, channel_public_input(&thumbnail)).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_thumbnail_is_rejected() {
+    let (circuit, thumbnail): (GrayCircuit<Fp>, _) = channel_circuit(&image(ChannelLayout::Gray));
+    let mut tampered = thumbnail.clone();
+    tampered.pixels[0] ^= 1;
+    let prover = MockProver::run(K, &circuit, channel_public_input(&tampered)).unwrap();
+    assert!(prover.verify().is_err());
+
+    // one less with a remainder of 4 more still satisfies the gate, the remainder
+    // lookup rejects it
+    let (mut circuit, thumbnail): (RgbCircuit<Fp>, _) = channel_circuit(&image(ChannelLayout::Rgb));
+    let index = thumbnail.pixels.iter().position(|&v| v > 0).unwrap();
+    let mut tampered = thumbnail.clone();
+    tampered.pixels[index] -= 1;
+    circuit.reduced[index] = circuit.reduced[index].map(|v| v - 1);
+    circuit.remainder[index] = circuit.remainder[index].map(|r| r + 4);
+    let prover = MockProver::run(K, &circuit, channel_public_input(&tampered)).unwrap();
+    assert!(prover.verify().is_err());
+}
@@ -0,0 +1,100 @@
+use std::io::Cursor;
+
+use core::{
+    channels::ChannelLayout,
+    decode::{canonicalize_rgba, decode, decode_original, unpremultiply},
+};
+use image::{DynamicImage, GrayImage, ImageOutputFormat, RgbaImage};
+
+// what a canvas does with RGBA8 pixels: store them premultiplied, hand them back straight
+fn canvas_premultiply(buf: &[u8]) -> Vec<u8> {
+    buf.chunks(4)
+        .flat_map(|p| {
+            let alpha = p[3] as u32;
+            let premultiply = |c: u8| ((c as u32 * alpha + 127) / 255) as u8;
+            [
+                premultiply(p[0]),
+                premultiply(p[1]),
+                premultiply(p[2]),
+                p[3],
+            ]
+        })
+        .collect()
+}
+
+fn canvas_round_trip(buf: &[u8]) -> Vec<u8> {
+    unpremultiply(&canvas_premultiply(buf))
+}
+
+// every (color, alpha) pair once
+fn all_pixels() -> Vec<u8> {
+    (0..=255u8)
+        .flat_map(|alpha| (0..=255u8).flat_map(move |c| [c, 255 - c, c / 2, alpha]))
+        .collect()
+}
+
+fn encode(img: DynamicImage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn canonical_pixels_survive_a_canvas_round_trip() {
+    let mut canonical = all_pixels();
+    canonicalize_rgba(&mut canonical);
+    assert_eq!(canvas_round_trip(&canonical), canonical);
+}
+
+#[test]
+fn canvas_and_file_originals_agree_after_canonicalization() {
+    let pixels = all_pixels();
+
+    let mut from_canvas = canvas_round_trip(&pixels);
+    canonicalize_rgba(&mut from_canvas);
+
+    let png = encode(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(256, 256, pixels).unwrap(),
+    ));
+    let from_file = decode_original(&png).unwrap();
+
+    assert_eq!(from_file.layout, ChannelLayout::Rgba);
+    assert_eq!(from_file.pixels, from_canvas);
+}
+
+#[test]
+fn canonicalization_keeps_opaque_pixels_and_clears_transparent_ones() {
+    let mut buf = vec![10, 20, 30, 255, 10, 20, 30, 0];
+    canonicalize_rgba(&mut buf);
+    assert_eq!(buf, vec![10, 20, 30, 255, 0, 0, 0, 0]);
+}
+
+#[test]
+fn unpremultiply_inverts_premultiplied_canonical_pixels() {
+    let mut canonical = all_pixels();
+    canonicalize_rgba(&mut canonical);
+    assert_eq!(unpremultiply(&canvas_premultiply(&canonical)), canonical);
+}
+
+#[test]
+fn decode_returns_the_stored_samples() {
+    let pixels = all_pixels();
+    let png = encode(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(256, 256, pixels.clone()).unwrap(),
+    ));
+    let decoded = decode(&png).unwrap();
+    assert_eq!((decoded.width, decoded.height), (256, 256));
+    assert_eq!(decoded.pixels, pixels);
+}
+
+#[test]
+fn decode_keeps_the_channel_layout() {
+    let gray = (0..=255u8).collect::<Vec<_>>();
+    let png = encode(DynamicImage::ImageLuma8(
+        GrayImage::from_raw(16, 16, gray.clone()).unwrap(),
+    ));
+    let decoded = decode(&png).unwrap();
+    assert_eq!(decoded.layout, ChannelLayout::Gray);
+    assert_eq!(decoded.pixels, gray);
+}
@@ -0,0 +1,109 @@
+use core::digest::{hash_pair, pixels_digest, DigestConfig};
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    dev::MockProver,
+    halo2curves::pasta::Fp,
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Instance},
+};
+
+// digest of free pixel cells, channels given as field elements so that tests can overflow a byte
+#[derive(Clone, Default)]
+struct DigestCircuit {
+    pixels: Vec<[u64; 4]>,
+}
+
+#[derive(Clone)]
+struct DigestCircuitConfig {
+    pixel: [Column<Advice>; 4],
+    digest: DigestConfig,
+    instance: Column<Instance>,
+}
+
+impl Circuit<Fp> for DigestCircuit {
+    type Config = DigestCircuitConfig;
+
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            pixels: vec![[0; 4]; self.pixels.len()],
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let pixel = [(); 4].map(|_| meta.advice_column());
+        pixel.iter().for_each(|&col| meta.enable_equality(col));
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        DigestCircuitConfig {
+            pixel,
+            digest: DigestConfig::configure(meta),
+            instance,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let pixels = layouter.assign_region(
+            || "pixels",
+            |mut region| {
+                let mut cells = Vec::with_capacity(self.pixels.len());
+                for (i, pixel) in self.pixels.iter().enumerate() {
+                    let mut cell = Vec::with_capacity(4);
+                    for (&col, &v) in config.pixel.iter().zip(pixel) {
+                        cell.push(region.assign_advice(
+                            || "channel",
+                            col,
+                            i,
+                            || Value::known(Fp::from(v)),
+                        )?);
+                    }
+                    cells.push(<[_; 4]>::try_from(cell).unwrap());
+                }
+                Ok(cells)
+            },
+        )?;
+        config.digest.load(&mut layouter)?;
+        let digest = config
+            .digest
+            .digest(layouter.namespace(|| "digest"), &pixels)?;
+        layouter.constrain_instance(digest.cell(), config.instance, 0)
+    }
+}
+
+const K: u32 = 9;
+
+#[test]
+fn digest_matches_pixels_digest() {
+    let buf = [10, 20, 30, 255, 0, 1, 2, 3];
+    let circuit = DigestCircuit {
+        pixels: buf
+            .chunks(4)
+            .map(|p| [p[0], p[1], p[2], p[3]].map(u64::from))
+            .collect(),
+    };
+    let prover = MockProver::run(K, &circuit, vec![vec![pixels_digest(&buf)]]).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn carry_between_channels_is_rejected() {
+    // (10 + 256, 20 - 1) packs to the same element as (10, 20)
+    let buf = [10, 20, 30, 255];
+    let digest = pixels_digest(&buf);
+    assert_eq!(
+        digest,
+        hash_pair(
+            Fp::zero(),
+            Fp::from(266 + (19 << 8) + (30 << 16) + (255 << 24))
+        )
+    );
+    let circuit = DigestCircuit {
+        pixels: vec![[266, 19, 30, 255]],
+    };
+    let prover = MockProver::run(K, &circuit, vec![vec![digest]]).unwrap();
+    assert!(prover.verify().is_err());
+}
@@ -0,0 +1,85 @@
+use core::{
+    digest::pixels_digest,
+    disclosure::{
+        committed_circuit, create_img_proof_committed, disclose_rows, row_tree,
+        verify_img_committed, Disclosure,
+    },
+    merkle::MerklePath,
+};
+use halo2_proofs::{dev::MockProver, halo2curves::group::ff::PrimeField};
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+// six row digests of 8 pixels and the tree over them
+const K: u32 = 12;
+
+fn gradient() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn committed_circuit_is_satisfied() {
+    let (circuit, public_input) = committed_circuit(gradient(), WIDTH, HEIGHT);
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn root_of_another_original_is_rejected() {
+    let (circuit, mut public_input) = committed_circuit(gradient(), WIDTH, HEIGHT);
+    let mut other = gradient();
+    other[4 * WIDTH as usize * 5] ^= 1;
+    *public_input.last_mut().unwrap() = vec![row_tree(&other, WIDTH, HEIGHT).root()];
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn disclosed_rows_match_the_proved_root() {
+    let origin = gradient();
+    let thumbnail = core::downsample::Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .pixels;
+    let (proof, root) = create_img_proof_committed(origin.clone(), WIDTH, HEIGHT);
+    assert_eq!(root, row_tree(&origin, WIDTH, HEIGHT).root().to_repr());
+    assert!(verify_img_committed(
+        proof.clone(),
+        thumbnail.clone(),
+        WIDTH,
+        HEIGHT,
+        root
+    ));
+    let mut tampered = thumbnail;
+    tampered[0] ^= 1;
+    assert!(!verify_img_committed(proof, tampered, WIDTH, HEIGHT, root));
+
+    let mut disclosed = disclose_rows(&origin, WIDTH, HEIGHT, 2..4);
+    assert!(disclosed
+        .iter()
+        .all(|disclosure| disclosure.verify_row(&root, WIDTH, HEIGHT)));
+    disclosed[0].pixels[0] ^= 1;
+    assert!(!disclosed[0].verify_row(&root, WIDTH, HEIGHT));
+}
+
+#[test]
+fn padding_node_is_not_a_row() {
+    // the six rows are padded to eight leaves, leaves 6 and 7 are zero and their
+    // parent hashes like the digest of one transparent black pixel
+    let origin = gradient();
+    let tree = row_tree(&origin, WIDTH, HEIGHT);
+    let root = tree.root().to_repr();
+    let forged = Disclosure {
+        pixels: vec![0; 4],
+        path: MerklePath {
+            index: 3,
+            siblings: tree.path(6).siblings[1..].to_vec(),
+        },
+    };
+    assert_eq!(
+        forged.path.root(pixels_digest(&forged.pixels)),
+        Some(tree.root())
+    );
+    assert!(!forged.verify_row(&root, WIDTH, HEIGHT));
+}
@@ -0,0 +1,56 @@
+use core::downsample::{Downsampler, Rounding};
+
+// one row of 2 X 2 gray blocks with the given sums (0..=1020)
+fn blocks(sums: &[u32]) -> (Vec<u8>, u32) {
+    let width = sums.len() * 2;
+    let mut buf = vec![0; width * 2];
+    for (i, &sum) in sums.iter().enumerate() {
+        for (k, index) in [2 * i, 2 * i + 1, width + 2 * i, width + 2 * i + 1]
+            .into_iter()
+            .enumerate()
+        {
+            buf[index] = sum.saturating_sub(255 * k as u32).min(255) as u8;
+        }
+    }
+    (buf, width as u32)
+}
+
+fn averages(rounding: Rounding, sums: &[u32]) -> Vec<u8> {
+    let (buf, width) = blocks(sums);
+    let reduced = Downsampler::new(2)
+        .with_rounding(rounding)
+        .downsample(&buf, 1, width, 2);
+    let offset = rounding.offset(4);
+    for (i, &sum) in sums.iter().enumerate() {
+        assert_eq!(
+            sum + offset,
+            4 * reduced.pixels[i] as u32 + reduced.remainders[i]
+        );
+    }
+    reduced.pixels
+}
+
+#[test]
+fn rounding_modes_differ_only_where_expected() {
+    let sums = [0, 1, 2, 3, 5, 6, 10, 14, 1018, 1020];
+    assert_eq!(
+        averages(Rounding::Floor, &sums),
+        vec![0, 0, 0, 0, 1, 1, 2, 3, 254, 255]
+    );
+    assert_eq!(
+        averages(Rounding::HalfUp, &sums),
+        vec![0, 0, 1, 1, 1, 2, 3, 4, 255, 255]
+    );
+    assert_eq!(
+        averages(Rounding::HalfEven, &sums),
+        vec![0, 0, 0, 1, 1, 2, 2, 4, 254, 255]
+    );
+}
+
+#[test]
+fn upscale_keeps_the_size_and_repeats_the_last_block() {
+    let downsampler = Downsampler::new(2);
+    let buf = [0, 4, 8, 100, 100, 100, 8, 100, 9];
+    let mosaic = downsampler.mosaic(&buf, 1, 3, 3);
+    assert_eq!(mosaic, vec![51; 9]);
+}
@@ -0,0 +1,55 @@
+// Deploys the generated verifier in revm, no network needed.
+// Compiling the contract requires `solc` in PATH: cargo test -p core --features evm
+#![cfg(feature = "evm")]
+
+use core::{
+    downsample::Downsampler,
+    load_kzg_srs,
+    evm::{
+        compile_solidity, create_img_proof_evm, encode_calldata, evm_verify, img_calldata,
+        img_solidity_verifier,
+    },
+};
+use halo2_proofs::{
+    halo2curves::bn256::Bn256,
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::commitment::ParamsKZG,
+    },
+};
+use image::EncodableLayout;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+
+fn gradient() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn evm_verifier_accepts_thumbnail_and_rejects_tampered_one() {
+    // test-only SRS, real deployments load a ceremony
+    let mut srs = Vec::new();
+    ParamsKZG::<Bn256>::new(10).write(&mut srs).unwrap();
+    assert!(load_kzg_srs(&mut &srs[..]).unwrap());
+
+    let origin = gradient();
+    let thumbnail = Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .to_rgba_image();
+    let (s_width, s_height) = (thumbnail.width(), thumbnail.height());
+
+    let (proof, instances) = create_img_proof_evm(origin, WIDTH, HEIGHT);
+    let deployment_code = compile_solidity(&img_solidity_verifier(s_width, s_height));
+
+    let calldata = img_calldata(&proof, thumbnail.as_bytes().to_vec(), s_width, s_height);
+    assert_eq!(calldata, encode_calldata(&instances, &proof));
+    assert!(evm_verify(deployment_code.clone(), calldata).is_ok());
+
+    let mut tampered = thumbnail.as_bytes().to_vec();
+    tampered[0] ^= 1;
+    let calldata = img_calldata(&proof, tampered, s_width, s_height);
+    assert!(evm_verify(deployment_code, calldata).is_err());
+}
@@ -0,0 +1,38 @@
+use core::{
+    downsample::Downsampler,
+    hashed::{create_img_proof_hashed, verify_img_hashed},
+};
+use image::EncodableLayout;
+
+const WIDTH: u32 = 9;
+const HEIGHT: u32 = 6;
+
+#[test]
+fn hashed_proof_verifies_with_the_original_shape() {
+    let origin: Vec<u8> = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect();
+    let thumbnail = Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .to_rgba_image()
+        .as_bytes()
+        .to_vec();
+    let proof = create_img_proof_hashed(origin, WIDTH, HEIGHT);
+
+    assert!(verify_img_hashed(
+        proof.clone(),
+        thumbnail.clone(),
+        WIDTH,
+        HEIGHT
+    ));
+    // same thumbnail size, but another circuit shape
+    assert!(!verify_img_hashed(
+        proof.clone(),
+        thumbnail.clone(),
+        WIDTH - 1,
+        HEIGHT
+    ));
+    let mut tampered = thumbnail;
+    tampered[0] ^= 1;
+    assert!(!verify_img_hashed(proof, tampered, WIDTH, HEIGHT));
+}
@@ -0,0 +1,75 @@
+// Keys written once and read back: all tests share the 8 X 6 shape.
+use core::{
+    backend::Backend,
+    create_img_proof_with_pk,
+    downsample::Downsampler,
+    keys::{read_img_proving_key, read_img_verifying_key, write_img_keys},
+    verify_img_with_vk,
+};
+use image::EncodableLayout;
+use std::path::PathBuf;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+
+fn keys_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("img-keys-{}-{}", name, std::process::id()))
+}
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 7) as u8, (i * 3) as u8, (200 - i) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn written_keys_prove_and_verify() {
+    let dir = keys_dir("ipa");
+    write_img_keys(Backend::Ipa, WIDTH, HEIGHT, &dir).unwrap();
+
+    let pk = read_img_proving_key(Backend::Ipa, WIDTH, HEIGHT, &dir).unwrap();
+    let proof = create_img_proof_with_pk(&pk, origin(), WIDTH, HEIGHT);
+
+    let vk = read_img_verifying_key(Backend::Ipa, WIDTH, HEIGHT, &dir).unwrap();
+    let thumbnail = Downsampler::default()
+        .downsample_rgba(&origin(), WIDTH, HEIGHT)
+        .to_rgba_image()
+        .as_bytes()
+        .to_vec();
+    let (s_width, s_height) = (WIDTH / 2, HEIGHT / 2);
+    assert!(verify_img_with_vk(
+        &vk,
+        proof.clone(),
+        thumbnail.clone(),
+        s_width,
+        s_height
+    ));
+
+    let mut tampered = thumbnail.clone();
+    tampered[0] ^= 1;
+    assert!(!verify_img_with_vk(
+        &vk,
+        proof.clone(),
+        tampered,
+        s_width,
+        s_height
+    ));
+    // a thumbnail of another shape is rejected without panicking
+    assert!(!verify_img_with_vk(
+        &vk,
+        proof,
+        thumbnail,
+        s_width + 1,
+        s_height
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keys_of_another_shape_are_an_error() {
+    let dir = keys_dir("shape");
+    assert!(core::set_shape(WIDTH, HEIGHT));
+    assert!(write_img_keys(Backend::Ipa, WIDTH + 2, HEIGHT, &dir).is_err());
+    assert!(read_img_verifying_key(Backend::Ipa, WIDTH, HEIGHT, &keys_dir("missing")).is_err());
+}
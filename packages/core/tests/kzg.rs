@@ -0,0 +1,61 @@
+use core::{
+    backend::Backend, create_img_proof_with, load_kzg_srs, load_kzg_srs_file, verify_img_with,
+};
+use halo2_proofs::{
+    halo2curves::bn256::Bn256,
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::commitment::ParamsKZG,
+    },
+};
+use image::EncodableLayout;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+
+// test-only SRS: the toxic waste is sampled here, real deployments load a ceremony
+fn ceremony(k: u32) -> Vec<u8> {
+    let mut srs = Vec::new();
+    ParamsKZG::<Bn256>::new(k).write(&mut srs).unwrap();
+    srs
+}
+
+#[test]
+fn kzg_proof_round_trips_with_a_loaded_srs() {
+    let srs = ceremony(11);
+    let path = std::env::temp_dir().join(format!("kzg-srs-{}.bin", std::process::id()));
+    std::fs::write(&path, &srs).unwrap();
+    assert!(load_kzg_srs_file(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    // the same ceremony loads again, another one does not replace it
+    assert!(load_kzg_srs(&mut &srs[..]).unwrap());
+    assert!(!load_kzg_srs(&mut &ceremony(11)[..]).unwrap());
+    assert!(load_kzg_srs(&mut &srs[..10]).is_err());
+
+    let origin: Vec<u8> = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect();
+    let thumbnail = core::downsample::Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .to_rgba_image();
+    let (s_width, s_height) = thumbnail.dimensions();
+    let proof = create_img_proof_with(Backend::Kzg, origin, WIDTH, HEIGHT);
+
+    let thumbnail = thumbnail.as_bytes().to_vec();
+    assert!(verify_img_with(
+        Backend::Kzg,
+        proof.clone(),
+        thumbnail.clone(),
+        s_width,
+        s_height
+    ));
+    let mut tampered = thumbnail;
+    tampered[0] ^= 1;
+    assert!(!verify_img_with(
+        Backend::Kzg,
+        proof,
+        tampered,
+        s_width,
+        s_height
+    ));
+}
@@ -0,0 +1,77 @@
+// Mosaic circuits of one 6 X 4 RGB image with 2 X 2 blocks for the whole process.
+use core::{
+    channels::{channel_public_input, ChannelLayout, NormalizedImage},
+    mosaic::{mosaic, mosaic_circuit, set_mosaic_shape, MosaicCircuit},
+};
+use halo2_proofs::{dev::MockProver, halo2curves::pasta::Fp};
+
+const WIDTH: u32 = 6;
+const HEIGHT: u32 = 4;
+const BLOCK: u32 = 2;
+const K: u32 = 10;
+
+fn image() -> NormalizedImage {
+    let pixels = (0..WIDTH * HEIGHT * 3)
+        .map(|i| (i * 37 % 256) as u8)
+        .collect();
+    NormalizedImage {
+        layout: ChannelLayout::Rgb,
+        width: WIDTH,
+        height: HEIGHT,
+        pixels,
+    }
+}
+
+#[test]
+fn mosaic_circuit_is_satisfied() {
+    let (circuit, published): (MosaicCircuit<Fp, 3>, _) = mosaic_circuit(&image(), BLOCK);
+    assert_eq!(published.pixels, mosaic(&image(), BLOCK).pixels);
+    let prover = MockProver::run(K, &circuit, channel_public_input(&published)).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_mosaic_is_rejected() {
+    let (circuit, published): (MosaicCircuit<Fp, 3>, _) = mosaic_circuit(&image(), BLOCK);
+    let mut tampered = published.clone();
+    tampered.pixels[0] ^= 1;
+    let prover = MockProver::run(K, &circuit, channel_public_input(&tampered)).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn remainder_of_block_size_is_rejected() {
+    // one less with a remainder of block^2 more still satisfies the gate, the
+    // remainder lookup rejects it
+    let (mut circuit, published): (MosaicCircuit<Fp, 3>, _) = mosaic_circuit(&image(), BLOCK);
+    let blocks_x = (WIDTH / BLOCK) as usize;
+    // the first block and channel with a positive average
+    let index = (0..circuit.average.len())
+        .find(|&i| {
+            let (block, c) = (i / 3, i % 3);
+            let (x, y) = (block % blocks_x, block / blocks_x);
+            let pixel = (y * BLOCK as usize) * WIDTH as usize + x * BLOCK as usize;
+            published.pixels[pixel * 3 + c] > 0
+        })
+        .unwrap();
+    circuit.average[index] = circuit.average[index].map(|v| v - 1);
+    circuit.remainder[index] = circuit.remainder[index].map(|r| r + BLOCK * BLOCK);
+
+    let (block, c) = (index / 3, index % 3);
+    let (x, y) = (block % blocks_x, block / blocks_x);
+    let mut tampered = published.clone();
+    for dy in 0..BLOCK as usize {
+        for dx in 0..BLOCK as usize {
+            let pixel = (y * BLOCK as usize + dy) * WIDTH as usize + x * BLOCK as usize + dx;
+            tampered.pixels[pixel * 3 + c] -= 1;
+        }
+    }
+    let prover = MockProver::run(K, &circuit, channel_public_input(&tampered)).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_block_size_is_rejected() {
+    assert!(set_mosaic_shape(WIDTH, HEIGHT, BLOCK));
+    assert!(!set_mosaic_shape(WIDTH, HEIGHT, BLOCK + 1));
+}
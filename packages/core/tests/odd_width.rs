@@ -0,0 +1,54 @@
+// 5 X 3 originals for the whole process: the trailing column and row are not reduced.
+use core::{
+    disclosure::{committed_circuit, row_tree},
+    downsample::Downsampler,
+    img_circuit, img_public_input,
+};
+use halo2_proofs::{dev::MockProver, halo2curves::pasta::Fp};
+
+const WIDTH: u32 = 5;
+const HEIGHT: u32 = 3;
+// the row digests of the committed circuit need more rows
+const K: u32 = 11;
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 7) as u8, (i * 11) as u8, (200 - i) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn odd_width_is_satisfied() {
+    let (circuit, reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    assert_eq!(reduced.dimensions(), (2, 1));
+    assert_eq!(
+        reduced.as_raw(),
+        &Downsampler::default()
+            .downsample_rgba(&origin(), WIDTH, HEIGHT)
+            .pixels
+    );
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_reduction_of_odd_width_is_rejected() {
+    let (circuit, mut reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    reduced.get_pixel_mut(1, 0)[0] ^= 1;
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn committed_rows_include_the_trailing_column() {
+    let (circuit, public_input) = committed_circuit(origin(), WIDTH, HEIGHT);
+    let prover = MockProver::run(K, &circuit, public_input.clone()).unwrap();
+    prover.assert_satisfied();
+
+    let mut other = origin();
+    other[4 * (WIDTH as usize - 1)] ^= 1;
+    let mut public_input = public_input;
+    *public_input.last_mut().unwrap() = vec![row_tree(&other, WIDTH, HEIGHT).root()];
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
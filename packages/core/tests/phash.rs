@@ -0,0 +1,110 @@
+// aHash circuits of one 8 X 8 original for the whole process.
+use core::{
+    digest::pixels_digest,
+    phash::{
+        gray_grid, hamming_distance, hash_circuit, hash_public_input, perceptual_hash,
+        set_hash_shape, PerceptualHash,
+    },
+};
+use halo2_proofs::{circuit::Value, dev::MockProver};
+
+const SIDE: u32 = 8;
+// the digest of the original needs more rows
+const K: u32 = 13;
+
+// opaque gray image, one value per pixel
+fn gray(width: u32, height: u32, value: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let v = value(x, y);
+            [v, v, v, 255]
+        })
+        .collect()
+}
+
+#[test]
+fn average_hash_marks_cells_above_the_mean() {
+    // black left half, white right half, 2 X 2 blocks
+    let buf = gray(16, 16, |x, _| if x < 8 { 0 } else { 255 });
+    let hash = perceptual_hash(PerceptualHash::Average, &buf, 16, 16);
+    assert_eq!(hash, 0x0f0f_0f0f_0f0f_0f0f);
+}
+
+#[test]
+fn difference_hash_marks_brighter_right_neighbours() {
+    let buf = gray(9, 8, |x, _| (x * 20) as u8);
+    assert_eq!(
+        perceptual_hash(PerceptualHash::Difference, &buf, 9, 8),
+        u64::MAX
+    );
+    let flipped = gray(9, 8, |x, _| ((8 - x) * 20) as u8);
+    assert_eq!(
+        perceptual_hash(PerceptualHash::Difference, &flipped, 9, 8),
+        0
+    );
+    assert_eq!(hamming_distance(u64::MAX, 0), 64);
+}
+
+#[test]
+fn small_images_have_no_block() {
+    assert_eq!(PerceptualHash::Average.block(7, 100), 0);
+    assert_eq!(PerceptualHash::Difference.block(27, 16), 2);
+}
+
+// gray x * 30 + y * 2 at (x, y), one pixel per cell
+fn origin() -> Vec<u8> {
+    gray(SIDE, SIDE, |x, y| (x * 30 + y * 2) as u8)
+}
+
+// aHash of a gray grid, as `perceptual_hash`
+fn average_hash(grays: &[u8]) -> u64 {
+    let total = grays.iter().map(|&g| g as u64).sum::<u64>();
+    grays
+        .iter()
+        .fold(0, |hash, &g| (hash << 1) | (64 * g as u64 > total) as u64)
+}
+
+#[test]
+fn hash_circuit_is_satisfied() {
+    let (circuit, hash) = hash_circuit(PerceptualHash::Average, &origin(), SIDE, SIDE).unwrap();
+    assert_eq!(
+        hash,
+        perceptual_hash(PerceptualHash::Average, &origin(), SIDE, SIDE)
+    );
+    let instance = hash_public_input(hash, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_hash_is_rejected() {
+    let (circuit, hash) = hash_circuit(PerceptualHash::Average, &origin(), SIDE, SIDE).unwrap();
+    let instance = hash_public_input(hash ^ 1, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn remainder_of_divisor_is_rejected() {
+    // one less with a remainder of 3 more still satisfies the gate, the
+    // remainder lookup rejects it
+    let (mut circuit, _) = hash_circuit(PerceptualHash::Average, &origin(), SIDE, SIDE).unwrap();
+    let mut grays = gray_grid(PerceptualHash::Average, &origin(), SIDE, SIDE)
+        .into_iter()
+        .map(|(gray, _)| gray)
+        .collect::<Vec<_>>();
+    assert_eq!(grays[1], 30);
+    grays[1] -= 1;
+    circuit.grays[1] = Value::known(grays[1]);
+    circuit.remainders[1] = circuit.remainders[1].map(|r| r + 3);
+    let instance = hash_public_input(average_hash(&grays), pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_hash_is_rejected() {
+    assert!(set_hash_shape(PerceptualHash::Average, SIDE, SIDE));
+    assert!(!set_hash_shape(PerceptualHash::Difference, SIDE, SIDE));
+}
@@ -0,0 +1,77 @@
+// Quantization circuits of one 4 X 2 RGBA original to 4 bits for the whole process.
+use core::{
+    channels::{ChannelLayout, NormalizedImage},
+    digest::pixels_digest,
+    quantize::{quantize, quantize_circuit, quantize_public_input, set_quantize_shape},
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+const BITS: u32 = 4;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+fn image() -> NormalizedImage {
+    let pixels = (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i * 37 % 256) as u8)
+        .collect();
+    NormalizedImage {
+        layout: ChannelLayout::Rgba,
+        width: WIDTH,
+        height: HEIGHT,
+        pixels,
+    }
+}
+
+#[test]
+fn quantize_circuit_is_satisfied() {
+    let (circuit, quantized) = quantize_circuit(&image(), BITS);
+    assert_eq!(quantized.pixels, quantize(&image(), BITS).pixels);
+    let public_input = quantize_public_input(&quantized, BITS, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_level_is_rejected() {
+    let (circuit, quantized) = quantize_circuit(&image(), BITS);
+    let mut tampered = quantized.clone();
+    tampered.pixels[0] ^= 1 << (8 - BITS);
+    let public_input = quantize_public_input(&tampered, BITS, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_original_is_rejected() {
+    // the levels are those of the original, the digest is of another image
+    let (circuit, quantized) = quantize_circuit(&image(), BITS);
+    let mut other = image().pixels;
+    other[0] ^= 1;
+    let public_input = quantize_public_input(&quantized, BITS, pixels_digest(&other));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn dropped_bits_of_a_whole_level_are_rejected() {
+    // one level less with 2^(8 - bits) more dropped still satisfies the gate,
+    // the remainder lookup rejects it
+    let (mut circuit, quantized) = quantize_circuit(&image(), BITS);
+    let index = quantized.pixels.iter().position(|&v| v > 0).unwrap();
+    let mut tampered = quantized.clone();
+    tampered.pixels[index] -= 1 << (8 - BITS);
+    circuit.quotient[index] = circuit.quotient[index].map(|q| q - 1);
+    circuit.level[index] = circuit.level[index].map(|l| l - 1);
+    circuit.dropped[index] = circuit.dropped[index].map(|d| d + (1 << (8 - BITS)));
+    let public_input = quantize_public_input(&tampered, BITS, pixels_digest(&image().pixels));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_bit_depth_is_rejected() {
+    assert!(set_quantize_shape(WIDTH, HEIGHT, BITS));
+    assert!(!set_quantize_shape(WIDTH, HEIGHT, BITS + 1));
+}
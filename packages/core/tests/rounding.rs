@@ -0,0 +1,91 @@
+// HalfEven for the whole process, 4 X 2 originals: a HalfUp witness of a tie is rejected.
+use core::{
+    digest::pixels_digest,
+    downsample::{Downsampler, Rounding},
+    img_circuit, img_public_input, set_rounding,
+    similarity::{set_similarity_shape, ReferenceWitness, SimilarityCircuit, Transform},
+};
+use halo2_proofs::{circuit::Value, dev::MockProver, halo2curves::pasta::Fp};
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+const K: u32 = 10;
+// the digest of the original needs more rows
+const SIMILARITY_K: u32 = 12;
+
+// red of the first block is 0, 0, 1, 1: (2 + 2) / 4 is a tie between 0 and 1
+fn origin() -> Vec<u8> {
+    let mut buf = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [0, (i * 5) as u8, (i * 9) as u8, 255])
+        .collect::<Vec<u8>>();
+    buf[4 * WIDTH as usize] = 1;
+    buf[4 * (WIDTH as usize + 1)] = 1;
+    buf
+}
+
+#[test]
+fn half_even_witness_is_satisfied() {
+    set_rounding(Rounding::HalfEven);
+    let (circuit, reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    assert_eq!(reduced.get_pixel(0, 0)[0], 0);
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn half_up_witness_is_rejected() {
+    set_rounding(Rounding::HalfEven);
+    let (mut circuit, mut reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    // what HalfUp rounds the tie to: 2 + 2 = 4 * 1 + 0 satisfies the gate,
+    // but 1 is odd and a remainder of 0 is a tie
+    assert_eq!(
+        Downsampler::new(2)
+            .with_rounding(Rounding::HalfUp)
+            .downsample_rgba(&origin(), WIDTH, HEIGHT)
+            .pixels[0],
+        1
+    );
+    circuit.reduced_red[0] = Value::known(1);
+    circuit.r_red[0] = Value::known(0);
+    reduced.get_pixel_mut(0, 0)[0] = 1;
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+fn similarity_circuit(average: Option<(u8, u32)>) -> (SimilarityCircuit, Vec<Vec<Fp>>) {
+    set_rounding(Rounding::HalfEven);
+    assert!(set_similarity_shape(Transform::Downsample, WIDTH, HEIGHT));
+    let origin = origin();
+    let mut reference = ReferenceWitness::new(Transform::Downsample, &origin, WIDTH, HEIGHT);
+    let mut published = Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .pixels;
+    if let Some((average, remainder)) = average {
+        reference.average[0] = Value::known(average);
+        reference.remainder[0] = Value::known(remainder);
+        published[0] = average;
+    }
+    let instance = vec![
+        published.iter().map(|&v| Fp::from(v as u64)).collect(),
+        vec![Fp::zero(), pixels_digest(&origin)],
+    ];
+    let circuit = SimilarityCircuit {
+        reference,
+        published: published.iter().map(|&v| Value::known(v)).collect(),
+    };
+    (circuit, instance)
+}
+
+#[test]
+fn half_even_reference_is_satisfied() {
+    let (circuit, instance) = similarity_circuit(None);
+    let prover = MockProver::run(SIMILARITY_K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn half_up_reference_is_rejected() {
+    let (circuit, instance) = similarity_circuit(Some((1, 0)));
+    let prover = MockProver::run(SIMILARITY_K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
@@ -0,0 +1,38 @@
+// HalfUp for the whole process, 4 X 2 originals: a HalfEven witness of a tie is rejected.
+use core::{downsample::Rounding, img_circuit, img_public_input, set_rounding};
+use halo2_proofs::{circuit::Value, dev::MockProver, halo2curves::pasta::Fp};
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+const K: u32 = 10;
+
+// red of the first block is 0, 0, 1, 1: (2 + 2) / 4 is a tie between 0 and 1
+fn origin() -> Vec<u8> {
+    let mut buf = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [0, (i * 5) as u8, (i * 9) as u8, 255])
+        .collect::<Vec<u8>>();
+    buf[4 * WIDTH as usize] = 1;
+    buf[4 * (WIDTH as usize + 1)] = 1;
+    buf
+}
+
+#[test]
+fn half_up_witness_is_satisfied() {
+    set_rounding(Rounding::HalfUp);
+    let (circuit, reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    assert_eq!(reduced.get_pixel(0, 0)[0], 1);
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn half_even_witness_is_rejected() {
+    set_rounding(Rounding::HalfUp);
+    let (mut circuit, mut reduced) = img_circuit::<Fp>(origin(), WIDTH, HEIGHT);
+    // what HalfEven rounds the tie to: 2 + 2 = 4 * 0 + 4
+    circuit.reduced_red[0] = Value::known(0);
+    circuit.r_red[0] = Value::known(4);
+    reduced.get_pixel_mut(0, 0)[0] = 0;
+    let prover = MockProver::run(K, &circuit, img_public_input(&reduced)).unwrap();
+    assert!(prover.verify().is_err());
+}
@@ -0,0 +1,118 @@
+// Similarity of one 4 X 2 RGBA original and its downsampled thumbnail for the whole process.
+use core::{
+    digest::pixels_digest,
+    downsample::Downsampler,
+    similarity::{
+        max_mse_for_psnr, set_similarity_shape, similarity_circuit, similarity_public_input,
+        sum_squared_error, Transform,
+    },
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+// the digest of the original needs more rows
+const K: u32 = 12;
+const MAX_MSE: u32 = 2;
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 9) as u8, 255])
+        .collect()
+}
+
+// four of the eight samples are off by 2: the squared error is exactly MAX_MSE * 8
+fn published() -> Vec<u8> {
+    let mut published = Downsampler::default()
+        .downsample_rgba(&origin(), WIDTH, HEIGHT)
+        .pixels;
+    for v in published.iter_mut().take(4) {
+        *v = if *v > 253 { *v - 2 } else { *v + 2 };
+    }
+    published
+}
+
+#[test]
+fn psnr_bounds_round_down() {
+    // 255^2 / 10^3 = 65.025, 255^2 / 10^4 = 6.5025
+    assert_eq!(max_mse_for_psnr(30.0), 65);
+    assert_eq!(max_mse_for_psnr(40.0), 6);
+    assert_eq!(max_mse_for_psnr(0.0), 255 * 255);
+}
+
+#[test]
+fn squared_errors_are_summed() {
+    assert_eq!(sum_squared_error(&[0, 10, 255], &[3, 6, 255]), 9 + 16);
+    assert_eq!(sum_squared_error(&[255], &[0]), 255 * 255);
+}
+
+#[test]
+fn error_at_the_threshold_is_satisfied() {
+    let reference = Transform::Downsample.apply(&origin(), WIDTH, HEIGHT).0;
+    assert_eq!(
+        sum_squared_error(&reference, &published()),
+        MAX_MSE as u64 * reference.len() as u64
+    );
+    let circuit = similarity_circuit(
+        Transform::Downsample,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+        &published(),
+        MAX_MSE,
+    )
+    .unwrap();
+    let instance = similarity_public_input(&published(), MAX_MSE, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn error_above_the_threshold_is_rejected() {
+    assert!(similarity_circuit(
+        Transform::Downsample,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+        &published(),
+        MAX_MSE - 1,
+    )
+    .is_none());
+    // the witness of MAX_MSE against a statement of MAX_MSE - 1: the slack is negative
+    let circuit = similarity_circuit(
+        Transform::Downsample,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+        &published(),
+        MAX_MSE,
+    )
+    .unwrap();
+    let instance = similarity_public_input(&published(), MAX_MSE - 1, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_digest_is_rejected() {
+    let circuit = similarity_circuit(
+        Transform::Downsample,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+        &published(),
+        MAX_MSE,
+    )
+    .unwrap();
+    let mut other = origin();
+    other[0] ^= 1;
+    let instance = similarity_public_input(&published(), MAX_MSE, pixels_digest(&other));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_transform_is_rejected() {
+    assert!(set_similarity_shape(Transform::Downsample, WIDTH, HEIGHT));
+    assert!(!set_similarity_shape(Transform::Identity, WIDTH, HEIGHT));
+}
@@ -0,0 +1,128 @@
+// Means circuits of one 4 X 2 RGBA original (Transform::Identity) for the whole process.
+use core::{
+    digest::pixels_digest,
+    similarity::Transform,
+    statistics::{
+        channel_means, histogram, set_statistics_shape, statistics_circuit,
+        statistics_public_input, Statistic,
+    },
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+// 3 pixels: black, gray and white, opaque
+fn pixels() -> Vec<u8> {
+    [[0, 0, 0, 255], [128, 64, 200, 255], [255, 255, 255, 255]].concat()
+}
+
+#[test]
+fn channel_means_use_floor_division() {
+    // (0 + 128 + 255) / 3 = 127 rem 2, (0 + 64 + 255) / 3 = 106 rem 1, 455 / 3 = 151 rem 2
+    assert_eq!(
+        channel_means(&pixels()),
+        [(127, 2), (106, 1), (151, 2), (255, 0)]
+    );
+    assert_eq!(Statistic::Means.compute(&pixels()), [127, 106, 151, 255]);
+}
+
+#[test]
+fn histogram_counts_equal_bins() {
+    assert_eq!(
+        histogram(&pixels(), 4),
+        [[1, 0, 1, 1], [1, 1, 0, 1], [1, 0, 0, 2], [0, 0, 0, 3]].concat()
+    );
+    assert!(Statistic::Histogram { bins: 16 }.is_valid());
+    assert!(!Statistic::Histogram { bins: 12 }.is_valid());
+    assert!(!Statistic::Histogram { bins: 64 }.is_valid());
+}
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 29 + 3) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn means_circuit_is_satisfied() {
+    let (circuit, statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Means,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    assert_eq!(statistics.values, Statistic::Means.compute(&origin()));
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_mean_is_rejected() {
+    let (circuit, mut statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Means,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    statistics.values[0] ^= 1;
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn remainder_of_sample_count_is_rejected() {
+    // one less with a remainder of 8 more still satisfies the sum
+    let (mut circuit, mut statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Means,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    assert!(statistics.values[0] > 0);
+    circuit.means[0] = circuit.means[0].map(|v| v - 1);
+    circuit.remainders[0] = circuit.remainders[0].map(|r| r + WIDTH * HEIGHT);
+    statistics.values[0] -= 1;
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_original_is_rejected() {
+    let (mut circuit, statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Means,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    // the red samples of the first two pixels swapped: the same sums, another digest
+    circuit.reference.original.swap(0, 4);
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_statistic_is_rejected() {
+    assert!(set_statistics_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        Statistic::Means
+    ));
+    assert!(!set_statistics_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        Statistic::Histogram { bins: 4 }
+    ));
+}
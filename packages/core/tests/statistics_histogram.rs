@@ -0,0 +1,69 @@
+// Histogram circuits of one 4 X 2 RGBA original (Transform::Identity) for the whole process.
+use core::{
+    digest::pixels_digest,
+    similarity::Transform,
+    statistics::{histogram, statistics_circuit, statistics_public_input, Statistic},
+};
+use halo2_proofs::{circuit::Value, dev::MockProver};
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+const BINS: u32 = 4;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 29 + 3) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn histogram_circuit_is_satisfied() {
+    let (circuit, statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Histogram { bins: BINS },
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    assert_eq!(statistics.values, histogram(&origin(), BINS));
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn moved_count_is_rejected() {
+    let (circuit, mut statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Histogram { bins: BINS },
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    // the red sample 0 of the first pixel counted in the last bin
+    statistics.values[0] -= 1;
+    statistics.values[BINS as usize - 1] += 1;
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn indicator_of_another_sample_is_rejected() {
+    // indicators of 255 for the red sample 0: the counts match, the bin lookup fails
+    let (mut circuit, mut statistics) = statistics_circuit(
+        Transform::Identity,
+        Statistic::Histogram { bins: BINS },
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    circuit.samples[0] = Value::known(255);
+    statistics.values[0] -= 1;
+    statistics.values[BINS as usize - 1] += 1;
+    let instance = statistics_public_input(&statistics, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
@@ -0,0 +1,142 @@
+// One tile shape for the whole process: 4 X 4 tiles of an 8 X 6 original.
+use core::tile::{create_tiled_proof, tile_circuit, verify_tiled_proof, TileLayout, TiledProof};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 8;
+const HEIGHT: u32 = 6;
+const TILE_SIZE: u32 = 4;
+// the thumbnail
+const S_WIDTH: u32 = WIDTH / 2;
+const S_HEIGHT: u32 = HEIGHT / 2;
+// the commitment of a 4 X 4 tile needs 1137 rows
+const K: u32 = 11;
+
+fn gradient() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 5) as u8, (i * 3) as u8, (255 - i) as u8, 255])
+        .collect()
+}
+
+fn layout() -> TileLayout {
+    TileLayout::new(WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE)
+}
+
+#[test]
+fn every_tile_circuit_is_satisfied() {
+    let origin = gradient();
+    for (column, row) in layout().tiles() {
+        let (circuit, public_input) = tile_circuit(&layout(), &origin, column, row);
+        let prover = MockProver::run(K, &circuit, public_input).unwrap();
+        prover.assert_satisfied();
+    }
+}
+
+#[test]
+fn tampered_tile_is_rejected() {
+    let origin = gradient();
+
+    // an original pixel that is not the committed one
+    let (mut circuit, public_input) = tile_circuit(&layout(), &origin, 0, 0);
+    circuit.0.red[0] = circuit.0.red[0].map(|v| v ^ 1);
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+
+    // the commitment of another tile
+    let (circuit, mut public_input) = tile_circuit(&layout(), &origin, 0, 0);
+    let (_, other) = tile_circuit(&layout(), &origin, 1, 0);
+    *public_input.last_mut().unwrap() = other.last().unwrap().clone();
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+
+    // a reduced pixel off by one
+    let (mut circuit, public_input) = tile_circuit(&layout(), &origin, 1, 1);
+    circuit.0.reduced_green[0] = circuit.0.reduced_green[0].map(|v| v.wrapping_add(1));
+    let prover = MockProver::run(K, &circuit, public_input).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn tiled_proof_verifies_and_rejects_another_thumbnail() {
+    let origin = gradient();
+    let tiled = create_tiled_proof(origin.clone(), WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE);
+    let thumbnail = core::downsample::Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .pixels;
+    assert!(verify_tiled_proof(&tiled, &thumbnail, S_WIDTH, S_HEIGHT));
+
+    let mut tampered = thumbnail.clone();
+    tampered[0] ^= 1;
+    assert!(!verify_tiled_proof(&tiled, &tampered, S_WIDTH, S_HEIGHT));
+
+    // deserialized layouts are untrusted: none of these may panic
+    for layout in [
+        TileLayout {
+            tile_width: 0,
+            ..tiled.layout
+        },
+        TileLayout {
+            tile_height: 3,
+            ..tiled.layout
+        },
+        TileLayout {
+            width: 0,
+            height: 0,
+            ..tiled.layout
+        },
+        // a 6 X 2 thumbnail, as many pixels as the 4 X 3 one
+        TileLayout {
+            width: 12,
+            height: 4,
+            ..tiled.layout
+        },
+    ] {
+        let malformed = TiledProof {
+            layout,
+            ..tiled.clone()
+        };
+        assert!(!verify_tiled_proof(
+            &malformed, &thumbnail, S_WIDTH, S_HEIGHT
+        ));
+    }
+    let empty = TiledProof {
+        tiles: Vec::new(),
+        ..tiled.clone()
+    };
+    assert!(!verify_tiled_proof(&empty, &thumbnail, S_WIDTH, S_HEIGHT));
+    assert!(!verify_tiled_proof(&tiled, &thumbnail, S_HEIGHT, S_WIDTH));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_and_serial_tiled_proofs_agree() {
+    use core::tile::create_tiled_proof_with_threads;
+
+    let origin = gradient();
+    let thumbnail = core::downsample::Downsampler::default()
+        .downsample_rgba(&origin, WIDTH, HEIGHT)
+        .pixels;
+    let serial =
+        create_tiled_proof_with_threads(origin.clone(), WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE, 1);
+    let parallel = create_tiled_proof_with_threads(origin, WIDTH, HEIGHT, TILE_SIZE, TILE_SIZE, 4);
+
+    // proofs are randomized, everything else is deterministic and in raster order
+    assert_eq!(serial.root, parallel.root);
+    for (serial, parallel) in serial.tiles.iter().zip(&parallel.tiles) {
+        assert_eq!(
+            (
+                serial.column,
+                serial.row,
+                serial.commitment,
+                &serial.thumbnail
+            ),
+            (
+                parallel.column,
+                parallel.row,
+                parallel.commitment,
+                &parallel.thumbnail
+            )
+        );
+    }
+    assert!(verify_tiled_proof(&serial, &thumbnail, S_WIDTH, S_HEIGHT));
+    assert!(verify_tiled_proof(&parallel, &thumbnail, S_WIDTH, S_HEIGHT));
+}
@@ -0,0 +1,17 @@
+// The circuit shape is unset until the test sets it: one test for the whole process.
+use core::{backend::Backend, set_shape, verify_imgs, verify_imgs_with};
+
+#[test]
+fn verify_imgs_rejects_an_unset_or_another_shape() {
+    let proofs = [(vec![0u8; 32], vec![0u8; 2 * 2 * 4])];
+    // no shape yet: false instead of a panic in MyCircuit::default
+    assert!(!verify_imgs(&proofs, 2, 2));
+    assert!(!verify_imgs_with(Backend::Kzg, &[], 2, 2));
+
+    assert!(set_shape(4, 4));
+    // thumbnails of another shape
+    assert!(!verify_imgs(&[(vec![0u8; 32], vec![0u8; 3 * 2 * 4])], 3, 2));
+    assert!(!verify_imgs_with(Backend::Kzg, &[], 3, 2));
+    // the right shape but a thumbnail of another length
+    assert!(!verify_imgs(&[(vec![0u8; 32], vec![0u8; 3 * 4])], 2, 2));
+}
@@ -0,0 +1,104 @@
+// Watermark circuits over one 4 X 2 RGBA original (Transform::Identity) for the whole process.
+use core::{
+    digest::pixels_digest,
+    similarity::Transform,
+    watermark::{
+        overlay, set_watermark_shape, watermark_circuit, watermark_public_input, Watermark,
+    },
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+// 2 X 1 watermark at (1, 0) over a 3 X 1 gray RGBA image
+fn blended(alpha: u8) -> Vec<u8> {
+    let base = [100, 100, 100, 255].repeat(3);
+    let watermark = Watermark::new([200, 0, 255, alpha].repeat(2), 2, 1, 1, 0);
+    overlay(&base, 3, &watermark)
+}
+
+#[test]
+fn overlay_blends_covered_pixels() {
+    assert_eq!(blended(0), [100, 100, 100, 255].repeat(3));
+    assert_eq!(
+        blended(255),
+        [[100, 100, 100, 255], [200, 0, 255, 255], [200, 0, 255, 255]].concat()
+    );
+    // (100 * 204 + 200 * 51) / 255 = 120, (100 * 204) / 255 = 80, (100 * 204 + 255 * 51) / 255 = 131
+    assert_eq!(&blended(51)[4..8], [120, 80, 131, 255]);
+}
+
+#[test]
+fn watermark_must_fit() {
+    let watermark = Watermark::new(vec![0; 16], 2, 2, 1, 1);
+    assert!(watermark.fits(3, 3));
+    assert!(!watermark.fits(2, 3));
+}
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 9) as u8, 255])
+        .collect()
+}
+
+// 2 X 1 at (1, 0), half transparent and nearly opaque
+fn logo() -> Watermark {
+    Watermark::new([[200, 0, 255, 51], [10, 250, 90, 200]].concat(), 2, 1, 1, 0)
+}
+
+#[test]
+fn watermark_circuit_is_satisfied() {
+    let (circuit, composited) =
+        watermark_circuit(Transform::Identity, &origin(), WIDTH, HEIGHT, &logo()).unwrap();
+    assert_eq!(composited, overlay(&origin(), WIDTH, &logo()));
+    let instance = watermark_public_input(&composited, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_composite_is_rejected() {
+    let (circuit, mut composited) =
+        watermark_circuit(Transform::Identity, &origin(), WIDTH, HEIGHT, &logo()).unwrap();
+    // red of the first covered pixel
+    composited[4] ^= 1;
+    let instance = watermark_public_input(&composited, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn remainder_of_opaque_is_rejected() {
+    // one less with a remainder of 255 more still satisfies the gate, the
+    // remainder lookup rejects it
+    let (mut circuit, mut composited) =
+        watermark_circuit(Transform::Identity, &origin(), WIDTH, HEIGHT, &logo()).unwrap();
+    assert!(composited[4] > 0);
+    circuit.out[0] = circuit.out[0].map(|v| v - 1);
+    circuit.remainder[0] = circuit.remainder[0].map(|r| r + 255);
+    composited[4] -= 1;
+    let instance = watermark_public_input(&composited, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_watermark_is_rejected() {
+    assert!(set_watermark_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        &logo()
+    ));
+    let mut moved = logo();
+    moved.x = 0;
+    assert!(!set_watermark_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        &moved
+    ));
+}
@@ -0,0 +1,118 @@
+// 4:2:0 circuits of one 4 X 2 RGBA original (Transform::Identity) for the whole process.
+use core::{
+    digest::pixels_digest,
+    similarity::Transform,
+    ycbcr::{
+        convert, rgb_to_ycbcr, set_ycbcr_shape, ycbcr_circuit, ycbcr_public_input, Subsampling,
+        YCbCrImage,
+    },
+};
+use halo2_proofs::dev::MockProver;
+
+const WIDTH: u32 = 4;
+const HEIGHT: u32 = 2;
+// the digest of the original needs more rows
+const K: u32 = 12;
+
+#[test]
+fn converts_like_libjpeg() {
+    assert_eq!(rgb_to_ycbcr(255, 255, 255), (255, 128, 128));
+    assert_eq!(rgb_to_ycbcr(0, 0, 0), (0, 128, 128));
+    assert_eq!(rgb_to_ycbcr(255, 0, 0), (76, 85, 255));
+    assert_eq!(rgb_to_ycbcr(0, 0, 255), (29, 255, 107));
+}
+
+#[test]
+fn subsamples_chroma_420() {
+    // 2 X 2 red and blue columns, floor averages of the chroma blocks
+    let buf = [[255, 0, 0, 255], [0, 0, 255, 255]].concat().repeat(2);
+    let image = convert(&buf, 2, 2, Subsampling::Chroma420);
+    assert_eq!(image.y, [76, 29, 76, 29]);
+    assert_eq!((image.cb, image.cr), (vec![170], vec![181]));
+
+    let planar = convert(&buf, 2, 2, Subsampling::Chroma420).to_planar();
+    assert_eq!(planar.len(), 6);
+    assert_eq!(
+        YCbCrImage::from_planar(&planar, 2, 2, Subsampling::Chroma420),
+        Some(convert(&buf, 2, 2, Subsampling::Chroma420))
+    );
+    assert_eq!(
+        YCbCrImage::from_planar(&planar, 2, 2, Subsampling::Chroma444),
+        None
+    );
+}
+
+fn origin() -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i * 31) as u8, (i * 5 + 7) as u8, (i * 29 + 3) as u8, 255])
+        .collect()
+}
+
+#[test]
+fn ycbcr_circuit_is_satisfied() {
+    let (circuit, image) = ycbcr_circuit(
+        Transform::Identity,
+        Subsampling::Chroma420,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    assert_eq!(
+        image,
+        convert(&origin(), WIDTH, HEIGHT, Subsampling::Chroma420)
+    );
+    let instance = ycbcr_public_input(&image, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    prover.assert_satisfied();
+}
+
+#[test]
+fn tampered_chroma_is_rejected() {
+    let (circuit, mut image) = ycbcr_circuit(
+        Transform::Identity,
+        Subsampling::Chroma420,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    image.cb[0] ^= 1;
+    let instance = ycbcr_public_input(&image, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn chroma_remainder_of_block_size_is_rejected() {
+    // one less with a remainder of 4 more still satisfies the gate, the
+    // remainder lookup rejects it
+    let (mut circuit, mut image) = ycbcr_circuit(
+        Transform::Identity,
+        Subsampling::Chroma420,
+        &origin(),
+        WIDTH,
+        HEIGHT,
+    );
+    assert!(image.cr[0] > 0);
+    circuit.chroma[1][0] = circuit.chroma[1][0].map(|v| v - 1);
+    circuit.chroma_remainder[1][0] = circuit.chroma_remainder[1][0].map(|r| r + 4);
+    image.cr[0] -= 1;
+    let instance = ycbcr_public_input(&image, pixels_digest(&origin()));
+    let prover = MockProver::run(K, &circuit, instance).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn another_subsampling_is_rejected() {
+    assert!(set_ycbcr_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        Subsampling::Chroma420
+    ));
+    assert!(!set_ycbcr_shape(
+        Transform::Identity,
+        WIDTH,
+        HEIGHT,
+        Subsampling::Chroma444
+    ));
+}
@@ -1,11 +1,40 @@
-use core::*;
+use core::{
+    backend::Backend,
+    bundle::ProofBundle,
+    decode::{canonicalize_rgba, decode, decode_original},
+    downsample::Downsampler,
+    progress::{CancelToken, Phase, ProgressSink},
+    *,
+};
+use js_sys::Function;
 use wasm_bindgen::{prelude::wasm_bindgen, Clamped, JsValue};
 
+// the thumbnail create_proof_bundle proves, from the same canonical pixels
+#[wasm_bindgen]
+#[cfg(feature = "console_error_panic_hook")]
+pub fn downsample(buf: Clamped<Vec<u8>>, width: u32, height: u32) -> Vec<u8> {
+    exec_mosaic(buf, SCALE_FACTOR, width, height)
+}
+
+// (width / grain) X (height / grain) RGBA8 of grain X grain block averages,
+// the same as `downsample` for grain 2
 #[wasm_bindgen]
 #[cfg(feature = "console_error_panic_hook")]
 pub fn exec_mosaic(buf: Clamped<Vec<u8>>, grain: u32, width: u32, height: u32) -> Vec<u8> {
-    let result = core::exec_mosaic(buf.0, grain, width, height);
-    result
+    let mut buf = buf.0;
+    canonicalize_rgba(&mut buf);
+    core::exec_mosaic(buf, grain.max(1), width, height)
+}
+
+// width X height RGBA8 with every grain X grain block replaced by its average
+#[wasm_bindgen]
+#[cfg(feature = "console_error_panic_hook")]
+pub fn mosaic(buf: Clamped<Vec<u8>>, grain: u32, width: u32, height: u32) -> Vec<u8> {
+    let mut buf = buf.0;
+    canonicalize_rgba(&mut buf);
+    Downsampler::new(grain.max(1))
+        .with_rounding(rounding())
+        .mosaic(&buf, 4, width, height)
 }
 
 #[wasm_bindgen]
@@ -19,4 +48,116 @@ pub fn create_proof(buf: Clamped<Vec<u8>>, width: u32, height: u32) -> Vec<u8> {
 #[wasm_bindgen]
 pub fn verify_proof(proof: Clamped<Vec<u8>>, small_buf: Clamped<Vec<u8>>, s_width: u32, s_height: u32) -> bool {
     verify_img(proof.0, small_buf.0, s_width, s_height)
-}
\ No newline at end of file
+}
+
+// ProofBundle as a JSON document, binary fields are base64 encoded
+#[wasm_bindgen]
+pub fn create_proof_bundle(buf: Clamped<Vec<u8>>, width: u32, height: u32) -> String {
+    let mut buf = buf.0;
+    canonicalize_rgba(&mut buf);
+    ProofBundle::create(buf, width, height).to_json()
+}
+
+// on_progress(phase, index, total) is called before every phase, returning false aborts the proof
+struct JsProgress<'a> {
+    on_progress: &'a Function,
+    cancel: CancelToken,
+}
+
+impl ProgressSink for JsProgress<'_> {
+    fn phase(&self, phase: Phase) {
+        let (index, total) = match phase {
+            Phase::Tile { index, total } => (index, total),
+            _ => (0, 0),
+        };
+        let keep_going = self.on_progress.call3(
+            &JsValue::NULL,
+            &JsValue::from_str(phase.name()),
+            &JsValue::from(index),
+            &JsValue::from(total),
+        );
+        if keep_going.map_or(false, |value| value == JsValue::FALSE) {
+            self.cancel.cancel();
+        }
+    }
+}
+
+// runs synchronously for seconds to minutes, call it from a Web Worker (project/src/prover.worker.ts)
+#[wasm_bindgen]
+pub fn create_proof_bundle_with_progress(
+    buf: Clamped<Vec<u8>>,
+    width: u32,
+    height: u32,
+    on_progress: &Function,
+) -> Result<String, JsValue> {
+    let cancel = CancelToken::new();
+    let progress = JsProgress {
+        on_progress,
+        cancel: cancel.clone(),
+    };
+    let mut buf = buf.0;
+    canonicalize_rgba(&mut buf);
+    ProofBundle::create_with_progress(Backend::Ipa, buf, width, height, &progress, &cancel)
+        .map(|bundle| bundle.to_json())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn verify_proof_bundle(json: &str) -> bool {
+    match ProofBundle::from_json(json) {
+        Ok(bundle) => bundle.verify(),
+        Err(_) => false,
+    }
+}
+
+// RGBA8 の画像と、canvas を通さずに読んだときのサイズ (EXIF の向きは無視される)
+#[wasm_bindgen]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl DecodedImage {
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> Clamped<Vec<u8>> {
+        Clamped(self.pixels.clone())
+    }
+}
+
+// canvas の getImageData を通さずにファイルのバイト列から元の画像を RGBA8 で読み込む
+#[wasm_bindgen]
+pub fn decode_original_rgba(bytes: &[u8]) -> Result<DecodedImage, JsValue> {
+    let img = decode_original(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(DecodedImage {
+        width: img.width,
+        height: img.height,
+        pixels: img.to_dynamic().to_rgba8().into_raw(),
+    })
+}
+
+// 公開された縮小画像ファイルが bundle の縮小画像と一致し、証明が正しいか
+#[wasm_bindgen]
+pub fn verify_proof_bundle_with_thumbnail(json: &str, thumbnail: &[u8]) -> bool {
+    let (bundle, thumbnail) = match (ProofBundle::from_json(json), decode(thumbnail)) {
+        (Ok(bundle), Ok(thumbnail)) => (bundle, thumbnail),
+        _ => return false,
+    };
+    let thumbnail = thumbnail.to_dynamic().to_rgba8();
+    thumbnail.dimensions() == (bundle.thumbnail.width, bundle.thumbnail.height)
+        && thumbnail.as_raw() == &bundle.thumbnail.pixels
+        && bundle.verify()
+}
+
+#[wasm_bindgen]
+pub fn proof_bundle_to_cbor(json: &str) -> Result<Vec<u8>, JsValue> {
+    let bundle = ProofBundle::from_json(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(bundle.to_cbor())
+}
+
+#[wasm_bindgen]
+pub fn proof_bundle_from_cbor(cbor: &[u8]) -> Result<String, JsValue> {
+    let bundle = ProofBundle::from_cbor(cbor).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(bundle.to_json())
+}
@@ -1,13 +1,33 @@
 import { FormEvent, useCallback, useEffect, useRef, useState } from "react";
-import init, {exec_mosaic, create_proof, verify_proof } from "./pkg/wasm";
+import init, {
+  decode_original_rgba,
+  exec_mosaic,
+  verify_proof_bundle,
+  verify_proof_bundle_with_thumbnail,
+} from "./pkg/wasm";
+import type { ProveMessage, ProveRequest } from "./prover.worker";
+
+type Original = {
+  width: number;
+  height: number;
+  // RGBA8, canonicalised by decode_original_rgba
+  pixels: Uint8ClampedArray;
+};
 
 function App() {
   const [loadWasm, setLoadWasmFlg] = useState(false);
-  const [loadedImage, setImage] = useState<HTMLImageElement | null>(null);
-  const [loadedSmallImage, setLoadedSmallImage] = useState<ArrayBuffer | null>(null);
+  // 元の画像は canvas を通さず wasm でデコードする (色管理や premultiplied alpha で値が変わるため)
+  const [loadedImage, setImage] = useState<Original | null>(null);
   // const [loadedSmnallImage, setSmallImage] = useState<HTMLImageElement | null>(null);
-  const [grain, setGrain] = useState(0);
-  const [proof, setProof] = useState<ArrayBuffer | null>(null);
+  // 証明される縮小画像は grain = 2 (SCALE_FACTOR) のもの
+  const [grain, setGrain] = useState(2);
+  // ProofBundle JSON (proof and thumbnail are base64 encoded by wasm)
+  const [proof, setProof] = useState<string | null>(null);
+  // 証明生成の進捗 (フェーズ名)
+  const [progress, setProgress] = useState<string | null>(null);
+  // 証明生成中の Worker と中断フラグ (SharedArrayBuffer が使えない場合は null)
+  const workerRef = useRef<Worker | null>(null);
+  const cancelRef = useRef<Int32Array | null>(null);
 
   const rawImagecanvasRef = useRef<HTMLCanvasElement>(null);
   const canvasRef = useRef<HTMLCanvasElement>(null);
@@ -30,45 +50,73 @@ function App() {
   const handleGenerateProof = () => {
     const imageBuf = getImageData();
     console.log("loadedImage", loadedImage?.width, loadedImage?.height);
-    if (imageBuf) {
-      const startTime = performance.now();
-      console.log("start");
-      // create_proof 関数に渡す
-      const proof = create_proof(
-        imageBuf,
-        loadedImage?.width || 0, // 幅
-        loadedImage?.height || 0, // 高さ
-      );
-      const endTime = performance.now();
-      console.log(endTime - startTime); 
-      // return <ByteDataDisplay byteData={proof} />;
-      setProof(proof);
-    
-    } else {
+    if (!imageBuf) {
       // エラーハンドリング（getImageData が undefined を返した場合の処理）
       console.error("Image data is undefined");
+      return;
     }
+    if (workerRef.current) {
+      console.error("a proof is already running");
+      return;
+    }
+    const startTime = performance.now();
+    console.log("start");
+    const worker = new Worker(new URL("./prover.worker.ts", import.meta.url), { type: "module" });
+    workerRef.current = worker;
+    cancelRef.current = window.crossOriginIsolated ? new Int32Array(new SharedArrayBuffer(4)) : null;
+
+    const finish = () => {
+      worker.terminate();
+      workerRef.current = null;
+      cancelRef.current = null;
+    };
+    worker.onmessage = (e: MessageEvent<ProveMessage>) => {
+      const message = e.data;
+      switch (message.type) {
+        case "progress":
+          console.log("phase", message.phase, message.index, message.total);
+          setProgress(message.total > 0 ? `${message.phase} ${message.index + 1}/${message.total}` : message.phase);
+          break;
+        case "done":
+          console.log(performance.now() - startTime);
+          setProof(message.bundle);
+          finish();
+          break;
+        case "error":
+          console.error(message.message);
+          setProgress("cancelled");
+          finish();
+          break;
+      }
+    };
+    const request: ProveRequest = {
+      buf: imageBuf,
+      width: loadedImage?.width || 0, // 幅
+      height: loadedImage?.height || 0, // 高さ
+      cancel: cancelRef.current,
+    };
+    worker.postMessage(request, [imageBuf.buffer]);
   }
 
-  // バイト列をBase64エンコードする関数
-  function arrayBufferToBase64(buffer: ArrayBuffer) {
-    const bytes = new Uint8Array(buffer);
-    let binary = '';
-    for (let i = 0; i < bytes.byteLength; i++) {
-      binary += String.fromCharCode(bytes[i]);
+  const handleCancel = () => {
+    const worker = workerRef.current;
+    if (!worker) return;
+    if (cancelRef.current) {
+      // 次のフェーズの前に Worker 側で中断される
+      Atomics.store(cancelRef.current, 0, 1);
+    } else {
+      worker.terminate();
+      workerRef.current = null;
+      setProgress("cancelled");
     }
-    return window.btoa(binary);
   }
 
-  // バイト列を表示するコンポーネント
-  function ByteDataDisplay({ byteData }: { byteData: ArrayBuffer | null }) {
-    if (!byteData) {
-      return null; // byteDataがnullの場合は何も表示しない
+  // ProofBundle を表示するコンポーネント
+  function ProofBundleDisplay({ bundle }: { bundle: string | null }) {
+    if (!bundle) {
+      return null; // bundleがnullの場合は何も表示しない
     }
-    const base64Data = arrayBufferToBase64(byteData);
-    const dataUrl = `data:application/octet-stream;base64,${base64Data}`;
-
-    return <img src={dataUrl} alt="Byte Data" />;
+    return <textarea readOnly value={bundle} rows={8} cols={80} />;
   }
 
 
@@ -77,54 +125,42 @@ function App() {
       console.error("Image not loaded");
       return;
     }
-
-    const canvas = document.createElement("canvas");
-    canvas.width = loadedImage.width;
-    canvas.height = loadedImage.height;
-    const ctx = canvas.getContext("2d");
-
-    if (!ctx) {
-      console.error("Canvas context not available");
-      return;
-    }
-
-    // 画像を Canvas に描画
-    ctx.drawImage(loadedImage, 0, 0);
-
-    // Canvas から ImageData を取得
-    const imageData = ctx.getImageData(0, 0, loadedImage.width, loadedImage.height);
-
-    // ImageData から Uint8ClampedArray を取得
-    const uint8ClampedArray = new Uint8ClampedArray(imageData.data.buffer);
-
-    // uint8ClampedArray を返す
-    return uint8ClampedArray;
+    // Worker に渡すとバッファが移るのでコピーする
+    return new Uint8ClampedArray(loadedImage.pixels);
   };
 
   const handleVerifyProof = () => {
-    const imageBuf = loadedSmallImage;
-
-    if (imageBuf && proof) {
-      // ImageData から Uint8ClampedArray を取得
-      const proofArray = new Uint8ClampedArray(proof);
-      const smallImageArray = new Uint8ClampedArray(imageBuf);
-      console.log(loadedImage?.height ? Math.floor(loadedImage.height / 2) : 0)
-      // create_proof 関数に渡す
-      const result = verify_proof(
-        proofArray,
-        smallImageArray,
-        loadedImage?.width ? Math.floor(loadedImage.width / 2) : 0, // 幅
-        loadedImage?.height ? Math.floor(loadedImage.height / 2) : 0, // 高さ
-      );
+    if (proof) {
+      const result = verify_proof_bundle(proof);
 
       console.log("result", result);
     
     } else {
-      // エラーハンドリング（getImageData が undefined を返した場合の処理）
-      console.error("Image data is undefined");
+      // エラーハンドリング（proof が生成されていない場合の処理）
+      console.error("Proof is undefined");
     }
   }
 
+  // 公開された縮小画像のファイルを wasm でデコードして bundle と照合する
+  const handleVerifyThumbnail = (e: FormEvent<HTMLFormElement>) => {
+    e.preventDefault();
+    if (!proof) {
+      console.error("Proof is undefined");
+      return;
+    }
+    // eslint-disable-next-line @typescript-eslint/ban-ts-comment
+    // @ts-ignore
+    const [file] = e.target["thumbnail"].files as FileList;
+    if (!file) {
+      alert("ファイルが選択されていません。");
+      return;
+    }
+    file.arrayBuffer().then((buf) => {
+      const result = verify_proof_bundle_with_thumbnail(proof, new Uint8Array(buf));
+      console.log("thumbnail result", result);
+    });
+  }
+
 
   const handleSaveImage = () => {
     if (!canvasRef.current) {
@@ -169,7 +205,6 @@ function App() {
   const handleSubmit = useCallback((e: FormEvent<HTMLFormElement>) => {
     e.preventDefault();
     setImage(null);
-    const image = new Image();
     const inputForm = e.target;
     // eslint-disable-next-line @typescript-eslint/ban-ts-comment
     // @ts-ignore
@@ -183,24 +218,24 @@ function App() {
       alert("ファイルが選択されていません。");
       return;
     }
+    if (!loadWasm) {
+      alert("wasm is not loaded yet");
+      return;
+    }
 
-
-    const reader = new FileReader();
-    reader.readAsDataURL(file);
-    reader.addEventListener("load", (event) => {
-      const imageUrl = event.target?.result;
-      // When array buffer comes, you should read image as readAsDataURL not readAsArrayBuffer.
-      if (typeof imageUrl !== "string") {
-        alert("画像の読み込みに失敗しました。");
+    file.arrayBuffer().then((buf) => {
+      let decoded;
+      try {
+        decoded = decode_original_rgba(new Uint8Array(buf));
+      } catch (err) {
+        alert(`画像の読み込みに失敗しました: ${err}`);
         return;
       }
-      image.src = imageUrl;
+      console.log("Image decoded successfully.");
+      setImage({ width: decoded.width, height: decoded.height, pixels: decoded.pixels });
+      decoded.free();
     });
-    image.onload = function () {
-      console.log("Image loaded successfully.");
-      setImage(image);
-    };
-  }, []);
+  }, [loadWasm]);
 
   useEffect(() => {
     if (!loadedImage || !loadWasm) return;
@@ -209,28 +244,21 @@ function App() {
       alert("Not found canvas el");
       return;
     }
-    canvasRenderingContext.drawImage(
-      loadedImage,
-      0,
+    // 表示するだけ、証明には loadedImage.pixels を使う
+    canvasRenderingContext.putImageData(
+      new ImageData(new Uint8ClampedArray(loadedImage.pixels), loadedImage.width, loadedImage.height),
       0,
-      loadedImage.width,
-      loadedImage.height
+      0
     );
-    const imageData = canvasRenderingContext.getImageData(
-      0,
-      0,
-      loadedImage.width,
-      loadedImage.height
-    );
-    rawImagecanvasRef.current?.getContext("2d")?.putImageData(imageData, 0, 0);
     console.log("loadedimage", loadedImage.width, loadedImage.height)
-    const new_width = Math.floor(loadedImage.width / 2);
-    const new_height = Math.floor(loadedImage.height / 2);
-    console.log("loadedimage", Math.floor(loadedImage.width / 2), Math.floor(loadedImage.height / 2))
+    const new_width = Math.floor(loadedImage.width / grain);
+    const new_height = Math.floor(loadedImage.height / grain);
+    console.log("loadedimage", new_width, new_height)
 
 
+    // grain 2: the thumbnail the proof bundle commits to
     const mosaiced = exec_mosaic(
-      imageData.data,
+      loadedImage.pixels,
       grain,
       loadedImage.width,
       loadedImage.height,
@@ -241,7 +269,6 @@ function App() {
       new_width,
       new_height
     );
-    setLoadedSmallImage(iamgedata.data);
     console.log("iamgedata", iamgedata.width, iamgedata.height);
     const canvasRefCurrent = canvasRef.current; // null チェックのために変数に格納
 
@@ -256,7 +283,7 @@ function App() {
     } else {
       alert("canvasRef is null");
     }
-  }, [loadedImage, loadWasm]);
+  }, [loadedImage, loadWasm, grain]);
 
   return (
     <div className="App">
@@ -273,7 +300,7 @@ function App() {
         <input
           name="grain"
           type="number"
-          min="0"
+          min="1"
           id="grain-input"
           defaultValue={2}
           required
@@ -291,17 +318,24 @@ function App() {
       ></canvas>
       <canvas
         ref={canvasRef}
-        width={loadedImage?.width ? Math.floor(loadedImage.width / 2) : undefined} // 幅を半分に設定
-        height={loadedImage?.height ? Math.floor(loadedImage.height / 2) : undefined}
+        width={loadedImage?.width ? Math.floor(loadedImage.width / grain) : undefined} // 幅を 1 / grain に設定
+        height={loadedImage?.height ? Math.floor(loadedImage.height / grain) : undefined}
         style={{ maxWidth: "100%", maxHeight: "400px" }}
       ></canvas>
       <p>
       <button onClick={handleSaveImage}>Save Image</button>
       <button onClick={handleGenerateProof}>Generate Proof</button>
+      <button onClick={handleCancel}>Cancel</button>
+      {progress && <span>{progress}</span>}
       <button onClick={handleVerifyProof}>Verify Proof</button>
       </p>
       {/* <p>JsValueの文字列表現: {proof}</p> */}
-      <p>proof: <ByteDataDisplay byteData={proof} /></p>
+      <p>proof: <ProofBundleDisplay bundle={proof} /></p>
+      <form onSubmit={handleVerifyThumbnail}>
+        <label htmlFor="thumbnail-input">Published thumbnail</label>
+        <input type="file" name="thumbnail" id="thumbnail-input" required></input>
+        <button type="submit">Verify Thumbnail</button>
+      </form>
       {/* <button onClick={handleVerifyProof}>Verify Proof</button> */}
     </div>
   );
@@ -0,0 +1,41 @@
+// 証明生成は数秒から数分かかるので、メインスレッドを止めないよう Worker で実行する
+import init, { create_proof_bundle_with_progress } from "./pkg/wasm";
+
+// main thread -> worker
+export type ProveRequest = {
+  buf: Uint8ClampedArray;
+  width: number;
+  height: number;
+  // Int32Array over a SharedArrayBuffer, [0] != 0 cancels before the next phase
+  // (null when the page is not cross-origin isolated, the main thread then terminates the worker)
+  cancel: Int32Array | null;
+};
+
+// worker -> main thread
+export type ProveMessage =
+  | { type: "progress"; phase: string; index: number; total: number }
+  | { type: "done"; bundle: string }
+  | { type: "error"; message: string };
+
+const ready = init();
+
+onmessage = async (e: MessageEvent<ProveRequest>) => {
+  const { buf, width, height, cancel } = e.data;
+  const post = (message: ProveMessage) => postMessage(message);
+  try {
+    await ready;
+    const bundle = create_proof_bundle_with_progress(
+      buf,
+      width,
+      height,
+      (phase: string, index: number, total: number) => {
+        post({ type: "progress", phase, index, total });
+        // false を返すと中断
+        return cancel === null || Atomics.load(cancel, 0) === 0;
+      },
+    );
+    post({ type: "done", bundle });
+  } catch (err) {
+    post({ type: "error", message: String(err) });
+  }
+};